@@ -2,6 +2,9 @@
 
 #include   "GIC.h"
 #include "PL011.h"
+#include "PL050.h"
+#include "PL111.h"
+#include   "SYS.h"
 #include "SP804.h"
 
 // Include functionality relating to the   kernel.
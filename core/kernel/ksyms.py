@@ -0,0 +1,20 @@
+# Converts the output of `nm -n -C --defined-only` into an assembly file
+# holding the address and name of every function, which the kernel uses
+# to put names to addresses in backtraces.  Each entry is a word address,
+# then the NUL terminated name padded to a word; a zero address ends it.
+
+import sys
+
+print( '.section .ksyms, "a"' )
+
+for line in sys.stdin :
+  fields = line.rstrip( '\n' ).split( ' ', 2 )
+
+  if( len( fields ) == 3 and fields[ 1 ] in 'tTwW' ) :
+    name = fields[ 2 ].replace( '\\', '\\\\' ).replace( '"', '\\"' )
+
+    print( '.word 0x%s' % ( fields[ 0 ] ) )
+    print( '.asciz "%s"' % ( name ) )
+    print( '.balign 4' )
+
+print( '.word 0' )
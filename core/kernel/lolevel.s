@@ -13,11 +13,18 @@
 .global lolevel_handler_rst
 .global lolevel_handler_irq
 .global lolevel_handler_svc
+.global lolevel_handler_und
+.global lolevel_handler_pab
+.global lolevel_handler_dab
 
 lolevel_handler_rst: bl    int_init                @ initialise interrupt vector table
 
                      msr   cpsr, #0xD2             @ enter IRQ mode with IRQ and FIQ interrupts disabled
                      ldr   sp, =tos_int            @ initialise IRQ mode stack pointer
+                     msr   cpsr, #0xD7             @ enter ABT mode with IRQ and FIQ interrupts disabled
+                     ldr   sp, =tos_abt            @ initialise ABT mode stack pointer
+                     msr   cpsr, #0xDB             @ enter UND mode with IRQ and FIQ interrupts disabled
+                     ldr   sp, =tos_und            @ initialise UND mode stack pointer
                      msr   cpsr, #0xD3             @ enter SVC mode with IRQ and FIQ interrupts disabled
                      ldr   sp, =tos_int            @ initialise SVC mode stack pointer
 
@@ -25,27 +32,20 @@ lolevel_handler_rst: bl    int_init                @ initialise interrupt vector
 
                      mov   r0, sp                  @ set    high-level C function arg. = SP
                      bl    hilevel_handler_rst     @ invoke high-level C function
-
-                     ldmia sp!, { r0, lr }         @ load     USR mode PC and CPSR
-                     msr   spsr, r0                @ move     USR mode        CPSR
-                     ldmia sp, { r0-r12, sp, lr }^ @ restore  USR mode registers
-                     add   sp, sp, #60             @ update   SVC mode SP
-                     movs  pc, lr                  @ return from interrupt
+                     b     lolevel_return          @ return to the first process
 
 lolevel_handler_irq: sub   lr, lr, #4              @ correct return address
                      sub   sp, sp, #60             @ update   SVC mode stack
                      stmia sp, { r0-r12, sp, lr }^ @ preserve USR registers
                      mrs   r0, spsr                @ move     USR        CPSR
                      stmdb sp!, { r0, lr }         @ store    USR PC and CPSR
+                     and   r1, r0, #0x1F           @ get      interrupted mode
+                     cmp   r1, #0x10
+                     blne  lolevel_save_svc        @ save the SP and LR of an interrupted kernel thread
 
                      mov   r0, sp                  @ set    high-level C function arg. = SP
                      bl    hilevel_handler_irq     @ invoke high-level C function
-
-                     ldmia sp!, { r0, lr }         @ load     USR mode PC and CPSR
-                     msr   spsr, r0                @ move     USR mode        CPSR
-                     ldmia sp, { r0-r12, sp, lr }^ @ restore  USR mode registers
-                     add   sp, sp, #60             @ update   SVC mode SP
-                     movs  pc, lr                  @ return from interrupt
+                     b     lolevel_return
 
 lolevel_handler_svc: sub   lr, lr, #0              @ correct return address
                      sub   sp, sp, #60             @ update   SVC mode stack
@@ -57,9 +57,99 @@ lolevel_handler_svc: sub   lr, lr, #0              @ correct return address
                      ldr   r1, [ lr, #-4 ]         @ load                     svc instruction
                      bic   r1, r1, #0xFF000000     @ set    high-level C function arg. = svc immediate
                      bl    hilevel_handler_svc     @ invoke high-level C function
+                     b     lolevel_return
+
+/* The fault handlers save the same context as an IRQ, then pass the type
+ * of fault (0 = undefined instruction, 1 = pre-fetch abort, 2 = data 
+ * abort) to the high-level handler.  If the fault was not in USR mode, 
+ * the SP and LR of the faulting mode are saved instead so that the kernel
+ * can be backtraced.
+ */
+
+lolevel_handler_und: sub   lr, lr, #4              @ correct return address
+                     sub   sp, sp, #60             @ update   UND mode stack
+                     stmia sp, { r0-r12, sp, lr }^ @ preserve USR registers
+                     mrs   r0, spsr                @ move     USR        CPSR
+                     stmdb sp!, { r0, lr }         @ store    USR PC and CPSR
+                     mov   r1, #0                  @ set    high-level C function arg. = fault type
+                     b     lolevel_handler_fault
+
+lolevel_handler_pab: sub   lr, lr, #4              @ correct return address
+                     sub   sp, sp, #60             @ update   ABT mode stack
+                     stmia sp, { r0-r12, sp, lr }^ @ preserve USR registers
+                     mrs   r0, spsr                @ move     USR        CPSR
+                     stmdb sp!, { r0, lr }         @ store    USR PC and CPSR
+                     mov   r1, #1                  @ set    high-level C function arg. = fault type
+                     b     lolevel_handler_fault
+
+lolevel_handler_dab: sub   lr, lr, #8              @ correct return address
+                     sub   sp, sp, #60             @ update   ABT mode stack
+                     stmia sp, { r0-r12, sp, lr }^ @ preserve USR registers
+                     mrs   r0, spsr                @ move     USR        CPSR
+                     stmdb sp!, { r0, lr }         @ store    USR PC and CPSR
+                     mov   r1, #2                  @ set    high-level C function arg. = fault type
+                     b     lolevel_handler_fault
+
+lolevel_handler_fault:
+                     and   r2, r0, #0x1F           @ get      faulting mode
+                     cmp   r2, #0x10               
+                     beq   l1                      @ skip if faulting mode == USR mode
+                     mrs   r3, cpsr                @ save     current  mode
+                     orr   r2, r2, #0xC0           
+                     msr   cpsr_c, r2              @ enter    faulting mode with IRQ and FIQ interrupts disabled
+                     mov   r4, sp                  @ get      faulting mode SP
+                     mov   r5, lr                  @ get      faulting mode LR
+                     msr   cpsr_c, r3              @ return to current  mode
+                     str   r4, [ sp, #60 ]         @ replace  saved SP
+                     str   r5, [ sp, #64 ]         @ replace  saved LR
+
+l1:                  mov   r0, sp                  @ set    high-level C function arg. = SP
+                     bl    hilevel_handler_fault   @ invoke high-level C function
+                     b     lolevel_return
+
+/* Kernel threads run in SVC mode, where SP and LR are banked, so an IRQ
+ * saves those rather than the USR mode ones.  The SVC mode SP is then reset,
+ * so that the next service call doesn't run on the kernel thread's stack.
+ * Kernel threads never make service calls themselves.
+ */
+
+lolevel_save_svc:    mrs   r2, cpsr                @ save     current mode
+                     msr   cpsr_c, #0xD3           @ enter    SVC mode with IRQ and FIQ interrupts disabled
+                     mov   r3, sp                  @ get      SVC mode SP
+                     mov   r4, lr                  @ get      SVC mode LR
+                     ldr   sp, =tos_int            @ reset    SVC mode SP
+                     msr   cpsr_c, r2              @ return to current  mode
+                     str   r3, [ sp, #60 ]         @ replace  saved SP
+                     str   r4, [ sp, #64 ]         @ replace  saved LR
+                     bx    lr
+
+/* Every handler returns through here, to the context at SP chosen by the
+ * scheduler.  A kernel thread is returned to in SVC mode by pushing its PC
+ * and CPSR onto its own stack, then using rfe.  Any exclusive monitor left
+ * open by an ldrex in the interrupted thread is cleared, so that a strex in
+ * the next thread can't succeed on it.
+ */
+
+lolevel_return:      clrex                         @ clear    exclusive monitor
+                     ldr   r0, [ sp ]              @ load     CPSR to return with
+                     and   r0, r0, #0x1F           @ get      mode to return to
+                     cmp   r0, #0x10
+                     bne   lolevel_return_svc      @ skip if returning to a kernel thread
 
                      ldmia sp!, { r0, lr }         @ load     USR mode PC and CPSR
                      msr   spsr, r0                @ move     USR mode        CPSR
                      ldmia sp, { r0-r12, sp, lr }^ @ restore  USR mode registers
-                     add   sp, sp, #60             @ update   SVC mode SP
+                     add   sp, sp, #60             @ update   current mode SP
                      movs  pc, lr                  @ return from interrupt
+
+lolevel_return_svc:  mov   r0, sp                  @ keep     context address
+                     add   sp, sp, #68             @ update   current mode SP
+                     msr   cpsr_c, #0xD3           @ enter    SVC mode with IRQ and FIQ interrupts disabled
+                     ldr   sp, [ r0, #60 ]         @ restore  SVC mode SP
+                     ldr   lr, [ r0, #64 ]         @ restore  SVC mode LR
+                     ldr   r1, [ r0, #4 ]          @ load     PC
+                     ldr   r2, [ r0 ]              @ load     CPSR
+                     stmdb sp!, { r1, r2 }         @ push     PC and CPSR
+                     add   r0, r0, #8
+                     ldmia r0, { r0-r12 }          @ restore  registers
+                     rfeia sp!                     @ return, popping PC and CPSR
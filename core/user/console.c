@@ -41,6 +41,10 @@ extern void main_P4();
 extern void main_P5();
 extern void main_pipes();
 extern void main_philosopher();
+extern void main_philosopher_gui();
+extern void main_sync_test();
+extern void main_messages();
+extern void main_sockets();
 
 void* load( char* x ) {
     if (strcmp(x, "P2") == 0) {
@@ -61,6 +65,18 @@ void* load( char* x ) {
     else if(strcmp( x, "philosopher" ) == 0) {
         return &main_philosopher;
     }
+    else if(strcmp( x, "philosopher_gui" ) == 0) {
+        return &main_philosopher_gui;
+    }
+    else if(strcmp( x, "sync_test" ) == 0) {
+        return &main_sync_test;
+    }
+    else if(strcmp( x, "messages" ) == 0) {
+        return &main_messages;
+    }
+    else if(strcmp( x, "sockets" ) == 0) {
+        return &main_sockets;
+    }
     return NULL;
 }
 
@@ -94,13 +110,215 @@ void* load( char* x ) {
  *    terminate 3
  *
  *    would terminate the process whose PID is 3.
+ *
+ * c. continue <process ID>
+ *
+ *    This command uses kill to send a SIG_CONT signal, resuming a
+ *    process that was stopped by typing ^Z.
+ *
+ * d. dmesg
+ *
+ *    Print the kernel log.
+ *
+ * e. loglevel <level>
+ *
+ *    Set which kernel messages are logged, 4 also traces context
+ *    switches.
+ *
+ * f. ps
+ *
+ *    List every process, with its status and resource usage.
+ *
+ * g. top
+ *
+ *    Repeatedly list every process, with the share of the CPU each
+ *    used since the last refresh.
+ *
+ * h. nice <pid> <x>
+ *
+ *    Set the nice value of process pid to x, from -20 to 19, which
+ *    the stride and priority schedulers use.
+ *
+ * i. mlfq [quanta <q0> <q1> ... | boost <ticks|off> | yield <demote|keep>
+ *         | preempt <ticks|off>]
+ *
+ *    Show or change the MLFQ scheduler parameters: the quantum of
+ *    each queue from the top down, how often every process is moved
+ *    to the top queue, whether yielding moves a process down, and
+ *    how many ticks a process woken into a higher queue may wait
+ *    before preempting the running one.
+ *
+ * j. deadlock <report|fail|terminate>
+ *
+ *    Choose what the kernel does about processes deadlocked on pipes,
+ *    such as those of philosopher: only log them, fail a blocked read
+ *    or write, or terminate a process, until the deadlock is broken.
  */
 
+char dmesg[ 8192 ];
+
+#define MAX_PROCS     ( 32 )
+#define TOP_REFRESHES (  8 )
+#define TOP_DELAY     ( 200 )
+
+proc_info_t procs[ MAX_PROCS ], procs_before[ MAX_PROCS ];
+
+// write x right aligned in a column of width n
+void put_int( int x, int n ) {
+  char r[ 12 ]; itoa( r, x );
+  for( int i = strlen( r ); i < n; i++ ) {
+    puts( " ", 1 );
+  }
+  puts( r, strlen( r ) );
+}
+
+char* proc_status( uint32_t x ) {
+  switch( x ) {
+    case PROC_READY     : return " ready   ";
+    case PROC_EXECUTING : return " running ";
+    case PROC_BLOCKED   : return " blocked ";
+    case PROC_STOPPED   : return " stopped ";
+    default             : return " exited  ";
+  }
+}
+
+// before is the list from the last refresh for top, or NULL for ps
+void list_procs( proc_info_t* before, int before_n ) {
+  int n = proclist( procs, MAX_PROCS ), total = 0;
+
+  for( int i = 0; i < n && before != NULL; i++ ) {
+    total += procs[ i ].ticks;
+  }
+  for( int i = 0; i < before_n; i++ ) {
+    total -= before[ i ].ticks;
+  }
+
+  puts( "  PID  PPID  PGID STATUS   QUEUE TICKS THR FIDS STACK", 53 );
+  puts( before != NULL ? "  CPU%\n" : "\n", before != NULL ? 7 : 1 );
+
+  for( int i = 0; i < n; i++ ) {
+    proc_info_t* p = &procs[ i ];
+    put_int( p->pid, 5 ); put_int( p->ppid, 6 ); put_int( p->pgid, 6 );
+    puts( proc_status( p->status ), 9 );
+    put_int( p->queue_level, 5 ); put_int( p->ticks, 6 ); put_int( p->threads, 4 ); put_int( p->fids, 5 ); put_int( p->stack_used, 6 );
+
+    if( before != NULL ) {
+      int used = p->ticks;
+      for( int j = 0; j < before_n; j++ ) {
+        if( before[ j ].pid == p->pid ) {
+          used -= before[ j ].ticks;
+        }
+      }
+      put_int( total > 0 ? ( 100 * used ) / total : 0, 6 );
+    }
+    puts( "\n", 1 );
+  }
+}
+
+void top() {
+  for( int i = 0; i < TOP_REFRESHES; i++ ) {
+    int before_n = proclist( procs_before, MAX_PROCS );
+    for( int j = 0; j < TOP_DELAY; j++ ) {
+      yield();
+    }
+    puts( "\x1B[2J\x1B[H", 7 );  // clear the screen
+    list_procs( procs_before, before_n );
+  }
+}
+
+mlfq_config_t mlfq;
+
+void show_mlfq() {
+  puts( "quanta:", 7 );
+  for( int i = 0; i < mlfq.levels; i++ ) {
+    put_int( mlfq.quantum[ i ], 4 );
+  }
+  puts( "\nboost: ", 8 );
+  if( mlfq.flags & MLFQ_FLAG_BOOST ) {
+    put_int( mlfq.boost_interval, 0 );
+  }
+  else {
+    puts( "off", 3 );
+  }
+  puts( "\nyield: ", 8 );
+  puts( ( mlfq.flags & MLFQ_FLAG_YIELD_DEMOTES ) ? "demote\n" : "keep\n", ( mlfq.flags & MLFQ_FLAG_YIELD_DEMOTES ) ? 7 : 5 );
+  puts( "preempt: ", 9 );
+  if( mlfq.flags & MLFQ_FLAG_WAKEUP_PREEMPT ) {
+    put_int( mlfq.preempt_delay, 0 );
+  }
+  else {
+    puts( "off", 3 );
+  }
+  puts( "\n", 1 );
+}
+
+void tune_mlfq( int argc, char* argv[] ) {
+  if( -1 == sched_ctl( SCHED_GET_MLFQ, &mlfq ) ) {
+    puts( "mlfq: not using the MLFQ scheduler\n", 35 ); return;
+  }
+  if( argc < 2 ) {
+    show_mlfq(); return;
+  }
+
+  if     ( 0 == strcmp( argv[ 0 ], "quanta" ) ) {
+    mlfq.levels = 0;
+    for( int i = 1; i < argc && i <= MLFQ_MAX_LEVELS; i++ ) {
+      mlfq.quantum[ mlfq.levels++ ] = atoi( argv[ i ] );
+    }
+  }
+  else if( 0 == strcmp( argv[ 0 ], "boost"  ) ) {
+    if( 0 == strcmp( argv[ 1 ], "off" ) ) {
+      mlfq.flags &= ~MLFQ_FLAG_BOOST;
+    }
+    else {
+      mlfq.flags |=  MLFQ_FLAG_BOOST; mlfq.boost_interval = atoi( argv[ 1 ] );
+    }
+  }
+  else if( 0 == strcmp( argv[ 0 ], "yield"  ) ) {
+    if( 0 == strcmp( argv[ 1 ], "keep" ) ) {
+      mlfq.flags &= ~MLFQ_FLAG_YIELD_DEMOTES;
+    }
+    else {
+      mlfq.flags |=  MLFQ_FLAG_YIELD_DEMOTES;
+    }
+  }
+  else if( 0 == strcmp( argv[ 0 ], "preempt" ) ) {
+    if( 0 == strcmp( argv[ 1 ], "off" ) ) {
+      mlfq.flags &= ~MLFQ_FLAG_WAKEUP_PREEMPT;
+    }
+    else {
+      mlfq.flags |=  MLFQ_FLAG_WAKEUP_PREEMPT; mlfq.preempt_delay = atoi( argv[ 1 ] );
+    }
+  }
+
+  if( -1 == sched_ctl( SCHED_SET_MLFQ, &mlfq ) ) {
+    puts( "mlfq: invalid parameters\n", 25 );
+  }
+}
+
+void set_deadlock( char* x ) {
+  int r = -1;
+  if     ( 0 == strcmp( x, "report"    ) ) {
+    r = deadlock( DEADLOCK_REPORT, 0 );
+  }
+  else if( 0 == strcmp( x, "fail"      ) ) {
+    r = deadlock( DEADLOCK_FAIL, 0 );
+  }
+  else if( 0 == strcmp( x, "terminate" ) ) {
+    r = deadlock( DEADLOCK_SIGNAL, SIG_TERM );
+  }
+  if( -1 == r ) {
+    puts( "deadlock: expected report, fail or terminate\n", 45 );
+  }
+}
+
 void main_console() {
   while( 1 ) {
     char cmd[ MAX_CMD_CHARS ];
 
-    // step 1: write command prompt, then read command.
+    // step 1: forget programs which have exited, write command prompt, then read command.
+
+    while( waitpid( -1, NULL, WAIT_NOHANG ) > 0 );
 
     // I changed n from 7 to 9
     puts( "console$ ", 9 ); gets( cmd, MAX_CMD_CHARS );
@@ -109,7 +327,7 @@ void main_console() {
 
     int cmd_argc = 0; char* cmd_argv[ MAX_CMD_ARGS ];
 
-    for( char* t = strtok( cmd, " " ); t != NULL; t = strtok( NULL, " " ) ) {
+    for( char* t = strtok( cmd, " " ); t != NULL && cmd_argc < MAX_CMD_ARGS; t = strtok( NULL, " " ) ) {
       cmd_argv[ cmd_argc++ ] = t;
     }
 
@@ -119,9 +337,14 @@ void main_console() {
       void* addr = load( cmd_argv[ 1 ] );
 
       if( addr != NULL ) {
-        if( 0 == fork() ) {
+        pid_t pid = fork();
+        if( 0 == pid ) {
+          setpgid( 0, 0 );
           exec( addr );
         }
+        // put the program in its own group, and make it receive ^C and ^Z
+        setpgid( pid, pid );
+        ioctl( UART1_FILENO, TTY_SET_PGRP, &pid );
       }
       else {
         puts( "unknown program\n", 16 );
@@ -130,6 +353,36 @@ void main_console() {
     else if( 0 == strcmp( cmd_argv[ 0 ], "terminate" ) ) {
       kill( atoi( cmd_argv[ 1 ] ), SIG_TERM );
     } 
+    else if( 0 == strcmp( cmd_argv[ 0 ], "continue"  ) ) {
+      kill( atoi( cmd_argv[ 1 ] ), SIG_CONT );
+    } 
+    else if( 0 == strcmp( cmd_argv[ 0 ], "dmesg"     ) ) {
+      int n = syslog( SYSLOG_READ_ALL, dmesg, sizeof( dmesg ) );
+      puts( dmesg, n );
+    } 
+    else if( 0 == strcmp( cmd_argv[ 0 ], "loglevel"  ) ) {
+      syslog( SYSLOG_SET_LEVEL, NULL, atoi( cmd_argv[ 1 ] ) );
+    } 
+    else if( 0 == strcmp( cmd_argv[ 0 ], "ps"        ) ) {
+      list_procs( NULL, 0 );
+    } 
+    else if( 0 == strcmp( cmd_argv[ 0 ], "top"       ) ) {
+      top();
+    } 
+    else if( 0 == strcmp( cmd_argv[ 0 ], "nice"      ) ) {
+      if( cmd_argc < 3 ) {
+        puts( "usage: nice <pid> <x>\n", 22 );
+      }
+      else if( nice( atoi( cmd_argv[ 1 ] ), atoi( cmd_argv[ 2 ] ) ) < 0 ) {
+        puts( "nice: no such process\n", 22 );
+      }
+    } 
+    else if( 0 == strcmp( cmd_argv[ 0 ], "mlfq"      ) ) {
+      tune_mlfq( cmd_argc - 1, &cmd_argv[ 1 ] );
+    } 
+    else if( 0 == strcmp( cmd_argv[ 0 ], "deadlock"  ) ) {
+      set_deadlock( cmd_argc > 1 ? cmd_argv[ 1 ] : "" );
+    } 
     else {
       puts( "unknown command\n", 16 );
     }
@@ -19,6 +19,6 @@
 #include "libc.h"
 
 #define MAX_CMD_CHARS ( 1024 )
-#define MAX_CMD_ARGS  (    2 )
+#define MAX_CMD_ARGS  (   10 )
 
 #endif
@@ -137,15 +137,18 @@ int  kill( int pid, int x ) {
   return r;
 }
 
-void nice( int pid, int x ) {
-  asm volatile( "mov r0, %1 \n" // assign r0 =  pid
-                "mov r1, %2 \n" // assign r1 =    x
-                "svc %0     \n" // make system call SYS_NICE
-              : 
+int  nice( int pid, int x ) {
+  int r;
+
+  asm volatile( "mov r0, %2 \n" // assign r0 =  pid
+                "mov r1, %3 \n" // assign r1 =    x
+                "svc %1     \n" // make system call SYS_NICE
+                "mov %0, r0 \n" // assign r0 =    r
+              : "=r" (r) 
               : "I" (SYS_NICE), "r" (pid), "r" (x)
               : "r0", "r1" );
 
-  return;
+  return r;
 }
 
 int pipe(int* fids) {
@@ -169,3 +172,359 @@ int close(int fid) {
     : "r0", "r1" );
     return r;
 }
+
+int ioctl(int fd, uint32_t request, void* arg) {
+    int r;
+    asm volatile( "mov r0, %2 \n" // assign r0 =  fd
+                  "mov r1, %3 \n" // assign r1 =  request
+                  "mov r2, %4 \n" // assign r2 =  arg
+                  "svc %1     \n" // make system call SYS_IOCTL
+                  "mov %0, r0 \n" // assign r  =  r0
+    : "=r" (r)
+    : "I" (SYS_IOCTL), "r" (fd), "r" (request), "r" (arg)
+    : "r0", "r1", "r2" );
+    return r;
+}
+
+void* mmap(int fd) {
+    void* r;
+    asm volatile( "mov r0, %2 \n" // assign r0 =  fd
+                  "svc %1     \n" // make system call SYS_MMAP
+                  "mov %0, r0 \n" // assign r  =  r0
+    : "=r" (r)
+    : "I" (SYS_MMAP), "r" (fd)
+    : "r0" );
+    return r;
+}
+
+int setpgid(pid_t pid, pid_t pgid) {
+    int r;
+    asm volatile( "mov r0, %2 \n" // assign r0 =  pid
+                  "mov r1, %3 \n" // assign r1 =  pgid
+                  "svc %1     \n" // make system call SYS_SETPGID
+                  "mov %0, r0 \n" // assign r  =  r0
+    : "=r" (r)
+    : "I" (SYS_SETPGID), "r" (pid), "r" (pgid)
+    : "r0", "r1" );
+    return r;
+}
+
+pid_t waitpid(pid_t pid, int* status, int options) {
+    int r;
+    asm volatile( "mov r0, %2 \n" // assign r0 =  pid
+                  "mov r1, %3 \n" // assign r1 =  status
+                  "mov r2, %4 \n" // assign r2 =  options
+                  "svc %1     \n" // make system call SYS_WAIT
+                  "mov %0, r0 \n" // assign r  =  r0
+    : "=r" (r)
+    : "I" (SYS_WAIT), "r" (pid), "r" (status), "r" (options)
+    : "r0", "r1", "r2", "memory" );
+    return r;
+}
+
+int syslog(int action, char* buf, int n) {
+    int r;
+    asm volatile( "mov r0, %2 \n" // assign r0 =  action
+                  "mov r1, %3 \n" // assign r1 =  buf
+                  "mov r2, %4 \n" // assign r2 =  n
+                  "svc %1     \n" // make system call SYS_SYSLOG
+                  "mov %0, r0 \n" // assign r  =  r0
+    : "=r" (r)
+    : "I" (SYS_SYSLOG), "r" (action), "r" (buf), "r" (n)
+    : "r0", "r1", "r2" );
+    return r;
+}
+
+int proclist(proc_info_t* x, int n) {
+    int r;
+    asm volatile( "mov r0, %2 \n" // assign r0 =  x
+                  "mov r1, %3 \n" // assign r1 =  n
+                  "svc %1     \n" // make system call SYS_PROCLIST
+                  "mov %0, r0 \n" // assign r  =  r0
+    : "=r" (r)
+    : "I" (SYS_PROCLIST), "r" (x), "r" (n)
+    : "r0", "r1" );
+    return r;
+}
+
+int getrusage(pid_t pid, rusage_t* x) {
+    int r;
+    asm volatile( "mov r0, %2 \n" // assign r0 =  pid
+                  "mov r1, %3 \n" // assign r1 =  x
+                  "svc %1     \n" // make system call SYS_GETRUSAGE
+                  "mov %0, r0 \n" // assign r  =  r0
+    : "=r" (r)
+    : "I" (SYS_GETRUSAGE), "r" (pid), "r" (x)
+    : "r0", "r1" );
+    return r;
+}
+
+int times(sched_stats_t* x) {
+    int r;
+    asm volatile( "mov r0, %2 \n" // assign r0 =  x
+                  "svc %1     \n" // make system call SYS_TIMES
+                  "mov %0, r0 \n" // assign r  =  r0
+    : "=r" (r)
+    : "I" (SYS_TIMES), "r" (x)
+    : "r0" );
+    return r;
+}
+
+// A thread returns here from fn
+static void thread_return() {
+    thread_exit( EXIT_SUCCESS );
+}
+
+tid_t clone(void (*fn)(void*), void* arg) {
+    tid_t r;
+    asm volatile( "mov r0, %2 \n" // assign r0 =  fn
+                  "mov r1, %3 \n" // assign r1 =  arg
+                  "mov r2, %4 \n" // assign r2 =  thread_return
+                  "svc %1     \n" // make system call SYS_CLONE
+                  "mov %0, r0 \n" // assign r  =  r0
+    : "=r" (r)
+    : "I" (SYS_CLONE), "r" (fn), "r" (arg), "r" (thread_return)
+    : "r0", "r1", "r2" );
+    return r;
+}
+
+void thread_exit(int x) {
+    asm volatile( "mov r0, %1 \n" // assign r0 =  x
+                  "svc %0     \n" // make system call SYS_THREAD_EXIT
+    :
+    : "I" (SYS_THREAD_EXIT), "r" (x)
+    : "r0" );
+}
+
+int sched_ctl(int request, mlfq_config_t* x) {
+    int r;
+    asm volatile( "mov r0, %2 \n" // assign r0 =  request
+                  "mov r1, %3 \n" // assign r1 =  x
+                  "svc %1     \n" // make system call SYS_SCHEDCTL
+                  "mov %0, r0 \n" // assign r  =  r0
+    : "=r" (r)
+    : "I" (SYS_SCHEDCTL), "r" (request), "r" (x)
+    : "r0", "r1" );
+    return r;
+}
+
+int futex(uint32_t* addr, int op, uint32_t val) {
+    int r;
+    asm volatile( "mov r0, %2 \n" // assign r0 =  addr
+                  "mov r1, %3 \n" // assign r1 =  op
+                  "mov r2, %4 \n" // assign r2 =  val
+                  "svc %1     \n" // make system call SYS_FUTEX
+                  "mov %0, r0 \n" // assign r  =  r0
+    : "=r" (r)
+    : "I" (SYS_FUTEX), "r" (addr), "r" (op), "r" (val)
+    : "r0", "r1", "r2", "memory" );
+    return r;
+}
+
+int shm_create(size_t n) {
+    int r;
+    asm volatile( "mov r0, %2 \n" // assign r0 =  n
+                  "svc %1     \n" // make system call SYS_SHM_CREATE
+                  "mov %0, r0 \n" // assign r  =  r0
+    : "=r" (r)
+    : "I" (SYS_SHM_CREATE), "r" (n)
+    : "r0" );
+    return r;
+}
+
+void* shm_attach(int id) {
+    void* r;
+    asm volatile( "mov r0, %2 \n" // assign r0 =  id
+                  "svc %1     \n" // make system call SYS_SHM_ATTACH
+                  "mov %0, r0 \n" // assign r  =  r0
+    : "=r" (r)
+    : "I" (SYS_SHM_ATTACH), "r" (id)
+    : "r0" );
+    return r;
+}
+
+int shm_detach(void* x) {
+    int r;
+    asm volatile( "mov r0, %2 \n" // assign r0 =  x
+                  "svc %1     \n" // make system call SYS_SHM_DETACH
+                  "mov %0, r0 \n" // assign r  =  r0
+    : "=r" (r)
+    : "I" (SYS_SHM_DETACH), "r" (x)
+    : "r0" );
+    return r;
+}
+
+int msg_send(pid_t pid, const void* x, void* r) {
+    int result;
+    asm volatile( "mov r0, %2 \n" // assign r0 =  pid
+                  "mov r1, %3 \n" // assign r1 =  x
+                  "mov r2, %4 \n" // assign r2 =  r
+                  "svc %1     \n" // make system call SYS_SEND
+                  "mov %0, r0 \n" // assign result = r0
+    : "=r" (result)
+    : "I" (SYS_SEND), "r" (pid), "r" (x), "r" (r)
+    : "r0", "r1", "r2", "memory" );
+    return result;
+}
+
+pid_t msg_receive(void* x) {
+    pid_t r;
+    asm volatile( "mov r0, %2 \n" // assign r0 =  x
+                  "svc %1     \n" // make system call SYS_RECEIVE
+                  "mov %0, r0 \n" // assign r  =  r0
+    : "=r" (r)
+    : "I" (SYS_RECEIVE), "r" (x)
+    : "r0", "memory" );
+    return r;
+}
+
+int msg_reply(pid_t pid, const void* x) {
+    int r;
+    asm volatile( "mov r0, %2 \n" // assign r0 =  pid
+                  "mov r1, %3 \n" // assign r1 =  x
+                  "svc %1     \n" // make system call SYS_REPLY
+                  "mov %0, r0 \n" // assign r  =  r0
+    : "=r" (r)
+    : "I" (SYS_REPLY), "r" (pid), "r" (x)
+    : "r0", "r1" );
+    return r;
+}
+
+int mq_open(const char* name, int flags, const mq_attr_t* attr) {
+    int r;
+    asm volatile( "mov r0, %2 \n" // assign r0 =  name
+                  "mov r1, %3 \n" // assign r1 =  flags
+                  "mov r2, %4 \n" // assign r2 =  attr
+                  "svc %1     \n" // make system call SYS_MQ_OPEN
+                  "mov %0, r0 \n" // assign r  =  r0
+    : "=r" (r)
+    : "I" (SYS_MQ_OPEN), "r" (name), "r" (flags), "r" (attr)
+    : "r0", "r1", "r2" );
+    return r;
+}
+
+int mq_send(int fd, const void* x, size_t n, uint32_t prio) {
+    int r;
+    asm volatile( "mov r0, %2 \n" // assign r0 =  fd
+                  "mov r1, %3 \n" // assign r1 =  x
+                  "mov r2, %4 \n" // assign r2 =  n
+                  "mov r3, %5 \n" // assign r3 =  prio
+                  "svc %1     \n" // make system call SYS_MQ_SEND
+                  "mov %0, r0 \n" // assign r  =  r0
+    : "=r" (r)
+    : "I" (SYS_MQ_SEND), "r" (fd), "r" (x), "r" (n), "r" (prio)
+    : "r0", "r1", "r2", "r3" );
+    return r;
+}
+
+int mq_receive(int fd, void* x, size_t n, uint32_t* prio) {
+    int r;
+    asm volatile( "mov r0, %2 \n" // assign r0 =  fd
+                  "mov r1, %3 \n" // assign r1 =  x
+                  "mov r2, %4 \n" // assign r2 =  n
+                  "mov r3, %5 \n" // assign r3 =  prio
+                  "svc %1     \n" // make system call SYS_MQ_RECEIVE
+                  "mov %0, r0 \n" // assign r  =  r0
+    : "=r" (r)
+    : "I" (SYS_MQ_RECEIVE), "r" (fd), "r" (x), "r" (n), "r" (prio)
+    : "r0", "r1", "r2", "r3", "memory" );
+    return r;
+}
+
+int socket(int type) {
+    int r;
+    asm volatile( "mov r0, %2 \n" // assign r0 =  type
+                  "svc %1     \n" // make system call SYS_SOCKET
+                  "mov %0, r0 \n" // assign r  =  r0
+    : "=r" (r)
+    : "I" (SYS_SOCKET), "r" (type)
+    : "r0" );
+    return r;
+}
+
+int bind(int fd, const char* name) {
+    int r;
+    asm volatile( "mov r0, %2 \n" // assign r0 =  fd
+                  "mov r1, %3 \n" // assign r1 =  name
+                  "svc %1     \n" // make system call SYS_BIND
+                  "mov %0, r0 \n" // assign r  =  r0
+    : "=r" (r)
+    : "I" (SYS_BIND), "r" (fd), "r" (name)
+    : "r0", "r1" );
+    return r;
+}
+
+int listen(int fd, int backlog) {
+    int r;
+    asm volatile( "mov r0, %2 \n" // assign r0 =  fd
+                  "mov r1, %3 \n" // assign r1 =  backlog
+                  "svc %1     \n" // make system call SYS_LISTEN
+                  "mov %0, r0 \n" // assign r  =  r0
+    : "=r" (r)
+    : "I" (SYS_LISTEN), "r" (fd), "r" (backlog)
+    : "r0", "r1" );
+    return r;
+}
+
+int accept(int fd) {
+    int r;
+    asm volatile( "mov r0, %2 \n" // assign r0 =  fd
+                  "svc %1     \n" // make system call SYS_ACCEPT
+                  "mov %0, r0 \n" // assign r  =  r0
+    : "=r" (r)
+    : "I" (SYS_ACCEPT), "r" (fd)
+    : "r0" );
+    return r;
+}
+
+int connect(int fd, const char* name) {
+    int r;
+    asm volatile( "mov r0, %2 \n" // assign r0 =  fd
+                  "mov r1, %3 \n" // assign r1 =  name
+                  "svc %1     \n" // make system call SYS_CONNECT
+                  "mov %0, r0 \n" // assign r  =  r0
+    : "=r" (r)
+    : "I" (SYS_CONNECT), "r" (fd), "r" (name)
+    : "r0", "r1" );
+    return r;
+}
+
+int sendmsg(int fd, const void* x, size_t n, int pass_fd) {
+    int r;
+    asm volatile( "mov r0, %2 \n" // assign r0 =  fd
+                  "mov r1, %3 \n" // assign r1 =  x
+                  "mov r2, %4 \n" // assign r2 =  n
+                  "mov r3, %5 \n" // assign r3 =  pass_fd
+                  "svc %1     \n" // make system call SYS_SENDMSG
+                  "mov %0, r0 \n" // assign r  =  r0
+    : "=r" (r)
+    : "I" (SYS_SENDMSG), "r" (fd), "r" (x), "r" (n), "r" (pass_fd)
+    : "r0", "r1", "r2", "r3" );
+    return r;
+}
+
+int recvmsg(int fd, void* x, size_t n, int* fd_out) {
+    int r;
+    asm volatile( "mov r0, %2 \n" // assign r0 =  fd
+                  "mov r1, %3 \n" // assign r1 =  x
+                  "mov r2, %4 \n" // assign r2 =  n
+                  "mov r3, %5 \n" // assign r3 =  fd_out
+                  "svc %1     \n" // make system call SYS_RECVMSG
+                  "mov %0, r0 \n" // assign r  =  r0
+    : "=r" (r)
+    : "I" (SYS_RECVMSG), "r" (fd), "r" (x), "r" (n), "r" (fd_out)
+    : "r0", "r1", "r2", "r3", "memory" );
+    return r;
+}
+
+int deadlock(int action, int signal) {
+    int r;
+    asm volatile( "mov r0, %2 \n" // assign r0 =  action
+                  "mov r1, %3 \n" // assign r1 =  signal
+                  "svc %1     \n" // make system call SYS_DEADLOCK
+                  "mov %0, r0 \n" // assign r  =  r0
+    : "=r" (r)
+    : "I" (SYS_DEADLOCK), "r" (action), "r" (signal)
+    : "r0", "r1" );
+    return r;
+}
@@ -16,6 +16,10 @@
 
 typedef int pid_t;
 
+// And a Thread IDentifier (TID), which is separate from PIDs.
+
+typedef int tid_t;
+
 /* The definitions below capture symbolic constants within these classes:
  *
  * 1. system call identifiers (i.e., the constant used by a system call
@@ -40,9 +44,43 @@ typedef int pid_t;
 #define SYS_NICE      ( 0x07 )
 #define SYS_CLOSE     ( 0x08 )
 #define SYS_PIPE      ( 0x09 )
+#define SYS_IOCTL     ( 0x0A )
+#define SYS_MMAP      ( 0x0B )
+#define SYS_SETPGID   ( 0x0C )
+#define SYS_SYSLOG    ( 0x0D )
+#define SYS_PROCLIST  ( 0x0E )
+#define SYS_GETRUSAGE ( 0x0F )
+#define SYS_TIMES     ( 0x10 )
+#define SYS_SCHEDCTL  ( 0x11 )
+#define SYS_CLONE     ( 0x12 )
+#define SYS_THREAD_EXIT ( 0x13 )
+#define SYS_FUTEX     ( 0x14 )
+#define SYS_SHM_CREATE ( 0x15 )
+#define SYS_SHM_ATTACH ( 0x16 )
+#define SYS_SHM_DETACH ( 0x17 )
+#define SYS_SEND      ( 0x18 )
+#define SYS_RECEIVE   ( 0x19 )
+#define SYS_REPLY     ( 0x1A )
+#define SYS_MQ_OPEN   ( 0x1B )
+#define SYS_MQ_SEND   ( 0x1C )
+#define SYS_MQ_RECEIVE ( 0x1D )
+#define SYS_SOCKET    ( 0x1E )
+#define SYS_BIND      ( 0x1F )
+#define SYS_LISTEN    ( 0x20 )
+#define SYS_ACCEPT    ( 0x21 )
+#define SYS_CONNECT   ( 0x22 )
+#define SYS_SENDMSG   ( 0x23 )
+#define SYS_RECVMSG   ( 0x24 )
+#define SYS_DEADLOCK  ( 0x25 )
+#define SYS_WAIT      ( 0x26 )
 
 #define SIG_TERM      ( 0x00 )
 #define SIG_QUIT      ( 0x01 )
+#define SIG_INT       ( 0x02 )
+#define SIG_TSTP      ( 0x03 )
+#define SIG_CONT      ( 0x04 )
+#define SIG_SEGV      ( 0x05 )   // sent by the kernel on a pre-fetch or data abort
+#define SIG_ILL       ( 0x06 )   // sent by the kernel on an undefined instruction
 
 #define EXIT_SUCCESS  ( 0 )
 #define EXIT_FAILURE  ( 1 )
@@ -51,6 +89,9 @@ typedef int pid_t;
 #define STDOUT_FILENO ( 1 )
 #define STDERR_FILENO ( 2 )
 #define  UART1_FILENO ( 3 )
+#define KEYBOARD_FILENO ( 4 )
+#define    MOUSE_FILENO ( 5 )
+#define       FB0_FILENO ( 6 )
 
 // convert ASCII string x into integer r
 extern int  atoi( char* x        );
@@ -75,7 +116,213 @@ extern void exec( const void* x );
 // for process identified by pid, send signal of x
 extern int  kill( pid_t pid, int x );
 // for process identified by pid, set  priority to x
-extern void nice( pid_t pid, int x );
+extern int  nice( pid_t pid, int x );
+
+// move process pid into process group pgid, 0 for pid means the caller and 0 for pgid means use pid
+extern int setpgid( pid_t pid, pid_t pgid );
+
+/* An exited process keeps its pid until its parent waits for it, or the
+ * parent exits.  Its status is the value it passed to exit, or
+ * WAIT_SIGNALLED plus the signal which killed it.
+ */
+
+#define WAIT_NOHANG    ( 0x01 )         // return 0 rather than wait if no child has exited
+#define WAIT_SIGNALLED ( 128 )
+
+// wait for child pid, or any child if -1, to exit and store its status in status unless it is NULL; return its pid, or -1 if there is no such child
+extern pid_t waitpid( pid_t pid, int* status, int options );
+
+// kernel log actions, buf and n are used by SYSLOG_READ_ALL, otherwise n is the new setting
+#define SYSLOG_READ_ALL  ( 0 )          // copy the oldest n bytes of the kernel log into buf
+#define SYSLOG_CLEAR     ( 1 )
+#define SYSLOG_SET_LEVEL ( 2 )          // 0 off, 1 error, 2 warn, 3 info (default), 4 debug, 5 trace
+#define SYSLOG_SET_SINK  ( 3 )          // 0 memory only, 1 UART0 (default), 2 UART1
+
+extern int syslog( int action, char* buf, int n );
+
+// process status, as reported by proclist
+#define PROC_READY     ( 0 )
+#define PROC_EXECUTING ( 1 )
+#define PROC_BLOCKED   ( 2 )
+#define PROC_STOPPED   ( 3 )
+#define PROC_EXITED    ( 4 )
+
+typedef struct {
+    pid_t    pid;
+    pid_t    ppid;              // -1 if the process has no parent
+    pid_t    pgid;
+    uint32_t status;
+    int32_t  queue_level;       // 0 is the top scheduler queue, -1 if not queued
+    uint32_t run_count;         // quanta used since last dispatched
+    uint32_t ticks;             // total quanta used
+    uint32_t fids;              // open file descriptors
+    uint32_t stack_used;        // bytes
+    uint32_t stack_size;
+    uint32_t threads;
+} proc_info_t;
+
+// fill x with information on up to n processes, ordered by pid; return the number filled
+extern int proclist( proc_info_t* x, int n );
+
+// CPU accounting for one process, in timer ticks
+typedef struct {
+    uint32_t user_ticks;
+    uint32_t ready_ticks;           // waiting to be scheduled
+    uint32_t blocked_ticks;         // blocked or stopped
+    uint32_t voluntary_switches;    // after yielding, blocking or exiting
+    uint32_t involuntary_switches;  // after preemption or a signal
+    uint32_t demotions;             // moves to a lower scheduler queue
+    uint32_t boosts;                // moves to the top scheduler queue
+} rusage_t;
+
+// totals for the whole scheduler, in timer ticks
+typedef struct {
+    uint32_t ticks;
+    uint32_t idle_ticks;
+    uint32_t voluntary_switches;
+    uint32_t involuntary_switches;
+    uint32_t demotions;
+    uint32_t boosts;                // priority boosts
+} sched_stats_t;
+
+#define RUSAGE_SELF ( -1 )
+
+// fill x with the CPU accounting of process pid, or the calling process if pid is RUSAGE_SELF; return 0 or -1
+extern int getrusage( pid_t pid, rusage_t* x );
+// fill x with the scheduler totals
+extern int times( sched_stats_t* x );
+
+// sched_ctl requests
+#define SCHED_GET_MLFQ ( 0 )
+#define SCHED_SET_MLFQ ( 1 )
+
+#define MLFQ_MAX_LEVELS         ( 8 )
+#define MLFQ_FLAG_BOOST         ( 0x01 ) // periodically move every process to the top queue
+#define MLFQ_FLAG_YIELD_DEMOTES ( 0x02 ) // yielding moves a process down a queue
+#define MLFQ_FLAG_WAKEUP_PREEMPT ( 0x04 ) // a woken process in a higher queue preempts the running one
+
+typedef struct {
+    uint32_t levels;
+    uint32_t quantum[ MLFQ_MAX_LEVELS ]; // ticks, for each queue from the top down
+    uint32_t boost_interval;             // ticks
+    uint32_t flags;
+    uint32_t preempt_delay;              // ticks, the most a woken process waits before preempting
+} mlfq_config_t;
+
+// read or change the MLFQ scheduler parameters in x, only processes started by the kernel can change them
+extern int sched_ctl( int request, mlfq_config_t* x );
+
+// start a thread in this process which calls fn( arg ) on its own stack, sharing everything else; return its tid
+extern tid_t clone( void (*fn)( void* ), void* arg );
+// exit the calling thread, the process exits when its last thread does (exit ends every thread)
+extern void thread_exit( int x );
+
+// futex operations
+#define FUTEX_WAIT ( 0 )                // block while *addr == val, until woken; -1 if *addr != val
+#define FUTEX_WAKE ( 1 )                // unblock up to val threads waiting on addr; return the number woken
+
+// wait on, or wake threads waiting on, the word at addr; see sync.h for locks built on this
+extern int futex( uint32_t* addr, int op, uint32_t val );
+
+#define SHM_MAX_BYTES ( 0x10000 )
+
+/* Shared memory segments are owned by the kernel and zeroed when created.
+ * Every attached process sees a segment at the same address, they are kept
+ * across fork, and a segment is freed once every process has detached or
+ * exited.
+ */
+
+// create a segment of at least n bytes and attach to it; return its id, or -1
+extern int shm_create( size_t n );
+// attach to the segment id; return its address, or (void*) -1 on failure
+extern void* shm_attach( int id );
+// detach from the segment at address x; return 0, or -1
+extern int shm_detach( void* x );
+
+/* Synchronous messages between processes: a sender blocks until its message
+ * has been received and replied to, and every message is MSG_BYTES long.
+ */
+
+#define MSG_BYTES ( 64 )
+
+// send x to process pid and wait for the reply to be written into r; return MSG_BYTES, or -1 if pid exits first
+extern int msg_send( pid_t pid, const void* x, void* r );
+// wait for a message to be sent to this process and copy it into x; return the pid of its sender
+extern pid_t msg_receive( void* x );
+// unblock process pid, which is waiting for a reply to a message this process received; return 0, or -1
+extern int msg_reply( pid_t pid, const void* x );
+
+/* Message queues are named, bounded queues of messages which are received
+ * highest priority first.  They are file descriptors, so are closed with
+ * close and kept across fork, and a queue is removed once it has been
+ * closed everywhere.
+ */
+
+#define MQ_CREATE    ( 0x01 )
+#define MQ_EXCLUSIVE ( 0x02 )           // with MQ_CREATE, fail if the queue already exists
+#define MQ_NONBLOCK  ( 0x04 )           // fail instead of blocking on a full or empty queue
+
+#define MQ_NAME_MAX         ( 32 )
+#define MQ_MAX_MESSAGES     ( 64 )
+#define MQ_MAX_MESSAGE_SIZE ( 1024 )
+
+typedef struct {
+    uint32_t max_messages;              // 8 by default
+    uint32_t message_size;              // bytes, 64 by default
+} mq_attr_t;
+
+// open the queue called name, creating it with attr (or the defaults if NULL) for MQ_CREATE; return a file descriptor, or -1
+extern int mq_open( const char* name, int flags, const mq_attr_t* attr );
+// send n bytes from x with priority prio; return n, or -1
+extern int mq_send( int fd, const void* x, size_t n, uint32_t prio );
+// receive the oldest message of the highest priority into x, of at least message_size bytes; return its length, or -1
+extern int mq_receive( int fd, void* x, size_t n, uint32_t* prio );
+
+/* Unix domain sockets, named by a string of up to SOCK_NAME_MAX bytes.
+ * A stream socket is bound and listens for connections, and each accept
+ * returns a new socket connected to the socket that called connect.  A
+ * datagram socket connects to a bound datagram socket to send to it, and
+ * each read returns one datagram, truncated to fit.  Sockets are read and
+ * written with read and write, and sendmsg can also pass a file descriptor
+ * to the peer, which gets its own descriptor for the file from recvmsg.
+ */
+
+#define SOCK_STREAM   ( 0x00 )
+#define SOCK_DGRAM    ( 0x01 )
+
+#define SOCK_NAME_MAX     ( 32 )
+#define SOCK_BACKLOG_MAX  ( 16 )
+
+// create a socket of type SOCK_STREAM or SOCK_DGRAM; return a file descriptor, or -1
+extern int socket( int type );
+// give the socket a name, released when the socket is closed; return 0, or -1
+extern int bind( int fd, const char* name );
+// accept up to backlog pending connections to a bound stream socket; return 0, or -1
+extern int listen( int fd, int backlog );
+// wait for a connection; return a file descriptor for it, or -1
+extern int accept( int fd );
+// connect to the socket called name; return 0, or -1
+extern int connect( int fd, const char* name );
+// write n bytes from x, passing pass_fd with them unless it is -1; return the bytes written, or -1
+extern int sendmsg( int fd, const void* x, size_t n, int pass_fd );
+// read up to n bytes into x, storing the descriptor of any file passed with them at fd_out (or -1); return the bytes read, or -1
+extern int recvmsg( int fd, void* x, size_t n, int* fd_out );
+
+/* The kernel logs processes blocked on pipes which can never be woken,
+ * because they wait on each other or on pipe ends that no process
+ * holds.  It can also break the deadlock, by failing the read or write
+ * of the youngest deadlocked thread with EDEADLK, or signalling its
+ * process, until there is no deadlock left.
+ */
+
+#define DEADLOCK_REPORT ( 0x00 )
+#define DEADLOCK_FAIL   ( 0x01 )
+#define DEADLOCK_SIGNAL ( 0x02 )         // the signal must terminate the process
+
+#define EDEADLK         ( -35 )
+
+// choose what the kernel does about deadlocks, only processes started by the kernel may; return 0, or -1
+extern int deadlock( int action, int signal );
 
 // Create an unnamed pipe, [0] = Read End, [1] = Write End
 int pipe(int [2]);
@@ -83,4 +330,79 @@ int pipe(int [2]);
 // Close a file descriptor
 int close(int fd);
 
+// Perform a device specific request on a file descriptor, returns -1 on failure
+int ioctl(int fd, uint32_t request, void* arg);
+
+// Get direct access to the memory behind a file descriptor, returns (void*) -1 on failure
+void* mmap(int fd);
+
+// Framebuffer (FB0_FILENO) ioctl requests
+
+#define FBIO_GET_INFO    ( 0x4600 )
+#define FB_FORMAT_RGB555 ( 0 )
+
+typedef struct {
+    uint32_t width;
+    uint32_t height;
+    uint32_t bits_per_pixel;
+    uint32_t stride;            // bytes per row
+    uint32_t format;
+} fb_info_t;
+
+// UART (STDIN_FILENO, UART1_FILENO) ioctl requests
+
+#define TTY_GET_FLAGS    ( 0x5401 )
+#define TTY_SET_FLAGS    ( 0x5402 )
+#define TTY_GET_PGRP     ( 0x540F )     // foreground process group, sent SIG_INT on ^C and SIG_TSTP on ^Z
+#define TTY_SET_PGRP     ( 0x5410 )
+
+#define TTY_FLAG_CANONICAL ( 0x01 )     // buffer input into lines and handle editing keys
+#define TTY_FLAG_ECHO      ( 0x02 )
+
+#define UART_GET_CONFIG  ( 0x5501 )
+#define UART_SET_CONFIG  ( 0x5502 )     // anything still in the FIFOs is lost
+#define UART_GET_ERRORS  ( 0x5503 )
+#define UART_SET_OVERFLOW ( 0x5504 )    // arg points to one of the UART_OVERFLOW_ policies
+#define UART_GET_RX_STATS ( 0x5505 )
+
+#define UART_PARITY_NONE ( 0 )
+#define UART_PARITY_ODD  ( 1 )
+#define UART_PARITY_EVEN ( 2 )
+
+#define UART_OVERFLOW_DROP_NEWEST   ( 0 )
+#define UART_OVERFLOW_DROP_OLDEST   ( 1 )
+#define UART_OVERFLOW_FLOW_CONTROL  ( 2 )   // stop reading the UART, and use RTS/CTS, until there is room
+
+#define UART_FIFO_1_8    ( 0 )
+#define UART_FIFO_1_4    ( 1 )
+#define UART_FIFO_1_2    ( 2 )
+#define UART_FIFO_3_4    ( 3 )
+#define UART_FIFO_7_8    ( 4 )
+
+typedef struct {
+    uint32_t ibrd;              // baud rate divisor = UARTCLK / (16 * baud) = ibrd + fbrd / 64
+    uint32_t fbrd;
+    uint32_t word_length;       // 5 to 8 bits
+    uint32_t parity;
+    uint32_t stop_bits;         // 1 or 2
+    uint32_t fifo;              // non-zero to enable the FIFOs
+    uint32_t rx_level;          // interrupt FIFO levels
+    uint32_t tx_level;
+} uart_config_t;
+
+typedef struct {
+    uint32_t overrun;
+    uint32_t framing;
+    uint32_t parity;
+    uint32_t breaks;
+} uart_errors_t;
+
+typedef struct {
+    uint32_t received;
+    uint32_t dropped;           // lost because the input buffer was full
+    uint32_t throttled;         // times reception was paused for flow control
+    uint32_t buffered;
+    uint32_t overflow;          // the current UART_OVERFLOW_ policy
+} uart_rx_stats_t;
+
 #endif
@@ -0,0 +1,47 @@
+#include "messages.h"
+#include <string.h>
+
+static void print(char* x) {
+    write(STDOUT_FILENO, x, strlen(x));
+}
+
+// Replies to each message with it in upper case, until it is sent an empty message
+static void uppercase_server() {
+    char message[MSG_BYTES];
+    while (true) {
+        pid_t client = msg_receive(message);
+        for (int i = 0; i < MSG_BYTES && message[i] != '\0'; i++) {
+            if (message[i] >= 'a' && message[i] <= 'z') message[i] = message[i] - 'a' + 'A';
+        }
+        msg_reply(client, message);
+        if (message[0] == '\0') exit(EXIT_SUCCESS);
+    }
+}
+
+void main_messages() {
+    print("\nStarting messages test program");
+
+    pid_t server = fork();
+    if (server == 0) uppercase_server();
+
+    char* requests[] = { "hello", "send, receive and reply", "" };
+    char message[MSG_BYTES];
+    char reply[MSG_BYTES];
+    for (int i = 0; i < 3; i++) {
+        memset(message, 0, MSG_BYTES);
+        strncpy(message, requests[i], MSG_BYTES - 1);
+        if (msg_send(server, message, reply) != MSG_BYTES) {
+            print("\nSend failed\n");
+            exit(EXIT_FAILURE);
+        }
+        print("\nSent: ");
+        print(message);
+        print(" got: ");
+        print(reply);
+    }
+
+    // The server has exited, so sending fails
+    int result = msg_send(server, message, reply);
+    print(result == -1 ? "\nSending to the exited server failed\n" : "\nSending to the exited server succeeded\n");
+    exit(result == -1 ? EXIT_SUCCESS : EXIT_FAILURE);
+}
@@ -0,0 +1,10 @@
+#ifndef __MESSAGES_H
+#define __MESSAGES_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#include "libc.h"
+
+#endif
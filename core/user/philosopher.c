@@ -6,8 +6,58 @@ int mod(int a, int b){
     return (a % b + b) %b;
 }
 
+// Philosopher states drawn on the framebuffer, in RGB555
+#define COLOUR_BACKGROUND ( 0x0000 )
+#define COLOUR_WAITING    ( 0x001F ) // red
+#define COLOUR_EATING     ( 0x03E0 ) // green
+#define COLOUR_FINISHED   ( 0x4210 ) // grey
+
+#define RING_STEPS ( 16 )
+#define BLOCK_SIZE ( 32 )
+
+// Points around a circle of radius 256, used to seat the philosophers around the table
+static const int ring_x[RING_STEPS] = { 256,  237,  181,   98,    0,  -98, -181, -237, -256, -237, -181,  -98,    0,   98,  181,  237 };
+static const int ring_y[RING_STEPS] = {   0,   98,  181,  237,  256,  237,  181,   98,    0,  -98, -181, -237, -256, -237, -181,  -98 };
+
+typedef struct Display {
+    uint16_t* pixels;   // NULL if not drawing
+    fb_info_t info;
+} Display;
+
+// Fill in the block for one philosopher
+void draw_state(Display display, int id, int count, uint16_t colour) {
+    if (display.pixels == NULL) return;
+    int step = (id * RING_STEPS) / count;
+    int radius = display.info.height / 3;
+    int cx = display.info.width / 2 + (ring_x[step] * radius) / 256;
+    int cy = display.info.height / 2 + (ring_y[step] * radius) / 256;
+    int row_pixels = display.info.stride / 2;
+    for (int y = cy - BLOCK_SIZE / 2; y < cy + BLOCK_SIZE / 2; y++) {
+        for (int x = cx - BLOCK_SIZE / 2; x < cx + BLOCK_SIZE / 2; x++) {
+            display.pixels[y * row_pixels + x] = colour;
+        }
+    }
+}
+
+// Map the framebuffer, returning a Display with NULL pixels if it isn't usable
+Display open_display() {
+    Display display;
+    display.pixels = NULL;
+    if (ioctl(FB0_FILENO, FBIO_GET_INFO, &display.info) != 0) return display;
+    if (display.info.format != FB_FORMAT_RGB555) return display;
+    void* pixels = mmap(FB0_FILENO);
+    if (pixels == (void*) -1) return display;
+    display.pixels = pixels;
+    for (int i = 0; i < (display.info.stride / 2) * display.info.height; i++) {
+        display.pixels[i] = COLOUR_BACKGROUND;
+    }
+    return display;
+}
+
 typedef struct Philosopher {
     int id;
+    int count;
+    Display display;
     int left_recv;
     int left_send;
     int right_recv;
@@ -32,6 +82,7 @@ void philosopher_function(Philosopher self) {
         strcpy(waitingMsg, startMsg);
         strcat(waitingMsg, " is waiting to eat");
         write(STDOUT_FILENO, waitingMsg, strlen(waitingMsg));
+        draw_state(self.display, self.id, self.count, COLOUR_WAITING);
         if (!self.has_left) read(self.left_recv, &left_fork, 1);
         if (!self.has_right) read(self.right_recv, &right_fork, 1);
         self.has_left = true;
@@ -41,6 +92,7 @@ void philosopher_function(Philosopher self) {
         strcpy(eatingMsg, startMsg);
         strcat(eatingMsg, " is now eating");
         write(STDOUT_FILENO, eatingMsg, strlen(eatingMsg));
+        draw_state(self.display, self.id, self.count, COLOUR_EATING);
 
         // Eating for some time
 
@@ -49,6 +101,7 @@ void philosopher_function(Philosopher self) {
         strcpy(finishMsg, startMsg);
         strcat(finishMsg, " is finished eating");
         write(STDOUT_FILENO, finishMsg, strlen(finishMsg));
+        draw_state(self.display, self.id, self.count, COLOUR_FINISHED);
         write(self.left_send, &left_fork, 1);
         write(self.right_send, &right_fork, 1);
 
@@ -58,10 +111,17 @@ void philosopher_function(Philosopher self) {
 }
 #pragma clang diagnostic pop
 
-void main_philosopher() {
+void run_philosophers(bool draw) {
 
     const int PHILOSOPHERS_COUNT = 16;
 
+    Display display;
+    if (draw) {
+        display = open_display();
+    } else {
+        display.pixels = NULL;
+    }
+
     int left_pipe[PHILOSOPHERS_COUNT][2];
     int right_pipe[PHILOSOPHERS_COUNT][2];
 
@@ -74,6 +134,8 @@ void main_philosopher() {
 
         Philosopher x;
         x.id = i;
+        x.count = PHILOSOPHERS_COUNT;
+        x.display = display;
 
         int left_id = mod(i - 1, PHILOSOPHERS_COUNT);
         x.left_recv = right_pipe[left_id][0];
@@ -102,3 +164,12 @@ void main_philosopher() {
     }
     exit(EXIT_SUCCESS);
 }
+
+void main_philosopher() {
+    run_philosophers(false);
+}
+
+// The same as main_philosopher, but also draws the state of each philosopher on the LCD
+void main_philosopher_gui() {
+    run_philosophers(true);
+}
@@ -42,8 +42,13 @@ void main_pipes() {
     writestr(STDOUT_FILENO, "\nAttempting to write to closed pipe returned: ");
     writestr(STDOUT_FILENO, write_result_str);
 
-    writestr(STDOUT_FILENO, "\nAttempting to read more bytes will cause a deadlock...\n");
-    read(read_fid, buffer, 1);
+    // Nothing can be written to the pipe any more, so reading reaches the end of the file
+    int read_result = read(read_fid, buffer, 1);
+    char read_result_str[10];
+    itoa(read_result_str, read_result);
+    writestr(STDOUT_FILENO, "\nReading from the pipe after its write end was closed returned: ");
+    writestr(STDOUT_FILENO, read_result_str);
+    writestr(STDOUT_FILENO, "\n");
 
     exit(EXIT_SUCCESS);
 }
@@ -0,0 +1,67 @@
+#include "sockets.h"
+#include <string.h>
+
+static char* greeting = "read from a pipe passed over a socket";
+
+static void print(char* x) {
+    write(STDOUT_FILENO, x, strlen(x));
+}
+
+// Connects to the server, and reads the greeting from the file it is sent
+static void client() {
+    int fd = socket(SOCK_STREAM);
+    if (fd == -1 || connect(fd, "files") == -1) {
+        print("\nConnect failed\n");
+        exit(EXIT_FAILURE);
+    }
+    write(fd, "open", 4);
+
+    char reply[8] = {0};
+    int file = -1;
+    if (recvmsg(fd, reply, 4, &file) != 4 || file == -1) {
+        print("\nNo file was passed\n");
+        exit(EXIT_FAILURE);
+    }
+    char message[64] = {0};
+    read(file, message, strlen(greeting));
+    print("\nClient got: ");
+    print(reply);
+    print(", ");
+    print(message);
+    close(file);
+    close(fd);
+    exit(EXIT_SUCCESS);
+}
+
+void main_sockets() {
+    print("\nStarting sockets test program");
+
+    int server = socket(SOCK_STREAM);
+    if (server == -1 || bind(server, "files") == -1 || listen(server, 1) == -1) {
+        print("\nListen failed\n");
+        exit(EXIT_FAILURE);
+    }
+    if (fork() == 0) client();
+
+    // Hand the client the read end of a pipe with the greeting in it
+    int connection = accept(server);
+    char request[8] = {0};
+    read(connection, request, 4);
+    print("\nServer got: ");
+    print(request);
+
+    int fds[2];
+    pipe(fds);
+    write(fds[1], greeting, strlen(greeting));
+    sendmsg(connection, "pipe", 4, fds[0]);
+    close(fds[0]);
+    close(fds[1]);
+
+    // The client closing its end is the end of the stream
+    char rest[8];
+    int result = read(connection, rest, sizeof(rest));
+    print(result == 0 ? "\nClient disconnected\n" : "\nUnexpected data\n");
+    close(connection);
+    close(server);
+    exit(result == 0 ? EXIT_SUCCESS : EXIT_FAILURE);
+}
@@ -0,0 +1,10 @@
+#ifndef __SOCKETS_H
+#define __SOCKETS_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#include "libc.h"
+
+#endif
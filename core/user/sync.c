@@ -0,0 +1,109 @@
+#include "sync.h"
+
+// A compare and swap returning the value that was found, like the kernel's futex checks
+static uint32_t cas(uint32_t* x, uint32_t expected, uint32_t desired) {
+    __atomic_compare_exchange_n(x, &expected, desired, false, __ATOMIC_ACQUIRE, __ATOMIC_RELAXED);
+    return expected;
+}
+
+void mutex_init(mutex_t* m) {
+    __atomic_store_n(&m->state, 0, __ATOMIC_RELEASE);
+}
+
+// Once a thread has had to wait it always sets the state to 2, so that whoever holds the lock then
+// knows to wake someone when unlocking
+void mutex_lock(mutex_t* m) {
+    uint32_t c = cas(&m->state, 0, 1);
+    if (c == 0) return;
+    if (c != 2) c = __atomic_exchange_n(&m->state, 2, __ATOMIC_ACQUIRE);
+    while (c != 0) {
+        futex(&m->state, FUTEX_WAIT, 2);
+        c = __atomic_exchange_n(&m->state, 2, __ATOMIC_ACQUIRE);
+    }
+}
+
+bool mutex_trylock(mutex_t* m) {
+    return cas(&m->state, 0, 1) == 0;
+}
+
+void mutex_unlock(mutex_t* m) {
+    if (__atomic_fetch_sub(&m->state, 1, __ATOMIC_RELEASE) != 1) {
+        __atomic_store_n(&m->state, 0, __ATOMIC_RELEASE);
+        futex(&m->state, FUTEX_WAKE, 1);
+    }
+}
+
+void cond_init(cond_t* c) {
+    __atomic_store_n(&c->sequence, 0, __ATOMIC_RELEASE);
+}
+
+// If a signal comes between unlocking and waiting the sequence has changed, so the wait returns at once
+void cond_wait(cond_t* c, mutex_t* m) {
+    uint32_t sequence = __atomic_load_n(&c->sequence, __ATOMIC_ACQUIRE);
+    mutex_unlock(m);
+    futex(&c->sequence, FUTEX_WAIT, sequence);
+    // Another thread may be waiting on the mutex too, so lock it as contended
+    while (__atomic_exchange_n(&m->state, 2, __ATOMIC_ACQUIRE) != 0) {
+        futex(&m->state, FUTEX_WAIT, 2);
+    }
+}
+
+void cond_signal(cond_t* c) {
+    __atomic_fetch_add(&c->sequence, 1, __ATOMIC_RELEASE);
+    futex(&c->sequence, FUTEX_WAKE, 1);
+}
+
+void cond_broadcast(cond_t* c) {
+    __atomic_fetch_add(&c->sequence, 1, __ATOMIC_RELEASE);
+    futex(&c->sequence, FUTEX_WAKE, UINT32_MAX);
+}
+
+void sem_init(sem_t* s, uint32_t value) {
+    __atomic_store_n(&s->waiters, 0, __ATOMIC_RELAXED);
+    __atomic_store_n(&s->value, value, __ATOMIC_RELEASE);
+}
+
+bool sem_trywait(sem_t* s) {
+    uint32_t value = __atomic_load_n(&s->value, __ATOMIC_RELAXED);
+    while (value > 0) {
+        uint32_t found = cas(&s->value, value, value - 1);
+        if (found == value) return true;
+        value = found;
+    }
+    return false;
+}
+
+// Waiters are counted so that posting only makes a system call when there is someone to wake
+void sem_wait(sem_t* s) {
+    while (!sem_trywait(s)) {
+        __atomic_fetch_add(&s->waiters, 1, __ATOMIC_ACQ_REL);
+        futex(&s->value, FUTEX_WAIT, 0);
+        __atomic_fetch_sub(&s->waiters, 1, __ATOMIC_ACQ_REL);
+    }
+}
+
+void sem_post(sem_t* s) {
+    __atomic_fetch_add(&s->value, 1, __ATOMIC_ACQ_REL);
+    if (__atomic_load_n(&s->waiters, __ATOMIC_ACQUIRE) > 0) futex(&s->value, FUTEX_WAKE, 1);
+}
+
+void barrier_init(barrier_t* b, uint32_t count) {
+    b->count = count;
+    __atomic_store_n(&b->arrived, 0, __ATOMIC_RELAXED);
+    __atomic_store_n(&b->generation, 0, __ATOMIC_RELEASE);
+}
+
+// The last thread to arrive resets the barrier for its next use before opening it
+bool barrier_wait(barrier_t* b) {
+    uint32_t generation = __atomic_load_n(&b->generation, __ATOMIC_ACQUIRE);
+    if (__atomic_add_fetch(&b->arrived, 1, __ATOMIC_ACQ_REL) == b->count) {
+        __atomic_store_n(&b->arrived, 0, __ATOMIC_RELAXED);
+        __atomic_fetch_add(&b->generation, 1, __ATOMIC_RELEASE);
+        futex(&b->generation, FUTEX_WAKE, UINT32_MAX);
+        return true;
+    }
+    while (__atomic_load_n(&b->generation, __ATOMIC_ACQUIRE) == generation) {
+        futex(&b->generation, FUTEX_WAIT, generation);
+    }
+    return false;
+}
@@ -0,0 +1,60 @@
+#ifndef __SYNC_H
+#define __SYNC_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#include "libc.h"
+
+/* Locks built on the futex system call, which only enter the kernel when a
+ * thread has to wait or there is a thread to wake.  They work between the
+ * threads of a process, and between processes since memory isn't private.
+ * Each is initialised by zeroing it, or with its init function.
+ */
+
+typedef struct {
+    uint32_t state;             // 0 unlocked, 1 locked, 2 locked with waiters
+} mutex_t;
+
+typedef struct {
+    uint32_t sequence;          // changed by every signal, so a waiter notices one sent before it waits
+} cond_t;
+
+typedef struct {
+    uint32_t value;
+    uint32_t waiters;
+} sem_t;
+
+typedef struct {
+    uint32_t count;             // threads to wait for
+    uint32_t arrived;
+    uint32_t generation;        // changed each time the barrier opens
+} barrier_t;
+
+extern void mutex_init( mutex_t* m );
+extern void mutex_lock( mutex_t* m );
+// lock m if it is unlocked, return true iff. it was locked
+extern bool mutex_trylock( mutex_t* m );
+extern void mutex_unlock( mutex_t* m );
+
+extern void cond_init( cond_t* c );
+// unlock m and wait for a signal, then lock m again; may return without a signal, so check the condition
+extern void cond_wait( cond_t* c, mutex_t* m );
+// wake one waiter
+extern void cond_signal( cond_t* c );
+// wake every waiter
+extern void cond_broadcast( cond_t* c );
+
+extern void sem_init( sem_t* s, uint32_t value );
+// wait for the value to be positive, then decrement it
+extern void sem_wait( sem_t* s );
+// decrement the value if it is positive, return true iff. it was
+extern bool sem_trywait( sem_t* s );
+extern void sem_post( sem_t* s );
+
+extern void barrier_init( barrier_t* b, uint32_t count );
+// wait for count threads to arrive, return true in exactly one of them
+extern bool barrier_wait( barrier_t* b );
+
+#endif
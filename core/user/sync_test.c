@@ -0,0 +1,109 @@
+#include "sync_test.h"
+#include <string.h>
+
+#define THREADS    ( 4 )
+#define INCREMENTS ( 10000 )
+#define ITEMS      ( 100 )
+#define SLOTS      ( 4 )
+
+static mutex_t lock;
+static barrier_t barrier;
+static int counter;
+
+// A bounded buffer of items passed from the producer thread to the consumers
+static sem_t empty_slots;
+static sem_t full_slots;
+static int slots[SLOTS];
+static int next_in, next_out;
+static int consumed_total;
+
+// Signalled once every thread has finished
+static mutex_t done_lock;
+static cond_t done_cond;
+static int done;
+
+static void print(char* x) {
+    write(STDOUT_FILENO, x, strlen(x));
+}
+
+static void print_int(char* label, int x) {
+    char buffer[12];
+    itoa(buffer, x);
+    print(label);
+    print(buffer);
+}
+
+static void finished() {
+    mutex_lock(&done_lock);
+    done = done + 1;
+    cond_signal(&done_cond);
+    mutex_unlock(&done_lock);
+}
+
+static void producer(void* arg) {
+    barrier_wait(&barrier);
+    for (int i = 1; i <= ITEMS; i++) {
+        sem_wait(&empty_slots);
+        mutex_lock(&lock);
+        slots[next_in] = i;
+        next_in = (next_in + 1) % SLOTS;
+        mutex_unlock(&lock);
+        sem_post(&full_slots);
+    }
+    finished();
+}
+
+// Consumers increment the shared counter, then take their share of the items
+static void consumer(void* arg) {
+    barrier_wait(&barrier);
+    for (int i = 0; i < INCREMENTS; i++) {
+        mutex_lock(&lock);
+        counter = counter + 1;
+        mutex_unlock(&lock);
+    }
+    for (int i = 0; i < ITEMS / (THREADS - 1); i++) {
+        sem_wait(&full_slots);
+        mutex_lock(&lock);
+        consumed_total = consumed_total + slots[next_out];
+        next_out = (next_out + 1) % SLOTS;
+        mutex_unlock(&lock);
+        sem_post(&empty_slots);
+    }
+    finished();
+}
+
+void main_sync_test() {
+    print("\nStarting sync test program");
+
+    mutex_init(&lock);
+    mutex_init(&done_lock);
+    cond_init(&done_cond);
+    barrier_init(&barrier, THREADS);
+    sem_init(&empty_slots, SLOTS);
+    sem_init(&full_slots, 0);
+    counter = 0;
+    next_in = next_out = consumed_total = done = 0;
+
+    clone(producer, NULL);
+    for (int i = 1; i < THREADS; i++) clone(consumer, NULL);
+
+    mutex_lock(&done_lock);
+    while (done < THREADS) cond_wait(&done_cond, &done_lock);
+    mutex_unlock(&done_lock);
+
+    // The consumers leave ITEMS % (THREADS - 1) items in the buffer
+    int left = ITEMS % (THREADS - 1);
+    for (int i = 0; i < left; i++) {
+        sem_wait(&full_slots);
+        consumed_total = consumed_total + slots[next_out];
+        next_out = (next_out + 1) % SLOTS;
+    }
+
+    print_int("\nCounter: ", counter);
+    print_int(" expected: ", (THREADS - 1) * INCREMENTS);
+    print_int("\nSum of items: ", consumed_total);
+    print_int(" expected: ", ITEMS * (ITEMS + 1) / 2);
+    print("\n");
+
+    exit(counter == (THREADS - 1) * INCREMENTS && consumed_total == ITEMS * (ITEMS + 1) / 2 ? EXIT_SUCCESS : EXIT_FAILURE);
+}
@@ -0,0 +1,11 @@
+#ifndef __SYNC_TEST_H
+#define __SYNC_TEST_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#include "libc.h"
+#include "sync.h"
+
+#endif
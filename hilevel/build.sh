@@ -24,5 +24,7 @@ set -x #echo on
 # allocator_api currently unstable
 rustup override set nightly
 rustup target add armv7a-none-eabi
+# Frame pointers are needed for backtraces in the panic and fault handlers
+export RUSTFLAGS="-C force-frame-pointers=yes"
 # This is a hack to force build.rs to run every time
-touch build.rs && cargo build --target=armv7a-none-eabi
+touch build.rs && cargo build --target=armv7a-none-eabi --features="$HILEVEL_FEATURES"
@@ -1,26 +1,63 @@
 use alloc::alloc::{GlobalAlloc, Layout};
 use crate::bindings::{malloc, calloc, free, realloc};
+use core::ptr;
 use cty::{c_uint, c_void};
 
+// NewLib's malloc only guarantees this alignment on ARM
+const MALLOC_ALIGN: usize = 8;
+
 struct NewLibAlloc;
 
+// Allocations with a larger alignment are over-allocated, and the block malloc returned is stored in
+// the word before the aligned pointer. As malloc's blocks are MALLOC_ALIGN aligned, there are always
+// at least MALLOC_ALIGN bytes before it, which is enough for a usize
+fn over_aligned(layout: &Layout) -> bool {
+    layout.align() > MALLOC_ALIGN
+}
+
+unsafe fn alloc_aligned(layout: Layout) -> *mut u8 {
+    // Layout guarantees this doesn't overflow
+    let block = malloc((layout.size() + layout.align()) as c_uint) as *mut u8;
+    if block.is_null() { return block }
+    let offset = layout.align() - block as usize % layout.align();
+    let aligned = block.add(offset);
+    (aligned as *mut usize).sub(1).write(block as usize);
+    aligned
+}
+
+unsafe fn dealloc_aligned(ptr: *mut u8) {
+    free((ptr as *mut usize).sub(1).read() as *mut c_void);
+}
+
 // By binding to the NewLib malloc, we are able to use Rust's heap features, such as Vectors etc.
 unsafe impl GlobalAlloc for NewLibAlloc {
 
     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if over_aligned(&layout) { return alloc_aligned(layout) }
         malloc(layout.size() as c_uint) as *mut u8
     }
 
-    unsafe fn dealloc(&self, ptr: *mut u8, _layout: Layout) {
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        if over_aligned(&layout) { return dealloc_aligned(ptr) }
         free(ptr as *mut c_void);
     }
 
     unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
-        calloc(layout.size() as c_uint, 1) as *mut u8
+        if !over_aligned(&layout) { return calloc(layout.size() as c_uint, 1) as *mut u8 }
+        let ptr = alloc_aligned(layout);
+        if !ptr.is_null() { ptr::write_bytes(ptr, 0, layout.size()) }
+        ptr
     }
 
-    unsafe fn realloc(&self, ptr: *mut u8, _layout: Layout, new_size: usize) -> *mut u8 {
-        realloc(ptr as *mut c_void, new_size as c_uint) as *mut u8
+    // realloc could move an over aligned block to an address with a different offset, so it is copied instead
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        if !over_aligned(&layout) { return realloc(ptr as *mut c_void, new_size as c_uint) as *mut u8 }
+        let new = alloc_aligned(Layout::from_size_align_unchecked(new_size, layout.align()));
+        if !new.is_null() {
+            ptr::copy_nonoverlapping(ptr, new, layout.size().min(new_size));
+            dealloc_aligned(ptr);
+        }
+        new
     }
 }
 
@@ -33,3 +70,96 @@ static A: NewLibAlloc = NewLibAlloc;
 fn alloc_error(_: Layout) -> ! {
     panic!("Memory allocation failed")
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::allocator::NewLibAlloc;
+    use alloc::alloc::{GlobalAlloc, Layout};
+    use alloc::vec::Vec;
+
+    // Fill an allocation with a pattern from its id, to check it isn't overwritten
+    unsafe fn fill(ptr: *mut u8, size: usize, id: usize) {
+        (0..size).for_each(|i| *ptr.add(i) = (id + i) as u8);
+    }
+
+    unsafe fn check(ptr: *mut u8, size: usize, id: usize) -> bool {
+        (0..size).all(|i| *ptr.add(i) == (id + i) as u8)
+    }
+
+    #[test]
+    fn alignment_test() {
+        unsafe {
+            for align in (0..13).map(|x| 1 << x) {
+                for size in [1, 3, 8, 100, 4096] {
+                    let layout = Layout::from_size_align(size, align).unwrap();
+                    let ptr = NewLibAlloc.alloc(layout);
+                    assert_eq!(ptr as usize % align, 0, "{:?}", layout);
+                    fill(ptr, size, align);
+
+                    // Growing and shrinking keeps the alignment and the contents
+                    let grown = NewLibAlloc.realloc(ptr, layout, size * 3);
+                    assert_eq!(grown as usize % align, 0);
+                    assert!(check(grown, size, align));
+                    let shrunk = NewLibAlloc.realloc(grown, Layout::from_size_align(size * 3, align).unwrap(), 1);
+                    assert_eq!((shrunk as usize % align, *shrunk), (0, align as u8));
+                    NewLibAlloc.dealloc(shrunk, Layout::from_size_align(1, align).unwrap());
+
+                    let zeroed = NewLibAlloc.alloc_zeroed(layout);
+                    assert_eq!(zeroed as usize % align, 0);
+                    assert!((0..size).all(|i| *zeroed.add(i) == 0));
+                    NewLibAlloc.dealloc(zeroed, layout);
+                }
+            }
+        }
+    }
+
+    // Interleave allocations of random sizes and alignments with freeing and resizing, so that blocks are
+    // reused from a fragmented heap, and check none of them overlap or are corrupted
+    #[test]
+    fn fragmentation_test() {
+        let mut seed: u32 = 0x2545F491;
+        let mut random = |n: usize| {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            seed as usize % n
+        };
+        let mut live: Vec<(*mut u8, Layout, usize)> = Vec::new();
+        unsafe {
+            for id in 0..2000 {
+                match random(4) {
+                    0 | 1 => {
+                        let layout = Layout::from_size_align(1 + random(600), 1 << random(10)).unwrap();
+                        let ptr = NewLibAlloc.alloc(layout);
+                        assert_eq!(ptr as usize % layout.align(), 0);
+                        fill(ptr, layout.size(), id);
+                        live.push((ptr, layout, id));
+                    }
+                    2 if !live.is_empty() => {
+                        let (ptr, layout, id) = live.swap_remove(random(live.len()));
+                        assert!(check(ptr, layout.size(), id));
+                        NewLibAlloc.dealloc(ptr, layout);
+                    }
+                    3 if !live.is_empty() => {
+                        let index = random(live.len());
+                        let (ptr, layout, old_id) = live[index];
+                        let size = 1 + random(1200);
+                        let new = NewLibAlloc.realloc(ptr, layout, size);
+                        assert_eq!(new as usize % layout.align(), 0);
+                        assert!(check(new, layout.size().min(size), old_id));
+                        fill(new, size, id);
+                        live[index] = (new, Layout::from_size_align(size, layout.align()).unwrap(), id);
+                    }
+                    _ => {}
+                }
+            }
+            let mut blocks: Vec<(usize, usize)> = live.iter().map(|(ptr, layout, _)| (*ptr as usize, layout.size())).collect();
+            blocks.sort();
+            assert!(blocks.windows(2).all(|x| x[0].0 + x[0].1 <= x[1].0));
+            for (ptr, layout, id) in live {
+                assert!(check(ptr, layout.size(), id));
+                NewLibAlloc.dealloc(ptr, layout);
+            }
+        }
+    }
+}
@@ -0,0 +1,286 @@
+use crate::process::{Context, PID};
+use core::fmt::{Display, Formatter, Write, Result as FmtResult};
+use core::str;
+use num_derive::FromPrimitive;
+
+pub const CPSR_MODE_MASK: u32 = 0x1F;
+pub const CPSR_MODE_USR: u32 = 0x10;
+
+const KERNEL_STACK_SIZE: u32 = 0x1000;      // Per image.ld
+const MAX_FRAMES: usize = 16;
+
+// Must match the types passed by lolevel_handler_fault
+#[derive(FromPrimitive, Debug, Clone, Copy, PartialEq)]
+pub enum FaultKind {
+    UndefinedInstruction = 0,
+    PrefetchAbort = 1,
+    DataAbort = 2,
+}
+
+impl Display for FaultKind {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            FaultKind::UndefinedInstruction => write!(f, "Undefined instruction"),
+            FaultKind::PrefetchAbort => write!(f, "Prefetch abort"),
+            FaultKind::DataAbort => write!(f, "Data abort"),
+        }
+    }
+}
+
+// The CP15 fault status and address registers
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FaultStatus {
+    pub dfsr: u32,
+    pub dfar: u32,
+    pub ifsr: u32,
+    pub ifar: u32,
+}
+
+impl FaultStatus {
+
+    #[cfg(target_arch = "arm")]
+    pub fn read() -> Self {
+        let mut status = FaultStatus::default();
+        unsafe {
+            asm!("mrc p15, 0, {}, c5, c0, 0", out(reg) status.dfsr);
+            asm!("mrc p15, 0, {}, c6, c0, 0", out(reg) status.dfar);
+            asm!("mrc p15, 0, {}, c5, c0, 1", out(reg) status.ifsr);
+            asm!("mrc p15, 0, {}, c6, c0, 2", out(reg) status.ifar);
+        }
+        status
+    }
+
+    #[cfg(not(target_arch = "arm"))]
+    pub fn read() -> Self {
+        Default::default()
+    }
+
+}
+
+// Decode the FS bits of a DFSR or IFSR, per the ARMv7-A short descriptor format
+pub fn describe_fsr(fsr: u32) -> &'static str {
+    match ((fsr >> 6) & 0x10) | (fsr & 0xF) {
+        0x01 => "alignment fault",
+        0x02 => "debug event",
+        0x03 | 0x06 => "access flag fault",
+        0x05 | 0x07 => "translation fault",
+        0x08 => "synchronous external abort",
+        0x09 | 0x0B => "domain fault",
+        0x0D | 0x0F => "permission fault",
+        0x16 => "asynchronous external abort",
+        _ => "unknown fault",
+    }
+}
+
+// Everything known about a fault, displayed as a register dump followed by a backtrace
+pub struct FaultReport<'a> {
+    pub kind: FaultKind,
+    pub ctx: &'a Context,
+    pub status: FaultStatus,
+    pub pid: Option<PID>,
+}
+
+impl<'a> FaultReport<'a> {
+
+    pub fn user_mode(&self) -> bool {
+        self.ctx.cpsr & CPSR_MODE_MASK == CPSR_MODE_USR
+    }
+
+}
+
+impl<'a> Display for FaultReport<'a> {
+
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        let ctx = self.ctx;
+        write!(f, "{} at {:#010x} in ", self.kind, ctx.pc)?;
+        match self.pid {
+            Some(pid) if self.user_mode() => writeln!(f, "process {}", pid)?,
+            Some(pid) => writeln!(f, "kernel mode, process {}", pid)?,
+            None => writeln!(f, "kernel mode")?,
+        }
+        match self.kind {
+            FaultKind::DataAbort => {
+                let access = if self.status.dfsr & 0x800 != 0 { "write" } else { "read" };
+                writeln!(f, "DFSR {:#010x} ({} on {}) DFAR {:#010x}",
+                         self.status.dfsr, describe_fsr(self.status.dfsr), access, self.status.dfar)?
+            }
+            FaultKind::PrefetchAbort => {
+                writeln!(f, "IFSR {:#010x} ({}) IFAR {:#010x}",
+                         self.status.ifsr, describe_fsr(self.status.ifsr), self.status.ifar)?
+            }
+            FaultKind::UndefinedInstruction => {}
+        }
+        for (i, value) in ctx.gpr.iter().enumerate() {
+            write!(f, "r{:<2} {:#010x}{}", i, value, if i % 4 == 3 { "\n" } else { "  " })?;
+        }
+        writeln!(f, "sp  {:#010x}  lr  {:#010x}  pc  {:#010x}  cpsr {:#010x}", ctx.sp, ctx.lr, ctx.pc, ctx.cpsr)?;
+        writeln!(f, "pc is at {}", Symbol(ctx.pc))?;
+        writeln!(f, "lr is at {}", Symbol(ctx.lr))?;
+        // User programs are compiled without frame pointers
+        if !self.user_mode() {
+            write_backtrace(f, ctx.pc, ctx.gpr[11])?;
+        }
+        Ok(())
+    }
+
+}
+
+// The name and offset of the function containing an address, if the symbol table has one
+pub struct Symbol(pub u32);
+
+impl Display for Symbol {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match lookup_symbol(symbol_table(), self.0) {
+            Some((name, offset)) => write!(f, "{:#010x} <{}+{:#x}>", self.0, name, offset),
+            None => write!(f, "{:#010x}", self.0),
+        }
+    }
+}
+
+// The table is a list of word addresses, each followed by its NUL terminated name padded to a word
+fn lookup_symbol(table: &[u8], address: u32) -> Option<(&str, u32)> {
+    let mut best: Option<(&str, u32)> = None;
+    let mut i = 0;
+    while i + 4 <= table.len() {
+        let start = u32::from_le_bytes([table[i], table[i + 1], table[i + 2], table[i + 3]]);
+        if start == 0 { break }
+        let name_start = i + 4;
+        let name_end = table[name_start..].iter().position(|b| *b == 0)? + name_start;
+        if start <= address && best.map_or(true, |(_, offset)| address - start < offset) {
+            best = Some((str::from_utf8(&table[name_start..name_end]).unwrap_or("?"), address - start));
+        }
+        i = (name_end + 4) & !3;
+    }
+    best
+}
+
+#[cfg(not(test))]
+fn symbol_table() -> &'static [u8] {
+    extern "C" {
+        static ksyms: u8;
+        static ksyms_end: u8;
+    }
+    unsafe {
+        let start = &ksyms as *const u8;
+        let end = &ksyms_end as *const u8;
+        core::slice::from_raw_parts(start, end as usize - start as usize)
+    }
+}
+
+#[cfg(test)]
+fn symbol_table() -> &'static [u8] {
+    &[]
+}
+
+// Follow a chain of frame records, each holding the caller's frame pointer then the return address
+// Stops at the first frame pointer which is not within bounds, or that doesn't move up the stack
+fn walk_frames<R, F>(mut fp: u32, bounds: (u32, u32), read: R, mut frame: F)
+    where R: Fn(u32) -> u32, F: FnMut(u32)
+{
+    for _ in 0..MAX_FRAMES {
+        if fp % 4 != 0 || fp < bounds.0 || fp + 8 > bounds.1 { return }
+        let lr = read(fp + 4);
+        if lr == 0 { return }
+        frame(lr);
+        let next = read(fp);
+        if next <= fp { return }
+        fp = next;
+    }
+}
+
+// The kernel stacks, from the bottom of the SVC/IRQ stack to the top of the UND stack
+#[cfg(not(test))]
+fn kernel_stack_bounds() -> (u32, u32) {
+    extern "C" {
+        static tos_int: u8;
+        static tos_und: u8;
+    }
+    unsafe { (&tos_int as *const u8 as u32 - KERNEL_STACK_SIZE, &tos_und as *const u8 as u32) }
+}
+
+#[cfg(test)]
+fn kernel_stack_bounds() -> (u32, u32) {
+    (0, 0)
+}
+
+pub fn write_backtrace<W: Write>(w: &mut W, pc: u32, fp: u32) -> FmtResult {
+    writeln!(w, "Backtrace:")?;
+    writeln!(w, "  #0  {}", Symbol(pc))?;
+    let mut depth = 1;
+    let mut result = Ok(());
+    walk_frames(fp, kernel_stack_bounds(), |address| unsafe { *(address as *const u32) }, |lr| {
+        if result.is_ok() { result = writeln!(w, "  #{:<2} {}", depth, Symbol(lr)) }
+        depth = depth + 1;
+    });
+    result
+}
+
+// The frame pointer and pc of the caller, to backtrace from a panic
+#[cfg(target_arch = "arm")]
+#[inline(always)]
+pub fn current_frame() -> (u32, u32) {
+    let (fp, pc): (u32, u32);
+    unsafe {
+        asm!("mov {}, r11", out(reg) fp);
+        asm!("mov {}, pc", out(reg) pc);
+    }
+    (fp, pc)
+}
+
+#[cfg(not(target_arch = "arm"))]
+pub fn current_frame() -> (u32, u32) {
+    (0, 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fault::{lookup_symbol, walk_frames, describe_fsr, FaultReport, FaultKind, FaultStatus};
+    use crate::process::Context;
+    use alloc::vec::Vec;
+    use alloc::string::ToString;
+
+    #[test]
+    fn lookup_test() {
+        let mut table = Vec::new();
+        for (address, name) in &[(0x100u32, "main"), (0x180, "helper_fn"), (0x200, "x")] {
+            table.extend_from_slice(&address.to_le_bytes());
+            table.extend_from_slice(name.as_bytes());
+            table.push(0);
+            while table.len() % 4 != 0 { table.push(0) }
+        }
+        table.extend_from_slice(&[0, 0, 0, 0]);
+        assert_eq!(lookup_symbol(&table, 0x50), None);
+        assert_eq!(lookup_symbol(&table, 0x104), Some(("main", 4)));
+        assert_eq!(lookup_symbol(&table, 0x1FC), Some(("helper_fn", 0x7C)));
+        assert_eq!(lookup_symbol(&table, 0x300), Some(("x", 0x100)));
+    }
+
+    #[test]
+    fn walk_test() {
+        // Three frame records, the last of which points back down the stack
+        let memory = [0x1008u32, 0xA0, 0x1010, 0xB0, 0x1000, 0xC0];
+        let read = |address: u32| memory[((address - 0x1000) / 4) as usize];
+        let mut frames = Vec::new();
+        walk_frames(0x1000, (0x1000, 0x1018), read, |lr| frames.push(lr));
+        assert_eq!(frames, vec![0xA0, 0xB0, 0xC0]);
+        frames.clear();
+        walk_frames(0x0FFC, (0x1000, 0x1018), read, |lr| frames.push(lr));
+        assert!(frames.is_empty());
+    }
+
+    #[test]
+    fn report_test() {
+        assert_eq!(describe_fsr(0x805), "translation fault");
+        let mut ctx = Context::new(0x7001_0010, 0x7100_0000);
+        ctx.gpr[3] = 0xDEAD;
+        let status = FaultStatus { dfsr: 0x805, dfar: 0x4, ifsr: 0, ifar: 0 };
+        let report = FaultReport { kind: FaultKind::DataAbort, ctx: &ctx, status, pid: Some(3) };
+        assert!(report.user_mode());
+        let text = report.to_string();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines[0], "Data abort at 0x70010010 in process 3");
+        assert_eq!(lines[1], "DFSR 0x00000805 (translation fault on write) DFAR 0x00000004");
+        assert_eq!(lines[2], "r0  0x00000000  r1  0x00000000  r2  0x00000000  r3  0x0000dead");
+        assert!(!text.contains("Backtrace"));
+    }
+}
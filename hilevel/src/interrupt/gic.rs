@@ -0,0 +1,118 @@
+use crate::bindings::{GICC_t, GICD_t};
+
+// IDs 0-95 are covered by the three banks of enable registers in GICD_t
+pub const GIC_MAX_ID: u32 = 95;
+// The ID returned by IAR when there was no pending interrupt
+pub const GIC_SPURIOUS_ID: u32 = 1023;
+// Interrupts with a priority value >= the mask will never be signalled
+pub const GIC_PRIORITY_MASK: u8 = 0xF0;
+
+const IAR_ID_BITS: u32 = 0x3FF;
+
+// Thin wrapper over the GIC CPU interface and distributor registers
+#[derive(Debug)]
+pub struct Gic {
+    cpu: *mut GICC_t,
+    distributor: *mut GICD_t,
+}
+
+// The register bank (i.e. ISENABLERn) and bit within it for an interrupt ID
+fn bank_and_bit(id: u32) -> (u32, u32) {
+    (id / 32, 1 << (id % 32))
+}
+
+// The IPRIORITYRn index and byte shift for an interrupt ID, each register holds 4 priorities
+fn priority_index_and_shift(id: u32) -> (usize, u32) {
+    ((id / 4) as usize, (id % 4) * 8)
+}
+
+impl Gic {
+
+    pub fn new(cpu: *mut GICC_t, distributor: *mut GICD_t) -> Self {
+        Gic { cpu, distributor }
+    }
+
+    // Unmask priorities below GIC_PRIORITY_MASK, then enable the CPU interface and distributor
+    pub fn start(&self) {
+        unsafe {
+            (*self.cpu).PMR = GIC_PRIORITY_MASK as u32;
+            (*self.cpu).CTLR = 0x00000001;
+            (*self.distributor).CTLR = 0x00000001;
+        }
+    }
+
+    // The set-enable registers ignore zero bits, so there is no need to read-modify-write
+    pub fn enable(&self, id: u32) {
+        let (bank, bit) = bank_and_bit(id);
+        unsafe {
+            let d = &mut *self.distributor;
+            match bank {
+                0 => d.ISENABLER0 = bit,
+                1 => d.ISENABLER1 = bit,
+                2 => d.ISENABLER2 = bit,
+                _ => panic!("Interrupt ID out of range"),
+            }
+        }
+    }
+
+    pub fn set_priority(&self, id: u32, priority: u8) {
+        let (index, shift) = priority_index_and_shift(id);
+        unsafe {
+            let d = &mut *self.distributor;
+            let existing = d.IPRIORITYR[index] & !(0xFF << shift);
+            d.IPRIORITYR[index] = existing | ((priority as u32) << shift);
+        }
+    }
+
+    // Read the interrupt identifier so we know the source, the raw value must be passed back to end()
+    pub fn acknowledge(&self) -> u32 {
+        unsafe { (*self.cpu).IAR }
+    }
+
+    // Write the interrupt identifier to signal we're done
+    pub fn end(&self, iar: u32) {
+        unsafe { (*self.cpu).EOIR = iar; }
+    }
+
+}
+
+// Strip the source CPU bits from an IAR value
+pub fn iar_id(iar: u32) -> u32 {
+    iar & IAR_ID_BITS
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::interrupt::gic::{Gic, iar_id};
+    use crate::bindings::{GICC_t, GICD_t};
+
+    #[test]
+    fn enable_test() {
+        let mut cpu: GICC_t = unsafe { core::mem::zeroed() };
+        let mut distributor: GICD_t = unsafe { core::mem::zeroed() };
+        let gic = Gic::new(&mut cpu, &mut distributor);
+        gic.enable(36);
+        gic.enable(5);
+        gic.enable(95);
+        assert_eq!(distributor.ISENABLER1, 1 << 4);
+        assert_eq!(distributor.ISENABLER0, 1 << 5);
+        assert_eq!(distributor.ISENABLER2, 1 << 31);
+    }
+
+    #[test]
+    fn priority_test() {
+        let mut cpu: GICC_t = unsafe { core::mem::zeroed() };
+        let mut distributor: GICD_t = unsafe { core::mem::zeroed() };
+        let gic = Gic::new(&mut cpu, &mut distributor);
+        gic.set_priority(44, 0x80);
+        gic.set_priority(45, 0x40);
+        gic.set_priority(44, 0x20);
+        assert_eq!(distributor.IPRIORITYR[11], 0x00004020);
+    }
+
+    #[test]
+    fn iar_id_test() {
+        assert_eq!(iar_id(0x0000_0C24), 36);
+        assert_eq!(iar_id(1023), 1023);
+    }
+}
@@ -0,0 +1,124 @@
+pub mod gic;
+
+use crate::bindings::{GICC0, GICD0};
+use crate::interrupt::gic::{Gic, GIC_MAX_ID, GIC_SPURIOUS_ID, GIC_PRIORITY_MASK, iar_id};
+use crate::process::Context;
+use crate::state::KernelState;
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+
+// Handlers are plain functions so that they can be given the whole KernelState,
+// including the InterruptManager they were looked up from
+pub type InterruptHandler = fn(&mut KernelState, &mut Context);
+
+// Interrupts that had no handler, the totals are included in the warnings logged for each one
+#[derive(Default, Debug)]
+struct InterruptStats {
+    spurious: u32,
+    unknown: u32,
+}
+
+// Drivers register a handler against a GIC interrupt ID, the manager takes care of the GIC itself
+pub struct InterruptManager {
+    gic: Gic,
+    handlers: BTreeMap<u32, InterruptHandler>,
+    stats: InterruptStats,
+}
+
+impl Default for InterruptManager {
+    fn default() -> Self {
+        InterruptManager::new(unsafe { Gic::new(GICC0, GICD0) })
+    }
+}
+
+impl InterruptManager {
+
+    pub fn new(gic: Gic) -> Self {
+        InterruptManager {
+            gic,
+            handlers: Default::default(),
+            stats: Default::default(),
+        }
+    }
+
+    // Lower priority values are higher priority, anything at or above the GIC mask would never fire
+    pub fn register(&mut self, id: u32, priority: u8, handler: InterruptHandler) -> Result<(), String> {
+        if id > GIC_MAX_ID { return Err("invalid interrupt id".to_string()) }
+        if priority >= GIC_PRIORITY_MASK { return Err("priority is masked".to_string()) }
+        if self.handlers.contains_key(&id) { return Err("interrupt already registered".to_string()) }
+        self.handlers.insert(id, handler);
+        self.gic.set_priority(id, priority);
+        self.gic.enable(id);
+        Ok(())
+    }
+
+    // Should be called once all of the boot time handlers are registered
+    pub fn start(&self) {
+        self.gic.start();
+    }
+
+    // Find the handler for an acknowledged interrupt, counting any that we can't handle
+    fn lookup(&mut self, id: u32) -> Option<InterruptHandler> {
+        if id == GIC_SPURIOUS_ID {
+            self.stats.spurious = self.stats.spurious.saturating_add(1);
+            warn!("Spurious IRQ, {} so far", self.stats.spurious);
+            return None
+        }
+        let handler = self.handlers.get(&id).copied();
+        if handler.is_none() {
+            self.stats.unknown = self.stats.unknown.saturating_add(1);
+            warn!("Unknown IRQ {}, {} unknown so far", id, self.stats.unknown);
+        }
+        handler
+    }
+
+    // Acknowledge the pending interrupt, run its handler, then signal the GIC we're done
+    pub fn handle(state: &mut KernelState, ctx: &mut Context) {
+        let iar = state.interrupt_manager.gic.acknowledge();
+        let id = iar_id(iar);
+        let handler = state.interrupt_manager.lookup(id);
+        handler.map(|handler| handler(state, ctx));
+        // A spurious interrupt was never activated, so there is nothing to end
+        if id != GIC_SPURIOUS_ID {
+            state.interrupt_manager.gic.end(iar);
+        }
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::interrupt::InterruptManager;
+    use crate::interrupt::gic::Gic;
+    use crate::bindings::{GICC_t, GICD_t};
+    use crate::state::KernelState;
+    use crate::process::Context;
+
+    fn noop(_state: &mut KernelState, _ctx: &mut Context) {}
+
+    #[test]
+    fn register_test() {
+        let mut cpu: GICC_t = unsafe { core::mem::zeroed() };
+        let mut distributor: GICD_t = unsafe { core::mem::zeroed() };
+        let mut manager = InterruptManager::new(Gic::new(&mut cpu, &mut distributor));
+        assert!(manager.register(44, 0x80, noop).is_ok());
+        assert!(manager.register(44, 0x80, noop).is_err());
+        assert!(manager.register(96, 0x80, noop).is_err());
+        assert!(manager.register(45, 0xF0, noop).is_err());
+        assert_eq!(distributor.IPRIORITYR[11], 0x80);
+        assert_eq!(distributor.ISENABLER1, 1 << 12);
+    }
+
+    #[test]
+    fn lookup_test() {
+        let mut cpu: GICC_t = unsafe { core::mem::zeroed() };
+        let mut distributor: GICD_t = unsafe { core::mem::zeroed() };
+        let mut manager = InterruptManager::new(Gic::new(&mut cpu, &mut distributor));
+        manager.register(36, 0x40, noop).unwrap();
+        assert!(manager.lookup(36).is_some());
+        assert!(manager.lookup(1023).is_none());
+        assert!(manager.lookup(52).is_none());
+        assert!(manager.lookup(52).is_none());
+        assert_eq!((manager.stats.spurious, manager.stats.unknown), (1, 2));
+    }
+}
@@ -1,40 +1,289 @@
 #![allow(non_snake_case)]
 
 use crate::bindings;
-use crate::bindings::{PL011_t, PL011_putc, PL011_getc};
+use crate::bindings::{PL011_t, PL011_putc};
 use core::fmt::{Write, Error};
 use core::result::Result;
 use crate::io::descriptor::{FileDescriptor, FileDescriptorBase, IOResult, FileError};
-use alloc::collections::VecDeque;
+use crate::io::tty::{LineDiscipline, TtySignal, OverflowPolicy};
+use alloc::vec::Vec;
 
 const KEYBOARD_BUFFER: usize = 4096;
+const TX_BUFFER: usize = 1024;
 
+const FR_BUSY: u32 = 0x00000008;
+const FR_RXFE: u32 = 0x00000010;        // Receive FIFO empty
+const FR_TXFF: u32 = 0x00000020;        // Transmit FIFO full
+const CR_UARTEN: u32 = 0x00000001;
+const CR_RTSEN: u32 = 0x00004000;       // Hardware flow control, RTS is deasserted while the Rx FIFO is full
+const CR_CTSEN: u32 = 0x00008000;
+const INT_RX: u32 = 0x00000010;
+const INT_TX: u32 = 0x00000020;
+const INT_RT: u32 = 0x00000040;         // Receive timeout
+
+const LCRH_PEN: u32 = 0x00000002;       // Parity enable
+const LCRH_EPS: u32 = 0x00000004;       // Even parity
+const LCRH_STP2: u32 = 0x00000008;      // Two stop bits
+const LCRH_FEN: u32 = 0x00000010;       // FIFO enable
+
+const DR_FE: u32 = 0x00000100;          // Framing error
+const DR_PE: u32 = 0x00000200;          // Parity error
+const DR_BE: u32 = 0x00000400;          // Break error
+const DR_OE: u32 = 0x00000800;          // Overrun error
+
+// ioctl requests, must match libc.h
+pub const UART_GET_CONFIG: u32 = 0x5501;
+pub const UART_SET_CONFIG: u32 = 0x5502;
+pub const UART_GET_ERRORS: u32 = 0x5503;
+pub const UART_SET_OVERFLOW: u32 = 0x5504;
+pub const UART_GET_RX_STATS: u32 = 0x5505;
+
+pub const UART_PARITY_NONE: u32 = 0;
+pub const UART_PARITY_ODD: u32 = 1;
+pub const UART_PARITY_EVEN: u32 = 2;
+
+// FIFO interrupt levels, from UART_FIFO_1_8 (0) to UART_FIFO_7_8 (4)
+pub const UART_FIFO_7_8: u32 = 4;
+
+// Line settings, must match uart_config_t in libc.h
+#[repr(C)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct UartConfig {
+    pub ibrd: u32,              // Baud rate divisor = UARTCLK / (16 * baud) = ibrd + fbrd / 64
+    pub fbrd: u32,
+    pub word_length: u32,       // 5 to 8 bits
+    pub parity: u32,
+    pub stop_bits: u32,         // 1 or 2
+    pub fifo: u32,              // Non zero to enable the FIFOs
+    pub rx_level: u32,
+    pub tx_level: u32,
+}
+
+impl UartConfig {
+
+    fn valid(&self) -> bool {
+        self.ibrd >= 1 && self.ibrd <= 0xFFFF && self.fbrd <= 0x3F
+            && self.word_length >= 5 && self.word_length <= 8
+            && self.parity <= UART_PARITY_EVEN
+            && (self.stop_bits == 1 || self.stop_bits == 2)
+            && self.rx_level <= UART_FIFO_7_8 && self.tx_level <= UART_FIFO_7_8
+    }
+
+    fn lcrh(&self) -> u32 {
+        let mut lcrh = (self.word_length - 5) << 5;
+        if self.parity != UART_PARITY_NONE { lcrh |= LCRH_PEN }
+        if self.parity == UART_PARITY_EVEN { lcrh |= LCRH_EPS }
+        if self.stop_bits == 2 { lcrh |= LCRH_STP2 }
+        if self.fifo != 0 { lcrh |= LCRH_FEN }
+        lcrh
+    }
+
+}
+
+// Receive errors, must match uart_errors_t in libc.h
+#[repr(C)]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct UartErrors {
+    pub overrun: u32,
+    pub framing: u32,
+    pub parity: u32,
+    pub breaks: u32,
+}
+
+impl UartErrors {
+
+    // Count the error bits of a received DR value, returns false if the byte is not valid data
+    fn record(&mut self, data: u32) -> bool {
+        if data & DR_OE != 0 { self.overrun = self.overrun.wrapping_add(1) }
+        if data & DR_FE != 0 { self.framing = self.framing.wrapping_add(1) }
+        if data & DR_PE != 0 { self.parity = self.parity.wrapping_add(1) }
+        if data & DR_BE != 0 { self.breaks = self.breaks.wrapping_add(1) }
+        // An overrun loses the following byte, not this one
+        data & (DR_FE | DR_PE | DR_BE) == 0
+    }
+
+}
+
+// Receive buffer statistics, must match uart_rx_stats_t in libc.h
+#[repr(C)]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct UartRxStats {
+    pub received: u32,
+    pub dropped: u32,           // Lost because the input buffer was full
+    pub throttled: u32,         // Times reception was paused for flow control
+    pub buffered: u32,
+    pub overflow: u32,          // The current overflow policy
+}
+
+// A fixed size ring buffer of bytes waiting to be transmitted
+#[derive(Debug)]
+pub struct TxRing {
+    data: [u8; TX_BUFFER],
+    head: usize,
+    len: usize,
+}
+
+impl TxRing {
+
+    pub const fn new() -> Self {
+        TxRing { data: [0; TX_BUFFER], head: 0, len: 0 }
+    }
+
+    pub fn len(&self) -> usize { self.len }
+
+    pub fn is_empty(&self) -> bool { self.len == 0 }
+
+    pub fn free(&self) -> usize { TX_BUFFER - self.len }
+
+    // Returns false if the ring is full
+    pub fn push(&mut self, byte: u8) -> bool {
+        if self.len == TX_BUFFER { return false }
+        self.data[(self.head + self.len) % TX_BUFFER] = byte;
+        self.len = self.len + 1;
+        true
+    }
+
+    pub fn pop(&mut self) -> Option<u8> {
+        if self.len == 0 { return None }
+        let byte = self.data[self.head];
+        self.head = (self.head + 1) % TX_BUFFER;
+        self.len = self.len - 1;
+        Some(byte)
+    }
+
+}
+
+// The transmit buffers are shared by everything writing to a UART, including the kernel's own messages
+static mut TX_RINGS: [TxRing; 2] = [TxRing::new(), TxRing::new()];
+
+// The registers of a UART, and the buffer of bytes waiting to be written to them
 #[derive(Clone, Debug)]
-pub struct PL011(*mut PL011_t);
+pub struct PL011(*mut PL011_t, *mut TxRing);
 
 pub fn UART0() -> PL011 {
-    unsafe { PL011(bindings::UART0) }
+    unsafe { PL011(bindings::UART0, &mut TX_RINGS[0]) }
 }
 
 pub fn UART1() -> PL011 {
-    unsafe { PL011(bindings::UART1) }
+    unsafe { PL011(bindings::UART1, &mut TX_RINGS[1]) }
 }
 
 impl PL011 {
     fn putc(&self, byte: u8, blocking: bool) {
         unsafe { PL011_putc(self.0, byte, blocking) };
     }
-    fn getc(&self, blocking: bool) -> u8 {
-        unsafe { PL011_getc(self.0, blocking) }
+    // Read the data register including its error bits, if a byte has been received
+    fn receive(&self) -> Option<u32> {
+        unsafe {
+            if (*self.0).FR & FR_RXFE != 0 { return None }
+            Some((*self.0).DR)
+        }
+    }
+    fn ring(&self) -> &mut TxRing {
+        unsafe { &mut *self.1 }
+    }
+    // Enable the UART for Tx+Rx, raising an interrupt when the Rx FIFO reaches its level,
+    // or when bytes below the level have been waiting too long
+    pub fn enable_rx_interrupt(&self) {
+        unsafe {
+            (*self.0).LCR  |= LCRH_FEN;
+            (*self.0).IMSC |= INT_RX | INT_RT;
+            (*self.0).CR    = 0x00000301;
+        }
+    }
+    // Leaving bytes in the FIFO without the Rx interrupts enabled lets it fill, pausing the sender
+    pub fn set_rx_interrupt(&self, enabled: bool) {
+        unsafe {
+            if enabled { (*self.0).IMSC |= INT_RX | INT_RT } else { (*self.0).IMSC &= !(INT_RX | INT_RT) }
+        }
+    }
+    pub fn set_flow_control(&self, enabled: bool) {
+        unsafe {
+            if enabled { (*self.0).CR |= CR_RTSEN | CR_CTSEN } else { (*self.0).CR &= !(CR_RTSEN | CR_CTSEN) }
+        }
+    }
+    pub fn config(&self) -> UartConfig {
+        unsafe {
+            let lcrh = (*self.0).LCR;
+            let ifls = (*self.0).IFLS;
+            UartConfig {
+                ibrd: (*self.0).IBRD,
+                fbrd: (*self.0).FBRD,
+                word_length: ((lcrh >> 5) & 0x3) + 5,
+                parity: if lcrh & LCRH_PEN == 0 { UART_PARITY_NONE }
+                        else if lcrh & LCRH_EPS == 0 { UART_PARITY_ODD } else { UART_PARITY_EVEN },
+                stop_bits: if lcrh & LCRH_STP2 == 0 { 1 } else { 2 },
+                fifo: if lcrh & LCRH_FEN == 0 { 0 } else { 1 },
+                rx_level: (ifls >> 3) & 0x7,
+                tx_level: ifls & 0x7,
+            }
+        }
+    }
+    // Follows the reprogramming sequence from the PL011 TRM, anything in the FIFOs is lost
+    pub fn configure(&self, config: &UartConfig) -> Result<(), FileError> {
+        if !config.valid() { return Err(FileError::InvalidArgument) }
+        unsafe {
+            let cr = (*self.0).CR;
+            (*self.0).CR = cr & !CR_UARTEN;
+            while (*self.0).FR & FR_BUSY != 0 {}
+            (*self.0).LCR &= !LCRH_FEN;
+            (*self.0).IBRD = config.ibrd;
+            (*self.0).FBRD = config.fbrd;
+            (*self.0).LCR  = config.lcrh();        // Must be written after the divisors to latch them
+            (*self.0).IFLS = (config.rx_level << 3) | config.tx_level;
+            (*self.0).CR   = cr;
+        }
+        Ok(())
+    }
+    pub fn rx_pending(&self) -> bool {
+        unsafe { (*self.0).MIS & (INT_RX | INT_RT) != 0 }
+    }
+    pub fn tx_pending(&self) -> bool {
+        unsafe { (*self.0).MIS & INT_TX != 0 }
+    }
+    // Add as much of data to the transmit buffer as will fit, returning the number of bytes taken
+    pub fn queue(&self, data: &[u8]) -> usize {
+        let ring = self.ring();
+        let count = data.iter().take_while(|b| ring.push(**b)).count();
+        self.start_tx();
+        count
+    }
+    // Move bytes from the buffer into the FIFO, the Tx interrupt is only enabled while bytes are waiting
+    pub fn start_tx(&self) {
+        let ring = self.ring();
+        unsafe {
+            while !ring.is_empty() && (*self.0).FR & FR_TXFF == 0 {
+                (*self.0).DR = ring.pop().unwrap() as u32;
+            }
+            if ring.is_empty() {
+                (*self.0).IMSC &= !INT_TX;
+                (*self.0).ICR   = INT_TX;
+            } else {
+                (*self.0).IMSC |= INT_TX;
+            }
+        }
+    }
+    // Busy wait until everything buffered has been sent, for when interrupts cannot be relied on
+    pub fn flush(&self) {
+        let ring = self.ring();
+        while let Some(b) = ring.pop() {
+            self.putc(b, true);
+        }
     }
 }
 
+// Used by the kernel, which cannot block, so if the buffer is full it waits for space instead
 impl Write for PL011 {
 
     fn write_str(&mut self, s: &str) -> Result<(), Error> {
-        s.as_bytes().iter().for_each(|b| {
-            self.putc(*b, true);
-        });
+        let mut bytes = s.as_bytes();
+        while !bytes.is_empty() {
+            let queued = self.queue(bytes);
+            bytes = &bytes[queued..];
+            if !bytes.is_empty() {
+                self.ring().pop().map(|b| self.putc(b, true));
+            }
+        }
         Ok(())
     }
 }
@@ -45,7 +294,11 @@ pub struct PL011FileDescriptor {
     base: FileDescriptorBase,
     read: bool,
     write: bool,
-    read_buffer: VecDeque<u8>,
+    tty: LineDiscipline,
+    errors: UartErrors,
+    received: u32,
+    throttled: u32,
+    throttling: bool,           // Rx interrupts are disabled until a reader makes room
 }
 
 impl PL011FileDescriptor {
@@ -57,19 +310,60 @@ impl PL011FileDescriptor {
             base: Default::default(),
             read,
             write,
-            read_buffer: Default::default()
+            tty: LineDiscipline::new(KEYBOARD_BUFFER),
+            errors: Default::default(),
+            received: 0,
+            throttled: 0,
+            throttling: false,
+        }
+    }
+
+    pub fn internal(&self) -> &PL011 {
+        &self.internal
+    }
+
+    // Drain the FIFO through the line discipline, then notify any blocked readers
+    // Returns the signals to deliver if control characters were typed
+    pub fn on_rx_interrupt(&mut self) -> Vec<TtySignal> {
+        let mut signals = Vec::new();
+        let mut echo = Vec::new();
+        loop {
+            if self.tty.overflow() == OverflowPolicy::FlowControl && self.tty.should_throttle() {
+                self.internal.set_rx_interrupt(false);
+                self.throttling = true;
+                self.throttled = self.throttled.wrapping_add(1);
+                break
+            }
+            let data = match self.internal.receive() {
+                Some(data) => data,
+                None => break,
+            };
+            self.received = self.received.wrapping_add(1);
+            // Bytes received with an error are counted then discarded
+            if !self.errors.record(data) { continue }
+            self.tty.receive(data as u8, &mut echo).map(|s| signals.push(s));
         }
+        self.internal.queue(&echo);     // Echo is dropped if the output is backed up
+        self.notify_pending_readers();
+        signals
     }
 
-    // Add chars to the input buffer, then notify any blocked readers
-    pub fn on_interrupt(&mut self) {
-        let char = self.internal.getc(true);
-        if self.read_buffer.len() < KEYBOARD_BUFFER {
-            self.read_buffer.push_back(char);
-            self.notify_pending_readers();
+    pub fn rx_stats(&self) -> UartRxStats {
+        UartRxStats {
+            received: self.received,
+            dropped: self.tty.dropped(),
+            throttled: self.throttled,
+            buffered: self.tty.buffered() as u32,
+            overflow: self.tty.overflow() as u32,
         }
     }
 
+    // Refill the FIFO, then let any blocked writers add to the buffer
+    pub fn on_tx_interrupt(&mut self) {
+        self.internal.start_tx();
+        self.notify_pending_writers();
+    }
+
 }
 
 impl FileDescriptor for PL011FileDescriptor {
@@ -79,24 +373,121 @@ impl FileDescriptor for PL011FileDescriptor {
     // This will return blocked until input is available
     fn read(&mut self, buffer: &mut [u8]) -> Result<IOResult, FileError> {
         if !self.read { return Err(FileError::UnsupportedOperation) }
-        let mut idx = 0;
-        while idx < buffer.len() {
-            if self.read_buffer.is_empty() {
-                return Ok(IOResult{ bytes: idx, blocked: true })
-            } else {
-                buffer[idx] = self.read_buffer.pop_front().unwrap();
-                idx = idx + 1;
-            }
-        };
-        Ok(IOResult{ bytes: idx, blocked: false })
+        let result = self.tty.read(buffer);
+        if self.throttling && !self.tty.should_throttle() {
+            self.throttling = false;
+            self.internal.set_rx_interrupt(true);
+        }
+        Ok(result)
     }
 
+    // This will return blocked until the rest of the data fits in the transmit buffer
     fn write(&mut self, data: &[u8]) -> Result<IOResult, FileError> {
         if !self.write { return Err(FileError::UnsupportedOperation) }
-        data.iter().for_each(|b| {
-            self.internal.putc(*b, true);
-        });
-        Ok(IOResult{ bytes: data.len(), blocked: false })
+        let bytes = self.internal.queue(data);
+        Ok(IOResult{ bytes, blocked: bytes < data.len() })
+    }
+
+    fn ioctl(&mut self, request: u32, arg: *mut u8) -> Result<u32, FileError> {
+        match request {
+            UART_GET_CONFIG | UART_SET_CONFIG | UART_GET_ERRORS | UART_SET_OVERFLOW | UART_GET_RX_STATS => {
+                if arg.is_null() { return Err(FileError::InvalidArgument) }
+            }
+            _ => return self.tty.ioctl(request, arg)
+        }
+        match request {
+            UART_GET_CONFIG => unsafe { *(arg as *mut UartConfig) = self.internal.config() },
+            UART_SET_CONFIG => self.internal.configure(unsafe { &*(arg as *const UartConfig) })?,
+            UART_GET_ERRORS => unsafe { *(arg as *mut UartErrors) = self.errors.clone() },
+            UART_SET_OVERFLOW => {
+                let policy = OverflowPolicy::from_u32(unsafe { *(arg as *const u32) }).ok_or(FileError::InvalidArgument)?;
+                self.internal.set_flow_control(policy == OverflowPolicy::FlowControl);
+                self.tty.set_overflow(policy);
+            }
+            _ => unsafe { *(arg as *mut UartRxStats) = self.rx_stats() },
+        }
+        Ok(0)
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::io::PL011::{TxRing, TX_BUFFER, PL011, PL011FileDescriptor, UartConfig, UartErrors, UartRxStats,
+                           UART_PARITY_EVEN, UART_SET_OVERFLOW, UART_GET_RX_STATS, KEYBOARD_BUFFER, DR_OE, DR_PE, DR_BE, INT_RX, INT_RT, CR_RTSEN};
+    use crate::io::tty::TTY_SET_FLAGS;
+    use crate::io::descriptor::FileDescriptor;
+    use crate::bindings::PL011_t;
+
+    #[test]
+    fn ring_test() {
+        let mut ring = TxRing::new();
+        assert_eq!(ring.pop(), None);
+        (0..TX_BUFFER).for_each(|i| assert!(ring.push(i as u8)));
+        assert!(!ring.push(0));
+        assert_eq!(ring.free(), 0);
+        // Wrap around the end of the array
+        assert_eq!(ring.pop(), Some(0));
+        assert_eq!(ring.pop(), Some(1));
+        assert!(ring.push(0xAA));
+        (2..TX_BUFFER).for_each(|i| assert_eq!(ring.pop(), Some(i as u8)));
+        assert_eq!(ring.pop(), Some(0xAA));
+        assert!(ring.is_empty());
+    }
+
+    #[test]
+    fn configure_test() {
+        let mut registers: PL011_t = unsafe { core::mem::zeroed() };
+        registers.CR = 0x301;
+        let mut ring = TxRing::new();
+        let uart = PL011(&mut registers, &mut ring);
+        let config = UartConfig {
+            ibrd: 13, fbrd: 1, word_length: 7, parity: UART_PARITY_EVEN,
+            stop_bits: 2, fifo: 1, rx_level: 1, tx_level: 2
+        };
+        uart.configure(&config).ok().unwrap();
+        assert_eq!((registers.IBRD, registers.FBRD, registers.LCR, registers.IFLS), (13, 1, 0x5E, 0x0A));
+        assert_eq!(registers.CR, 0x301);
+        assert_eq!(uart.config(), config);
+        assert!(uart.configure(&UartConfig { word_length: 9, ..config }).is_err());
+    }
+
+    #[test]
+    fn errors_test() {
+        let mut errors = UartErrors::default();
+        assert!(errors.record(b'a' as u32));
+        assert!(errors.record(DR_OE | b'b' as u32));
+        assert!(!errors.record(DR_PE));
+        assert_eq!(errors, UartErrors { overrun: 1, framing: 0, parity: 1, breaks: 0 });
+        // A noisy line can't overflow the counters
+        errors.breaks = u32::MAX;
+        assert!(!errors.record(DR_BE));
+        assert_eq!(errors.breaks, 0);
     }
 
+    #[test]
+    fn flow_control_test() {
+        let mut registers: PL011_t = unsafe { core::mem::zeroed() };
+        registers.DR = b'a' as u32;     // With FR zeroed the FIFO never runs dry
+        let mut ring = TxRing::new();
+        let mut uart = PL011FileDescriptor::new(PL011(&mut registers, &mut ring), true, true);
+        uart.internal().set_rx_interrupt(true);
+        let mut value = 0u32;
+        uart.ioctl(TTY_SET_FLAGS, &mut value as *mut _ as *mut u8).ok().unwrap();
+        value = 2;
+        uart.ioctl(UART_SET_OVERFLOW, &mut value as *mut _ as *mut u8).ok().unwrap();
+        assert_ne!(registers.CR & CR_RTSEN, 0);
+
+        // Reception stops once the buffer is full, rather than dropping input
+        assert!(uart.on_rx_interrupt().is_empty());
+        assert_eq!(registers.IMSC & (INT_RX | INT_RT), 0);
+        let mut stats = UartRxStats::default();
+        uart.ioctl(UART_GET_RX_STATS, &mut stats as *mut _ as *mut u8).ok().unwrap();
+        assert_eq!(stats, UartRxStats { received: KEYBOARD_BUFFER as u32, dropped: 0, throttled: 1, buffered: KEYBOARD_BUFFER as u32, overflow: 2 });
+
+        // Reading makes room, so reception resumes
+        let mut buffer = [0u8; 16];
+        assert_eq!(uart.read(&mut buffer).ok().unwrap().bytes, 16);
+        assert_eq!(registers.IMSC & (INT_RX | INT_RT), INT_RX | INT_RT);
+    }
 }
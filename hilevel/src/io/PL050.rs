@@ -0,0 +1,210 @@
+#![allow(non_snake_case)]
+
+use crate::bindings;
+use crate::bindings::{PL050_t, PL050_putc};
+use crate::io::descriptor::{FileDescriptor, FileDescriptorBase, IOResult, FileError, read_from_buffer};
+use crate::io::scancode::{ScancodeDecoder, KeyEvent};
+use alloc::collections::VecDeque;
+
+const INPUT_BUFFER: usize = 4096;
+
+const CR_ENABLE: u32 = 0x04;
+const CR_RX_INTERRUPT: u32 = 0x10;
+const STAT_RX_FULL: u32 = 0x10;
+
+const MOUSE_ENABLE_REPORTING: u8 = 0xF4;
+const MOUSE_ACK: u8 = 0xFA;
+const MOUSE_PACKET_BYTES: usize = 3;
+const MOUSE_PACKET_SYNC: u8 = 0x08;     // Bit 3 of the first byte in a packet is always set
+
+#[derive(Clone, Debug)]
+pub struct PL050(*mut PL050_t);
+
+pub fn PS20() -> PL050 {
+    unsafe { PL050(bindings::PS20) }
+}
+
+pub fn PS21() -> PL050 {
+    unsafe { PL050(bindings::PS21) }
+}
+
+impl PL050 {
+    fn putc(&self, byte: u8) {
+        unsafe { PL050_putc(self.0, byte) };
+    }
+    // Non blocking read from the data register
+    fn getc(&self) -> Option<u8> {
+        unsafe {
+            if (*self.0).STAT & STAT_RX_FULL != 0 { Some((*self.0).DATA as u8) } else { None }
+        }
+    }
+    // Enable the controller, raising an interrupt on Rx
+    pub fn enable_rx_interrupt(&self) {
+        unsafe { (*self.0).CR = CR_ENABLE | CR_RX_INTERRUPT; }
+    }
+}
+
+// The keyboard is read as a stream of ASCII characters, non-character keys are dropped
+#[derive(Debug)]
+pub struct KeyboardFileDescriptor {
+    internal: PL050,
+    base: FileDescriptorBase,
+    decoder: ScancodeDecoder,
+    read_buffer: VecDeque<u8>,
+}
+
+impl KeyboardFileDescriptor {
+
+    pub fn new(internal: PL050) -> Self {
+        KeyboardFileDescriptor {
+            internal,
+            base: Default::default(),
+            decoder: Default::default(),
+            read_buffer: Default::default(),
+        }
+    }
+
+    pub fn internal(&self) -> &PL050 {
+        &self.internal
+    }
+
+    // Decode any scancodes waiting in the controller, then notify any blocked readers
+    pub fn on_interrupt(&mut self) {
+        while let Some(byte) = self.internal.getc() {
+            self.on_scancode(byte);
+        }
+        self.notify_pending_readers();
+    }
+
+    fn on_scancode(&mut self, byte: u8) -> Option<KeyEvent> {
+        let event = self.decoder.push(byte);
+        event.and_then(|e| e.ascii).map(|c| {
+            if self.read_buffer.len() < INPUT_BUFFER {
+                self.read_buffer.push_back(c);
+            }
+        });
+        event
+    }
+
+}
+
+impl FileDescriptor for KeyboardFileDescriptor {
+
+    fn base(&mut self) -> &mut FileDescriptorBase {&mut self.base}
+
+    // This will return blocked until input is available
+    fn read(&mut self, buffer: &mut [u8]) -> Result<IOResult, FileError> {
+        Ok(read_from_buffer(&mut self.read_buffer, buffer))
+    }
+
+}
+
+// The mouse is read as a stream of raw 3 byte PS/2 packets, which are kept aligned
+#[derive(Debug)]
+pub struct MouseFileDescriptor {
+    internal: PL050,
+    base: FileDescriptorBase,
+    awaiting_ack: bool,
+    packet: [u8; MOUSE_PACKET_BYTES],
+    packet_len: usize,
+    read_buffer: VecDeque<u8>,
+}
+
+impl MouseFileDescriptor {
+
+    pub fn new(internal: PL050) -> Self {
+        MouseFileDescriptor {
+            internal,
+            base: Default::default(),
+            awaiting_ack: false,
+            packet: [0; MOUSE_PACKET_BYTES],
+            packet_len: 0,
+            read_buffer: Default::default(),
+        }
+    }
+
+    pub fn internal(&self) -> &PL050 {
+        &self.internal
+    }
+
+    // The mouse won't send any movement packets until told to
+    pub fn enable_reporting(&mut self) {
+        self.awaiting_ack = true;
+        self.internal.putc(MOUSE_ENABLE_REPORTING);
+    }
+
+    // Assemble packets from the bytes waiting in the controller, then notify any blocked readers
+    pub fn on_interrupt(&mut self) {
+        while let Some(byte) = self.internal.getc() {
+            self.on_byte(byte);
+        }
+        self.notify_pending_readers();
+    }
+
+    fn on_byte(&mut self, byte: u8) {
+        if self.awaiting_ack && byte == MOUSE_ACK {
+            self.awaiting_ack = false;
+            return
+        }
+        // Drop bytes until we find the start of a packet
+        if self.packet_len == 0 && byte & MOUSE_PACKET_SYNC == 0 { return }
+        self.packet[self.packet_len] = byte;
+        self.packet_len = self.packet_len + 1;
+        if self.packet_len == MOUSE_PACKET_BYTES {
+            self.packet_len = 0;
+            // Only whole packets are buffered, so a reader can never become misaligned
+            if self.read_buffer.len() + MOUSE_PACKET_BYTES <= INPUT_BUFFER {
+                self.read_buffer.extend(self.packet.iter());
+            }
+        }
+    }
+
+}
+
+impl FileDescriptor for MouseFileDescriptor {
+
+    fn base(&mut self) -> &mut FileDescriptorBase {&mut self.base}
+
+    // This will return blocked until input is available
+    fn read(&mut self, buffer: &mut [u8]) -> Result<IOResult, FileError> {
+        Ok(read_from_buffer(&mut self.read_buffer, buffer))
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::io::PL050::{PL050, KeyboardFileDescriptor, MouseFileDescriptor};
+    use crate::io::descriptor::FileDescriptor;
+
+    #[test]
+    fn keyboard_read_test() {
+        let mut kbd = KeyboardFileDescriptor::new(PL050(core::ptr::null_mut()));
+        for b in &[0x33, 0xF0, 0x33, 0x43, 0xF0, 0x43, 0x12] {
+            kbd.on_scancode(*b);
+        }
+        let mut buffer = [0u8; 3];
+        let result = kbd.read(&mut buffer).ok().unwrap();
+        assert_eq!(result.bytes, 2);
+        assert!(result.blocked);
+        assert_eq!(&buffer[..2], b"hi");
+    }
+
+    #[test]
+    fn mouse_packet_test() {
+        let mut mouse = MouseFileDescriptor::new(PL050(core::ptr::null_mut()));
+        mouse.awaiting_ack = true;
+        // Ack, then a stray byte without the sync bit, then a whole packet and a partial packet
+        for b in &[0xFA, 0x01, 0x09, 0x05, 0xFE, 0x08, 0x00] {
+            mouse.on_byte(*b);
+        }
+        let mut buffer = [0u8; 6];
+        let result = mouse.read(&mut buffer).ok().unwrap();
+        assert_eq!(result.bytes, 3);
+        assert_eq!(&buffer[..3], &[0x09, 0x05, 0xFE]);
+        mouse.on_byte(0x01);
+        let result = mouse.read(&mut buffer).ok().unwrap();
+        assert_eq!(result.bytes, 3);
+        assert_eq!(&buffer[..3], &[0x08, 0x00, 0x01]);
+    }
+}
@@ -0,0 +1,42 @@
+#![allow(non_snake_case)]
+
+use crate::bindings;
+use crate::bindings::PL111_t;
+use crate::io::framebuffer::Framebuffer;
+
+pub const LCD_WIDTH: usize = 800;
+pub const LCD_HEIGHT: usize = 600;
+
+#[derive(Clone, Debug)]
+pub struct PL111(*mut PL111_t);
+
+pub fn LCD() -> PL111 {
+    unsafe { PL111(bindings::LCD) }
+}
+
+impl PL111 {
+
+    // Configure the controller for 800x600 SVGA at 16BPP, scanning out of the framebuffer memory
+    // The framebuffer must not be moved or dropped while the LCD is enabled
+    pub fn enable(&self, framebuffer: &Framebuffer) {
+        assert_eq!(framebuffer.width(), LCD_WIDTH);
+        assert_eq!(framebuffer.height(), LCD_HEIGHT);
+        let base = framebuffer.pixels().as_ptr() as u32;
+        assert_eq!(base % 8, 0, "LCD base address must be doubleword aligned");
+        unsafe {
+            (*bindings::SYSCONF).CLCD = 0x00002CAC;      // per Table 4.6 of the RealView PB-A8 manual
+
+            (*self.0).LCDTiming0 = 0x1313A4C4;          // per Table 4.5, 800x600 SVGA
+            (*self.0).LCDTiming1 = 0x0505F657;
+            (*self.0).LCDTiming2 = 0x071F1800;
+
+            (*self.0).LCDUPBASE  = base;
+
+            (*self.0).LCDControl  = 0x00000020;          // select TFT   display type
+            (*self.0).LCDControl |= 0x00000008;          // select 16BPP display mode
+            (*self.0).LCDControl |= 0x00000800;          // power-on LCD controller
+            (*self.0).LCDControl |= 0x00000001;          // enable   LCD controller
+        }
+    }
+
+}
@@ -1,11 +1,15 @@
-use alloc::rc::Rc;
+use alloc::rc::{Rc, Weak};
+use alloc::vec::Vec;
 use core::cell::RefCell;
 use alloc::collections::VecDeque;
 use crate::io::tasks::{ReadTask, WriteTask};
+use crate::process::StrongPcbRef;
+use crate::io::socket::SocketDescriptor;
 use num::range;
 use core::fmt::Debug;
 
 pub type StrongFileDescriptorRef = Rc<RefCell<dyn FileDescriptor>>;
+pub type WeakFileDescriptorRef = Weak<RefCell<dyn FileDescriptor>>;
 
 pub struct IOResult {
     pub bytes: usize,
@@ -14,13 +18,46 @@ pub struct IOResult {
 
 #[derive(Default, Debug)]
 pub struct FileDescriptorBase {
-    pending_reads: VecDeque<ReadTask>,
+    pub(super) pending_reads: VecDeque<ReadTask>,
     pending_writes: VecDeque<WriteTask>
 }
 
+impl FileDescriptorBase {
+
+    // The processes waiting for a read or write to complete
+    pub fn blocked_processes(&self) -> Vec<StrongPcbRef> {
+        let reads = self.pending_reads.iter().filter_map(|x| x.process());
+        reads.chain(self.pending_writes.iter().filter_map(|x| x.process())).collect()
+    }
+
+    // Forget a process's waiting reads and writes, which are then never completed
+    pub fn cancel(&mut self, process: &StrongPcbRef) {
+        self.pending_reads.retain(|x| !x.is_for(process));
+        self.pending_writes.retain(|x| !x.is_for(process));
+    }
+
+}
+
+#[derive(Debug)]
 pub enum FileError {
     InvalidDescriptor,
     UnsupportedOperation,
+    InvalidArgument,
+    WouldBlock,             // A non-blocking file isn't ready
+}
+
+// Move bytes from an input buffer, returning blocked if the buffer runs out before the output is filled
+pub fn read_from_buffer(source: &mut VecDeque<u8>, buffer: &mut [u8]) -> IOResult {
+    let mut idx = 0;
+    while idx < buffer.len() {
+        if source.is_empty() {
+            return IOResult{ bytes: idx, blocked: true }
+        } else {
+            buffer[idx] = source.pop_front().unwrap();
+            idx = idx + 1;
+        }
+    };
+    IOResult{ bytes: idx, blocked: false }
 }
 
 // An "abstract class" for different types of files, accessed through the read/write API
@@ -62,4 +99,44 @@ pub trait FileDescriptor: Debug {
         Err(FileError::UnsupportedOperation)
     }
 
+    // Device specific requests, arg points to memory in the calling process
+    #[allow(unused_variables)]
+    fn ioctl(&mut self, request: u32, arg: *mut u8) -> Result<u32, FileError> {
+        Err(FileError::UnsupportedOperation)
+    }
+
+    // Give the calling process direct access to the memory behind the file
+    fn mmap(&mut self) -> Result<*mut u8, FileError> {
+        Err(FileError::UnsupportedOperation)
+    }
+
+    // Message based files send and receive whole messages with a priority. Like read and write these
+    // return the result, or None if the task's process has been blocked until the file is ready
+    #[allow(unused_variables)]
+    fn send(&mut self, task: WriteTask, priority: u32) -> Result<Option<u32>, FileError> {
+        Err(FileError::UnsupportedOperation)
+    }
+
+    // The priority of the message is stored at priority, unless it is null
+    #[allow(unused_variables)]
+    fn receive(&mut self, task: ReadTask, priority: *mut u32) -> Result<Option<u32>, FileError> {
+        Err(FileError::UnsupportedOperation)
+    }
+
+    // Processes blocked on a pipe end can only be unblocked by a process using the other end, which is
+    // returned with them for deadlock detection. Other files don't know what their processes wait for
+    fn blocked_on(&mut self) -> Option<(Vec<StrongPcbRef>, WeakFileDescriptorRef)> {
+        None
+    }
+
+    // Give up on the reads and writes a process is blocked on
+    fn cancel_blocked(&mut self, process: &StrongPcbRef) {
+        self.base().cancel(process)
+    }
+
+    // Sockets, for the socket specific system calls
+    fn socket(&mut self) -> Option<&mut SocketDescriptor> {
+        None
+    }
+
 }
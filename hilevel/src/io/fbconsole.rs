@@ -0,0 +1,178 @@
+use crate::io::framebuffer::{Framebuffer, Pixel, WHITE, BLACK};
+use crate::io::font::{glyph, GLYPH_WIDTH, GLYPH_HEIGHT};
+use crate::io::descriptor::{FileDescriptor, FileDescriptorBase, IOResult, FileError};
+use core::fmt::{Write, Error};
+
+const TAB_WIDTH: usize = 8;
+
+// A text terminal rendered into a framebuffer, which scrolls when the bottom line is full
+#[derive(Debug)]
+pub struct FramebufferConsole {
+    framebuffer: Framebuffer,
+    base: FileDescriptorBase,
+    column: usize,
+    row: usize,
+    foreground: Pixel,
+    background: Pixel,
+}
+
+impl FramebufferConsole {
+
+    pub fn new(framebuffer: Framebuffer) -> Self {
+        FramebufferConsole {
+            framebuffer,
+            base: Default::default(),
+            column: 0,
+            row: 0,
+            foreground: WHITE,
+            background: BLACK,
+        }
+    }
+
+    pub fn framebuffer(&self) -> &Framebuffer {
+        &self.framebuffer
+    }
+
+    pub fn framebuffer_mut(&mut self) -> &mut Framebuffer {
+        &mut self.framebuffer
+    }
+
+    pub fn columns(&self) -> usize {
+        self.framebuffer.width() / GLYPH_WIDTH
+    }
+
+    pub fn rows(&self) -> usize {
+        self.framebuffer.height() / GLYPH_HEIGHT
+    }
+
+    pub fn cursor(&self) -> (usize, usize) {
+        (self.column, self.row)
+    }
+
+    pub fn set_colours(&mut self, foreground: Pixel, background: Pixel) {
+        self.foreground = foreground;
+        self.background = background;
+    }
+
+    pub fn clear(&mut self) {
+        let (width, height) = (self.framebuffer.width(), self.framebuffer.height());
+        self.framebuffer.fill_rect(0, 0, width, height, self.background);
+        self.column = 0;
+        self.row = 0;
+    }
+
+    pub fn put_byte(&mut self, byte: u8) {
+        match byte {
+            b'\n' => self.new_line(),
+            b'\r' => self.column = 0,
+            b'\t' => {
+                let next = (self.column / TAB_WIDTH + 1) * TAB_WIDTH;
+                while self.column < next.min(self.columns()) { self.put_byte(b' ') }
+            }
+            0x08 => {
+                // Backspace moves back and erases, but not beyond the start of the line
+                if self.column > 0 {
+                    self.column = self.column - 1;
+                    self.draw(b' ');
+                }
+            }
+            _ => {
+                if self.column >= self.columns() { self.new_line() }
+                self.draw(byte);
+                self.column = self.column + 1;
+            }
+        }
+    }
+
+    fn new_line(&mut self) {
+        self.column = 0;
+        if self.row + 1 < self.rows() {
+            self.row = self.row + 1;
+        } else {
+            self.framebuffer.scroll_up(GLYPH_HEIGHT, self.background);
+        }
+    }
+
+    fn draw(&mut self, c: u8) {
+        let x0 = self.column * GLYPH_WIDTH;
+        let y0 = self.row * GLYPH_HEIGHT;
+        for (y, bits) in glyph(c).iter().enumerate() {
+            for x in 0..GLYPH_WIDTH {
+                let pixel = if bits & (1 << x) != 0 { self.foreground } else { self.background };
+                self.framebuffer.set(x0 + x, y0 + y, pixel);
+            }
+        }
+    }
+
+}
+
+impl FileDescriptor for FramebufferConsole {
+
+    fn base(&mut self) -> &mut FileDescriptorBase {&mut self.base}
+
+    fn write(&mut self, data: &[u8]) -> Result<IOResult, FileError> {
+        data.iter().for_each(|b| self.put_byte(*b));
+        Ok(IOResult{ bytes: data.len(), blocked: false })
+    }
+
+}
+
+impl Write for FramebufferConsole {
+
+    fn write_str(&mut self, s: &str) -> Result<(), Error> {
+        s.as_bytes().iter().for_each(|b| self.put_byte(*b));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::io::fbconsole::FramebufferConsole;
+    use crate::io::framebuffer::{Framebuffer, WHITE, BLACK};
+    use crate::io::font::glyph;
+    use crate::io::descriptor::FileDescriptor;
+
+    // Read back the 8x8 cell at a text position as glyph rows
+    fn cell(console: &FramebufferConsole, column: usize, row: usize) -> [u8; 8] {
+        let mut rows = [0u8; 8];
+        for y in 0..8 {
+            for x in 0..8 {
+                let pixel = console.framebuffer().get(column * 8 + x, row * 8 + y).unwrap();
+                if pixel == WHITE { rows[y] |= 1 << x }
+            }
+        }
+        rows
+    }
+
+    #[test]
+    fn render_test() {
+        let mut console = FramebufferConsole::new(Framebuffer::new(24, 16));
+        assert_eq!((console.columns(), console.rows()), (3, 2));
+        console.write(b"Hi\nA").ok().unwrap();
+        assert_eq!(&cell(&console, 0, 0), glyph(b'H'));
+        assert_eq!(&cell(&console, 1, 0), glyph(b'i'));
+        assert_eq!(&cell(&console, 0, 1), glyph(b'A'));
+        assert_eq!(console.cursor(), (1, 1));
+    }
+
+    #[test]
+    fn wrap_scroll_test() {
+        let mut console = FramebufferConsole::new(Framebuffer::new(16, 16));
+        console.write(b"abcde").ok().unwrap();
+        // "ab" then "cd" then wrapping to a third line scrolls "ab" off the top
+        assert_eq!(&cell(&console, 0, 0), glyph(b'c'));
+        assert_eq!(&cell(&console, 1, 0), glyph(b'd'));
+        assert_eq!(&cell(&console, 0, 1), glyph(b'e'));
+        assert_eq!(console.framebuffer().get(8, 8), Some(BLACK));
+    }
+
+    #[test]
+    fn backspace_test() {
+        let mut console = FramebufferConsole::new(Framebuffer::new(128, 8));
+        console.write(b"ab\x08").ok().unwrap();
+        assert_eq!(console.cursor(), (1, 0));
+        assert_eq!(&cell(&console, 1, 0), glyph(b' '));
+        console.write(b"\tc").ok().unwrap();
+        assert_eq!(console.cursor(), (9, 0));
+    }
+}
@@ -0,0 +1,98 @@
+use crate::io::fbconsole::FramebufferConsole;
+use crate::io::framebuffer::Pixel;
+use crate::io::descriptor::{FileDescriptor, FileDescriptorBase, FileError};
+use alloc::rc::Rc;
+use core::cell::RefCell;
+use core::mem::size_of;
+
+pub const FBIO_GET_INFO: u32 = 0x4600;
+
+pub const FB_FORMAT_RGB555: u32 = 0;
+
+// Must match fb_info_t in libc.h
+#[repr(C)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct FramebufferInfo {
+    pub width: u32,
+    pub height: u32,
+    pub bits_per_pixel: u32,
+    pub stride: u32,           // Bytes per row
+    pub format: u32,
+}
+
+// The /dev/fb0 device, user processes query it with ioctl then draw directly into the mapped pixels
+// It shares the LCD framebuffer with the text console, so they will draw over each other
+#[derive(Debug)]
+pub struct FramebufferDevice {
+    display: Rc<RefCell<FramebufferConsole>>,
+    base: FileDescriptorBase,
+}
+
+impl FramebufferDevice {
+
+    pub fn new(display: Rc<RefCell<FramebufferConsole>>) -> Self {
+        FramebufferDevice { display, base: Default::default() }
+    }
+
+    pub fn info(&self) -> FramebufferInfo {
+        let display = self.display.borrow();
+        let framebuffer = display.framebuffer();
+        FramebufferInfo {
+            width: framebuffer.width() as u32,
+            height: framebuffer.height() as u32,
+            bits_per_pixel: (size_of::<Pixel>() * 8) as u32,
+            stride: (framebuffer.width() * size_of::<Pixel>()) as u32,
+            format: FB_FORMAT_RGB555,
+        }
+    }
+
+}
+
+impl FileDescriptor for FramebufferDevice {
+
+    fn base(&mut self) -> &mut FileDescriptorBase {&mut self.base}
+
+    fn ioctl(&mut self, request: u32, arg: *mut u8) -> Result<u32, FileError> {
+        match request {
+            FBIO_GET_INFO => {
+                if arg.is_null() { return Err(FileError::UnsupportedOperation) }
+                unsafe { *(arg as *mut FramebufferInfo) = self.info() };
+                Ok(0)
+            }
+            _ => Err(FileError::UnsupportedOperation)
+        }
+    }
+
+    // Without an MMU the process can simply be given the address of the pixels
+    fn mmap(&mut self) -> Result<*mut u8, FileError> {
+        let mut display = self.display.borrow_mut();
+        Ok(display.framebuffer_mut().pixels_mut().as_mut_ptr() as *mut u8)
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::io::fbdev::{FramebufferDevice, FramebufferInfo, FBIO_GET_INFO, FB_FORMAT_RGB555};
+    use crate::io::fbconsole::FramebufferConsole;
+    use crate::io::framebuffer::Framebuffer;
+    use crate::io::descriptor::FileDescriptor;
+    use alloc::rc::Rc;
+    use core::cell::RefCell;
+
+    #[test]
+    fn ioctl_mmap_test() {
+        let display = Rc::new(RefCell::new(FramebufferConsole::new(Framebuffer::new(16, 8))));
+        let mut device = FramebufferDevice::new(Rc::clone(&display));
+        let mut info = FramebufferInfo { width: 0, height: 0, bits_per_pixel: 0, stride: 0, format: 99 };
+        let result = device.ioctl(FBIO_GET_INFO, &mut info as *mut _ as *mut u8);
+        assert_eq!(result.ok(), Some(0));
+        assert_eq!(info, FramebufferInfo { width: 16, height: 8, bits_per_pixel: 16, stride: 32, format: FB_FORMAT_RGB555 });
+        assert!(device.ioctl(0, &mut info as *mut _ as *mut u8).is_err());
+
+        // Drawing through the mapping is visible in the framebuffer
+        let pixels = device.mmap().ok().unwrap() as *mut u16;
+        unsafe { *pixels.offset(17) = 0x1234 };
+        assert_eq!(display.borrow().framebuffer().get(1, 1), Some(0x1234));
+    }
+}
@@ -0,0 +1,92 @@
+use alloc::vec::Vec;
+
+// 16 bits per pixel, 5 bits per channel with red in the low bits (the PL111 16BPP TFT format)
+pub type Pixel = u16;
+
+pub const BLACK: Pixel = 0x0000;
+pub const WHITE: Pixel = 0x7FFF;
+
+pub fn rgb(r: u8, g: u8, b: u8) -> Pixel {
+    ((r as u16 >> 3) << 0) | ((g as u16 >> 3) << 5) | ((b as u16 >> 3) << 10)
+}
+
+// A block of memory laid out row by row, which can either be scanned out by the LCD or inspected in tests
+#[derive(Debug)]
+pub struct Framebuffer {
+    pixels: Vec<Pixel>,
+    width: usize,
+    height: usize,
+}
+
+impl Framebuffer {
+
+    pub fn new(width: usize, height: usize) -> Self {
+        Framebuffer { pixels: vec![BLACK; width * height], width, height }
+    }
+
+    pub fn width(&self) -> usize { self.width }
+
+    pub fn height(&self) -> usize { self.height }
+
+    pub fn pixels(&self) -> &[Pixel] { &self.pixels }
+
+    pub fn pixels_mut(&mut self) -> &mut [Pixel] { &mut self.pixels }
+
+    // Pixels outside of the buffer are ignored
+    pub fn set(&mut self, x: usize, y: usize, pixel: Pixel) {
+        if x < self.width && y < self.height {
+            self.pixels[y * self.width + x] = pixel;
+        }
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Option<Pixel> {
+        if x < self.width && y < self.height { Some(self.pixels[y * self.width + x]) } else { None }
+    }
+
+    pub fn fill_rect(&mut self, x: usize, y: usize, width: usize, height: usize, pixel: Pixel) {
+        let x_end = (x + width).min(self.width);
+        let y_end = (y + height).min(self.height);
+        for row in y.min(y_end)..y_end {
+            let start = row * self.width;
+            for p in &mut self.pixels[start + x.min(x_end)..start + x_end] { *p = pixel; }
+        }
+    }
+
+    // Move everything up by a number of pixel rows, filling in the space left at the bottom
+    pub fn scroll_up(&mut self, rows: usize, fill: Pixel) {
+        let rows = rows.min(self.height);
+        let shift = rows * self.width;
+        let len = self.pixels.len();
+        self.pixels.copy_within(shift.., 0);
+        for p in &mut self.pixels[len - shift..] { *p = fill; }
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::io::framebuffer::{Framebuffer, rgb, WHITE};
+
+    #[test]
+    fn fill_rect_test() {
+        let mut fb = Framebuffer::new(4, 3);
+        fb.fill_rect(1, 1, 10, 10, WHITE);
+        assert_eq!(fb.pixels(), &[0, 0, 0, 0, 0, WHITE, WHITE, WHITE, 0, WHITE, WHITE, WHITE]);
+    }
+
+    #[test]
+    fn scroll_test() {
+        let mut fb = Framebuffer::new(2, 3);
+        fb.set(0, 1, 1);
+        fb.set(1, 2, 2);
+        fb.scroll_up(1, 7);
+        assert_eq!(fb.pixels(), &[1, 0, 0, 2, 7, 7]);
+    }
+
+    #[test]
+    fn rgb_test() {
+        assert_eq!(rgb(255, 255, 255), WHITE);
+        assert_eq!(rgb(255, 0, 0), 0x001F);
+        assert_eq!(rgb(0, 0, 255), 0x7C00);
+    }
+}
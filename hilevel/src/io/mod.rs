@@ -2,25 +2,57 @@
 #![allow(dead_code)]
 
 pub mod PL011;
+pub mod PL050;
+pub mod PL111;
+pub mod scancode;
+pub mod font;
+pub mod framebuffer;
+pub mod fbconsole;
+pub mod fbdev;
 pub mod tasks;
 pub mod descriptor;
 pub mod pipe;
+pub mod mqueue;
+pub mod socket;
+pub mod tty;
 
 use crate::process::FidTable;
 use alloc::rc::Rc;
 use crate::io::PL011::{UART0, UART1, PL011FileDescriptor};
 use core::cell::RefCell;
 use crate::io::descriptor::StrongFileDescriptorRef;
+use crate::interrupt::InterruptManager;
+use crate::process::ScheduleSource;
+use crate::io::PL050::{PS20, PS21, KeyboardFileDescriptor, MouseFileDescriptor};
+use crate::io::PL111::{LCD, LCD_WIDTH, LCD_HEIGHT};
+use crate::io::fbconsole::FramebufferConsole;
+use crate::io::framebuffer::Framebuffer;
+use crate::io::fbdev::FramebufferDevice;
+use crate::io::mqueue::MessageQueues;
+use crate::io::socket::Sockets;
+use crate::bindings::{GIC_SOURCE_UART0, GIC_SOURCE_UART1, GIC_SOURCE_PS20, GIC_SOURCE_PS21};
 
 pub const STDIN_FILENO: i32 = 0;
 pub const STDOUT_FILENO: i32 = 1;
 pub const STDERR_FILENO: i32 = 2;
 pub const UART1_FILENO: i32 = 3;
+pub const KEYBOARD_FILENO: i32 = 4;
+pub const MOUSE_FILENO: i32 = 5;
+pub const FRAMEBUFFER_FILENO: i32 = 6;
+
+const UART_PRIORITY: u8 = 0x80;
+const PS2_PRIORITY: u8 = 0x80;
 
 pub struct IoManager {
     pub uart0_ro: Rc<RefCell<PL011FileDescriptor>>,
     pub uart0_wo: Rc<RefCell<PL011FileDescriptor>>,
     pub uart1_rw: Rc<RefCell<PL011FileDescriptor>>,
+    pub keyboard: Rc<RefCell<KeyboardFileDescriptor>>,
+    pub mouse: Rc<RefCell<MouseFileDescriptor>>,
+    pub display: Rc<RefCell<FramebufferConsole>>,
+    pub framebuffer: Rc<RefCell<FramebufferDevice>>,
+    pub message_queues: MessageQueues,
+    pub sockets: Sockets,
 }
 
 impl IoManager {
@@ -29,23 +61,86 @@ impl IoManager {
         let mut table = FidTable::default();
         #[cfg(not(test))]
             {
-                table.insert(STDIN_FILENO, Rc::clone(&self.uart0_ro) as StrongFileDescriptorRef);
-                table.insert(STDOUT_FILENO, Rc::clone(&self.uart0_wo) as StrongFileDescriptorRef);
-                table.insert(STDERR_FILENO, Rc::clone(&self.uart0_wo) as StrongFileDescriptorRef);
+                #[cfg(not(feature = "display_console"))]
+                {
+                    table.insert(STDIN_FILENO, Rc::clone(&self.uart0_ro) as StrongFileDescriptorRef);
+                    table.insert(STDOUT_FILENO, Rc::clone(&self.uart0_wo) as StrongFileDescriptorRef);
+                    table.insert(STDERR_FILENO, Rc::clone(&self.uart0_wo) as StrongFileDescriptorRef);
+                }
+                // Use the keyboard and LCD in place of UART0
+                #[cfg(feature = "display_console")]
+                {
+                    table.insert(STDIN_FILENO, Rc::clone(&self.keyboard) as StrongFileDescriptorRef);
+                    table.insert(STDOUT_FILENO, Rc::clone(&self.display) as StrongFileDescriptorRef);
+                    table.insert(STDERR_FILENO, Rc::clone(&self.display) as StrongFileDescriptorRef);
+                }
                 table.insert(UART1_FILENO, Rc::clone(&self.uart1_rw) as StrongFileDescriptorRef);
+                table.insert(KEYBOARD_FILENO, Rc::clone(&self.keyboard) as StrongFileDescriptorRef);
+                table.insert(MOUSE_FILENO, Rc::clone(&self.mouse) as StrongFileDescriptorRef);
+                table.insert(FRAMEBUFFER_FILENO, Rc::clone(&self.framebuffer) as StrongFileDescriptorRef);
             }
         table
     }
 
+    // Enable the devices, and register the interrupt handlers which feed them into the files
+    pub fn init(&self, interrupt_manager: &mut InterruptManager) {
+        LCD().enable(self.display.borrow().framebuffer());
+
+        self.uart0_ro.borrow().internal().enable_rx_interrupt();
+        interrupt_manager.register(GIC_SOURCE_UART0, UART_PRIORITY, |state, ctx| {
+            // stdin and stdout are separate files sharing the UART
+            if UART0().rx_pending() {
+                let signals = state.io_manager.uart0_ro.borrow_mut().on_rx_interrupt();  // Add chars to the File buffer
+                signals.iter().for_each(|s| { state.process_manager.signal_group(s.pgid, s.signal).ok(); });
+            }
+            if UART0().tx_pending() {
+                state.io_manager.uart0_wo.borrow_mut().on_tx_interrupt();
+            }
+            state.process_manager.dispatch(ctx, ScheduleSource::Io);     // Invoke scheduler
+        }).unwrap();
+
+        self.uart1_rw.borrow().internal().enable_rx_interrupt();
+        interrupt_manager.register(GIC_SOURCE_UART1, UART_PRIORITY, |state, ctx| {
+            if UART1().rx_pending() {
+                let signals = state.io_manager.uart1_rw.borrow_mut().on_rx_interrupt();
+                signals.iter().for_each(|s| { state.process_manager.signal_group(s.pgid, s.signal).ok(); });
+            }
+            if UART1().tx_pending() {
+                state.io_manager.uart1_rw.borrow_mut().on_tx_interrupt();
+            }
+            state.process_manager.dispatch(ctx, ScheduleSource::Io);
+        }).unwrap();
+
+        self.keyboard.borrow().internal().enable_rx_interrupt();
+        interrupt_manager.register(GIC_SOURCE_PS20, PS2_PRIORITY, |state, ctx| {
+            state.io_manager.keyboard.borrow_mut().on_interrupt();
+            state.process_manager.dispatch(ctx, ScheduleSource::Io);
+        }).unwrap();
+
+        self.mouse.borrow().internal().enable_rx_interrupt();
+        self.mouse.borrow_mut().enable_reporting();
+        interrupt_manager.register(GIC_SOURCE_PS21, PS2_PRIORITY, |state, ctx| {
+            state.io_manager.mouse.borrow_mut().on_interrupt();
+            state.process_manager.dispatch(ctx, ScheduleSource::Io);
+        }).unwrap();
+    }
+
 }
 
 impl Default for IoManager {
 
     fn default() -> Self {
+        let display = Rc::new(RefCell::new(FramebufferConsole::new(Framebuffer::new(LCD_WIDTH, LCD_HEIGHT))));
         IoManager {
             uart0_ro: Rc::new(RefCell::new(PL011FileDescriptor::new(UART0(), true, false))),
             uart0_wo: Rc::new(RefCell::new(PL011FileDescriptor::new(UART0(), false, true))),
             uart1_rw: Rc::new(RefCell::new(PL011FileDescriptor::new(UART1(), true, true))),
+            keyboard: Rc::new(RefCell::new(KeyboardFileDescriptor::new(PS20()))),
+            mouse: Rc::new(RefCell::new(MouseFileDescriptor::new(PS21()))),
+            framebuffer: Rc::new(RefCell::new(FramebufferDevice::new(Rc::clone(&display)))),
+            message_queues: Default::default(),
+            sockets: Default::default(),
+            display,
         }
     }
 
@@ -0,0 +1,256 @@
+use crate::io::descriptor::{FileDescriptor, FileDescriptorBase, FileError, IOResult, StrongFileDescriptorRef};
+use crate::io::tasks::{ReadTask, WriteTask};
+use crate::process::StrongPcbRef;
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::rc::{Rc, Weak};
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::cell::RefCell;
+
+// mq_open flags, must match libc.h
+pub const MQ_CREATE: u32 = 0x01;
+pub const MQ_EXCLUSIVE: u32 = 0x02;        // With MQ_CREATE, fail if the queue already exists
+pub const MQ_NONBLOCK: u32 = 0x04;         // Fail instead of blocking on a full or empty queue
+
+pub const MQ_NAME_MAX: usize = 32;
+pub const MQ_MAX_MESSAGES: u32 = 64;
+pub const MQ_MAX_MESSAGE_SIZE: u32 = 1024;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MqAttr {
+    pub max_messages: u32,
+    pub message_size: u32,      // bytes
+}
+
+impl MqAttr {
+    fn valid(&self) -> bool {
+        self.max_messages > 0 && self.max_messages <= MQ_MAX_MESSAGES
+            && self.message_size > 0 && self.message_size <= MQ_MAX_MESSAGE_SIZE
+    }
+}
+
+impl Default for MqAttr {
+    fn default() -> Self {
+        MqAttr { max_messages: 8, message_size: 64 }
+    }
+}
+
+#[derive(Debug)]
+struct Message {
+    priority: u32,
+    data: Vec<u8>,
+}
+
+// A bounded queue of messages, received highest priority first, and in the order sent within a priority
+#[derive(Debug)]
+pub struct MessageQueue {
+    attr: MqAttr,
+    messages: VecDeque<Message>,
+    senders: VecDeque<(WriteTask, u32)>,            // Blocked while the queue is full, with their priority
+    receivers: VecDeque<(ReadTask, *mut u32)>,      // Blocked while it is empty, with where to put the priority
+}
+
+impl MessageQueue {
+
+    fn new(attr: MqAttr) -> Self {
+        MessageQueue { attr, messages: VecDeque::new(), senders: VecDeque::new(), receivers: VecDeque::new() }
+    }
+
+    fn push(&mut self, data: &[u8], priority: u32) -> Result<IOResult, FileError> {
+        if data.len() > self.attr.message_size as usize { return Err(FileError::InvalidArgument) }
+        if self.messages.len() >= self.attr.max_messages as usize { return Ok(IOResult { bytes: 0, blocked: true }) }
+        let index = self.messages.iter().position(|x| x.priority < priority).unwrap_or(self.messages.len());
+        self.messages.insert(index, Message { priority, data: data.to_vec() });
+        Ok(IOResult { bytes: data.len(), blocked: false })
+    }
+
+    // The buffer must be large enough for any message, as with POSIX
+    fn pop(&mut self, buffer: &mut [u8], priority: *mut u32) -> Result<IOResult, FileError> {
+        if buffer.len() < self.attr.message_size as usize { return Err(FileError::InvalidArgument) }
+        let message = match self.messages.pop_front() {
+            Some(x) => x,
+            None => return Ok(IOResult { bytes: 0, blocked: true }),
+        };
+        buffer[..message.data.len()].copy_from_slice(&message.data);
+        if !priority.is_null() { unsafe { *priority = message.priority } }
+        Ok(IOResult { bytes: message.data.len(), blocked: false })
+    }
+
+    // Retry the blocked tasks after the queue has changed, until none of them can make progress
+    fn notify(&mut self) {
+        loop {
+            let mut progress = false;
+            for _i in 0..self.receivers.len() {
+                let (mut task, priority) = self.receivers.pop_front().unwrap();
+                match task.attempt(|x| self.pop(x, priority)) {
+                    Some(_) => progress = true,
+                    None => self.receivers.push_back((task, priority)),
+                }
+            }
+            for _i in 0..self.senders.len() {
+                let (mut task, priority) = self.senders.pop_front().unwrap();
+                match task.attempt(|x| self.push(x, priority)) {
+                    Some(_) => progress = true,
+                    None => self.senders.push_back((task, priority)),
+                }
+            }
+            if !progress { break }
+        }
+    }
+
+}
+
+// One process's handle on a queue, shared with its children across fork
+#[derive(Debug)]
+pub struct MessageQueueDescriptor {
+    queue: Rc<RefCell<MessageQueue>>,
+    nonblocking: bool,
+    base: FileDescriptorBase,
+}
+
+// A task that would block on a non-blocking descriptor fails instead
+fn nonblocking(result: Result<IOResult, FileError>, nonblocking: bool) -> Result<IOResult, FileError> {
+    match result {
+        Ok(x) if x.blocked && nonblocking => Err(FileError::WouldBlock),
+        x => x,
+    }
+}
+
+impl FileDescriptor for MessageQueueDescriptor {
+
+    fn base(&mut self) -> &mut FileDescriptorBase { &mut self.base }
+
+    fn send(&mut self, mut task: WriteTask, priority: u32) -> Result<Option<u32>, FileError> {
+        let mut queue = self.queue.borrow_mut();
+        let result = task.attempt(|x| nonblocking(queue.push(x, priority), self.nonblocking));
+        match result {
+            Some(_) => queue.notify(),
+            None => queue.senders.push_back((task, priority)),
+        }
+        Ok(result)
+    }
+
+    fn receive(&mut self, mut task: ReadTask, priority: *mut u32) -> Result<Option<u32>, FileError> {
+        let mut queue = self.queue.borrow_mut();
+        let result = task.attempt(|x| nonblocking(queue.pop(x, priority), self.nonblocking));
+        match result {
+            Some(_) => queue.notify(),
+            None => queue.receivers.push_back((task, priority)),
+        }
+        Ok(result)
+    }
+
+    fn cancel_blocked(&mut self, process: &StrongPcbRef) {
+        let mut queue = self.queue.borrow_mut();
+        queue.senders.retain(|(x, _)| !x.is_for(process));
+        queue.receivers.retain(|(x, _)| !x.is_for(process));
+    }
+
+}
+
+// Queues by name, a queue exists while any process has it open
+#[derive(Default)]
+pub struct MessageQueues {
+    queues: BTreeMap<String, Weak<RefCell<MessageQueue>>>,
+}
+
+impl MessageQueues {
+
+    // attr sets the size of a created queue, or the default if it is None
+    pub fn open(&mut self, name: &str, flags: u32, attr: Option<MqAttr>) -> Result<StrongFileDescriptorRef, FileError> {
+        if name.is_empty() || name.len() > MQ_NAME_MAX { return Err(FileError::InvalidArgument) }
+        self.queues.retain(|_, x| x.strong_count() > 0);
+        let create = flags & MQ_CREATE != 0;
+        let queue = match self.queues.get(name).and_then(|x| x.upgrade()) {
+            Some(_) if create && flags & MQ_EXCLUSIVE != 0 => return Err(FileError::InvalidArgument),
+            Some(queue) => queue,
+            None if create => {
+                let attr = attr.unwrap_or_default();
+                if !attr.valid() { return Err(FileError::InvalidArgument) }
+                let queue = Rc::new(RefCell::new(MessageQueue::new(attr)));
+                self.queues.insert(name.to_string(), Rc::downgrade(&queue));
+                queue
+            }
+            None => return Err(FileError::InvalidDescriptor),
+        };
+        Ok(Rc::new(RefCell::new(MessageQueueDescriptor { queue, nonblocking: flags & MQ_NONBLOCK != 0, base: Default::default() })))
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::io::mqueue::{MessageQueues, MqAttr, MQ_CREATE, MQ_EXCLUSIVE, MQ_NONBLOCK};
+    use crate::io::tasks::{ReadTask, WriteTask};
+    use crate::process::ProcessStatus;
+    use crate::process::testing::new_process;
+    use alloc::rc::Rc;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn open_test() {
+        let mut queues = MessageQueues::default();
+        assert!(queues.open("q", 0, None).is_err());
+        assert!(queues.open("q", MQ_CREATE, Some(MqAttr { max_messages: 0, message_size: 8 })).is_err());
+        let first = queues.open("q", MQ_CREATE, None).unwrap();
+        assert!(queues.open("q", MQ_CREATE | MQ_EXCLUSIVE, None).is_err());
+        let second = queues.open("q", 0, None).unwrap();
+        assert!(!Rc::ptr_eq(&first, &second));
+        // A queue is removed once it has been closed everywhere
+        drop((first, second));
+        assert!(queues.open("q", 0, None).is_err());
+    }
+
+    #[test]
+    fn priority_test() {
+        let process = new_process();
+        let mut queues = MessageQueues::default();
+        let file = queues.open("q", MQ_CREATE, Some(MqAttr { max_messages: 4, message_size: 4 })).unwrap();
+        let mut file = file.borrow_mut();
+        for (message, priority) in [(b"a1", 1), (b"b0", 0), (b"c2", 2), (b"d1", 1)] {
+            assert_eq!(file.send(WriteTask::new(&process, message.as_ptr(), 2), priority).ok(), Some(Some(2)));
+        }
+        assert_eq!(file.send(WriteTask::new(&process, b"toolong".as_ptr(), 7), 0).ok(), Some(Some(-1i32 as u32)));
+
+        // Highest priority first, then in the order sent
+        let mut buffer = [0u8; 4];
+        let mut priority = 0;
+        let received: Vec<(u8, u32)> = (0..4).map(|_| {
+            assert_eq!(file.receive(ReadTask::new(&process, buffer.as_mut_ptr(), 4), &mut priority).ok(), Some(Some(2)));
+            (buffer[0], priority)
+        }).collect();
+        assert_eq!(received, vec![(b'c', 2), (b'a', 1), (b'd', 1), (b'b', 0)]);
+        // The buffer must fit the largest message
+        assert_eq!(file.receive(ReadTask::new(&process, buffer.as_mut_ptr(), 2), &mut priority).ok(), Some(Some(-1i32 as u32)));
+    }
+
+    #[test]
+    fn blocking_test() {
+        let (sender, receiver) = (new_process(), new_process());
+        let mut queues = MessageQueues::default();
+        let attr = Some(MqAttr { max_messages: 1, message_size: 1 });
+        let send_file = queues.open("q", MQ_CREATE, attr).unwrap();
+        let receive_file = queues.open("q", 0, None).unwrap();
+        let nonblocking = queues.open("q", MQ_NONBLOCK, None).unwrap();
+        let mut buffer = [0u8; 1];
+
+        // Receiving from an empty queue blocks, until a message is sent
+        assert_eq!(nonblocking.borrow_mut().receive(ReadTask::new(&receiver, buffer.as_mut_ptr(), 1), core::ptr::null_mut()).ok(), Some(Some(-1i32 as u32)));
+        assert_eq!(receive_file.borrow_mut().receive(ReadTask::new(&receiver, buffer.as_mut_ptr(), 1), core::ptr::null_mut()).ok(), Some(None));
+        assert_eq!(*receiver.borrow().status(), ProcessStatus::Blocked);
+        send_file.borrow_mut().send(WriteTask::new(&sender, b"x".as_ptr(), 1), 0).unwrap();
+        assert_eq!(*receiver.borrow().status(), ProcessStatus::Ready);
+        assert_eq!(buffer[0], b'x');
+
+        // Sending to a full queue blocks, until a message is received
+        send_file.borrow_mut().send(WriteTask::new(&sender, b"y".as_ptr(), 1), 0).unwrap();
+        assert_eq!(nonblocking.borrow_mut().send(WriteTask::new(&sender, b"z".as_ptr(), 1), 0).ok(), Some(Some(-1i32 as u32)));
+        assert_eq!(send_file.borrow_mut().send(WriteTask::new(&sender, b"z".as_ptr(), 1), 0).ok(), Some(None));
+        assert_eq!(*sender.borrow().status(), ProcessStatus::Blocked);
+        receive_file.borrow_mut().receive(ReadTask::new(&receiver, buffer.as_mut_ptr(), 1), core::ptr::null_mut()).unwrap();
+        assert_eq!((buffer[0], sender.borrow().status().clone()), (b'y', ProcessStatus::Ready));
+        receive_file.borrow_mut().receive(ReadTask::new(&receiver, buffer.as_mut_ptr(), 1), core::ptr::null_mut()).unwrap();
+        assert_eq!(buffer[0], b'z');
+    }
+}
@@ -1,7 +1,9 @@
 use alloc::collections::VecDeque;
-use crate::io::descriptor::{FileDescriptor, FileError, IOResult, FileDescriptorBase, StrongFileDescriptorRef};
+use crate::io::descriptor::{FileDescriptor, FileError, IOResult, FileDescriptorBase, StrongFileDescriptorRef, WeakFileDescriptorRef};
+use crate::process::StrongPcbRef;
 use core::cell::RefCell;
 use alloc::rc::{Rc, Weak};
+use alloc::vec::Vec;
 
 
 const PIPE_BUFFER: usize = 4096;
@@ -72,6 +74,19 @@ impl PipeWriteEnd {
     }
 }
 
+// Once one end is closed, readers at the other end get what is left then the end of the file, and writers fail
+impl Drop for PipeReadEnd {
+    fn drop(&mut self) {
+        self.notify_write_end();
+    }
+}
+
+impl Drop for PipeWriteEnd {
+    fn drop(&mut self) {
+        self.notify_read_end();
+    }
+}
+
 impl FileDescriptor for PipeReadEnd {
 
     fn base(&mut self) -> &mut FileDescriptorBase { &mut self.base }
@@ -80,7 +95,9 @@ impl FileDescriptor for PipeReadEnd {
         let mut idx = 0;
         while idx < buffer.len() {
             let mut pipe = self.pipe.try_borrow_mut().unwrap();
-            if pipe.buffer.is_empty() {
+            if pipe.buffer.is_empty() && pipe.write_end.strong_count() == 0 {
+                return Ok(IOResult{ bytes: idx, blocked: false })       // The end of the file, nothing more can be written
+            } else if pipe.buffer.is_empty() {
                 return Ok(IOResult{ bytes: idx, blocked: true })        // We are blocked, we need to wait for the new writes
             } else {
                 buffer[idx] = pipe.buffer.pop_front().unwrap();
@@ -91,6 +108,12 @@ impl FileDescriptor for PipeReadEnd {
         };
         Ok(IOResult{ bytes: idx, blocked: false })
     }
+
+    // Blocked readers wait for a write
+    fn blocked_on(&mut self) -> Option<(Vec<StrongPcbRef>, WeakFileDescriptorRef)> {
+        let write_end: WeakFileDescriptorRef = self.pipe.borrow().write_end.clone();
+        Some((self.base.blocked_processes(), write_end))
+    }
 }
 
 impl FileDescriptor for PipeWriteEnd {
@@ -98,6 +121,7 @@ impl FileDescriptor for PipeWriteEnd {
     fn base(&mut self) -> &mut FileDescriptorBase { &mut self.base }
 
     fn write(&mut self, data: &[u8]) -> Result<IOResult, FileError> {
+        if self.pipe.borrow().read_end.strong_count() == 0 { return Err(FileError::InvalidDescriptor) }     // Nothing can read it
         let mut idx = 0;
         while idx < data.len() {
             let mut pipe = self.pipe.borrow_mut();
@@ -112,4 +136,42 @@ impl FileDescriptor for PipeWriteEnd {
         };
         Ok(IOResult{ bytes: idx, blocked: false })
     }
+
+    // Blocked writers wait for a read
+    fn blocked_on(&mut self) -> Option<(Vec<StrongPcbRef>, WeakFileDescriptorRef)> {
+        let read_end: WeakFileDescriptorRef = self.pipe.borrow().read_end.clone();
+        Some((self.base.blocked_processes(), read_end))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::io::pipe::new_pipe;
+    use crate::io::tasks::{ReadTask, WriteTask};
+    use crate::process::ProcessStatus;
+    use crate::process::testing::new_process;
+
+    #[test]
+    fn close_test() {
+        let process = new_process();
+        let (read, write) = new_pipe();
+        let mut task = WriteTask::new(&process, b"ab".as_ptr(), 2);
+        assert_eq!(task.attempt(|x| write.borrow_mut().write(x)), Some(2));
+
+        // A blocked reader gets what is left once the write end is closed, then the end of the file
+        let mut buffer = [0u8; 4];
+        let mut task = ReadTask::new(&process, buffer.as_mut_ptr(), 4);
+        assert!(task.attempt(|x| read.borrow_mut().read(x)).is_none());
+        read.borrow_mut().add_pending_read(task);
+        drop(write);
+        assert_eq!((process.borrow().status().clone(), process.borrow().result(), &buffer[..2]), (ProcessStatus::Ready, 2, &b"ab"[..]));
+        let mut task = ReadTask::new(&process, buffer.as_mut_ptr(), 4);
+        assert_eq!(task.attempt(|x| read.borrow_mut().read(x)), Some(0));
+
+        // Writing fails once the read end is closed
+        let (read, write) = new_pipe();
+        drop(read);
+        let mut task = WriteTask::new(&process, b"c".as_ptr(), 1);
+        assert_eq!(task.attempt(|x| write.borrow_mut().write(x)), Some(-1i32 as u32));
+    }
 }
@@ -0,0 +1,214 @@
+// Decoder for PS/2 scancode set 2, which is what the PL050 keyboard sends by default
+
+const EXTENDED_PREFIX: u8 = 0xE0;
+const BREAK_PREFIX: u8 = 0xF0;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Key {
+    Printable(u8),      // The unshifted character printed on the key
+    Enter,
+    Backspace,
+    Tab,
+    Escape,
+    LeftShift,
+    RightShift,
+    LeftCtrl,
+    RightCtrl,
+    Alt,
+    CapsLock,
+    Up,
+    Down,
+    Left,
+    Right,
+    Home,
+    End,
+    Delete,
+    Unknown(u8),
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct KeyEvent {
+    pub key: Key,
+    pub pressed: bool,
+    pub ascii: Option<u8>,      // Only set for presses that produce a character
+}
+
+#[derive(Default, Debug)]
+pub struct ScancodeDecoder {
+    extended: bool,
+    released: bool,
+    left_shift: bool,
+    right_shift: bool,
+    left_ctrl: bool,
+    right_ctrl: bool,
+    caps_lock: bool,
+}
+
+impl ScancodeDecoder {
+
+    // Feed in the next byte from the keyboard, a multi-byte scancode will only produce an event on its last byte
+    pub fn push(&mut self, byte: u8) -> Option<KeyEvent> {
+        match byte {
+            EXTENDED_PREFIX => { self.extended = true; return None }
+            BREAK_PREFIX => { self.released = true; return None }
+            _ => {}
+        }
+        let key = if self.extended { extended_key(byte) } else { key(byte) };
+        let pressed = !self.released;
+        self.extended = false;
+        self.released = false;
+
+        match key {
+            Key::LeftShift => self.left_shift = pressed,
+            Key::RightShift => self.right_shift = pressed,
+            Key::LeftCtrl => self.left_ctrl = pressed,
+            Key::RightCtrl => self.right_ctrl = pressed,
+            Key::CapsLock => if pressed { self.caps_lock = !self.caps_lock },
+            _ => {}
+        }
+        let ascii = if pressed { self.ascii(key) } else { None };
+        Some(KeyEvent { key, pressed, ascii })
+    }
+
+    fn ascii(&self, key: Key) -> Option<u8> {
+        let shift = self.left_shift || self.right_shift;
+        let ctrl = self.left_ctrl || self.right_ctrl;
+        match key {
+            Key::Printable(c) if c.is_ascii_lowercase() => {
+                if ctrl {
+                    Some(c & 0x1F)                                      // Ctrl-A = 0x01 etc.
+                } else if shift ^ self.caps_lock {
+                    Some(c.to_ascii_uppercase())
+                } else {
+                    Some(c)
+                }
+            }
+            Key::Printable(c) => Some(if shift { shifted(c) } else { c }),
+            Key::Enter => Some(b'\n'),
+            Key::Backspace => Some(0x08),
+            Key::Tab => Some(b'\t'),
+            Key::Escape => Some(0x1B),
+            Key::Delete => Some(0x7F),
+            _ => None,
+        }
+    }
+
+}
+
+fn key(code: u8) -> Key {
+    match code {
+        0x1C => Key::Printable(b'a'), 0x32 => Key::Printable(b'b'), 0x21 => Key::Printable(b'c'),
+        0x23 => Key::Printable(b'd'), 0x24 => Key::Printable(b'e'), 0x2B => Key::Printable(b'f'),
+        0x34 => Key::Printable(b'g'), 0x33 => Key::Printable(b'h'), 0x43 => Key::Printable(b'i'),
+        0x3B => Key::Printable(b'j'), 0x42 => Key::Printable(b'k'), 0x4B => Key::Printable(b'l'),
+        0x3A => Key::Printable(b'm'), 0x31 => Key::Printable(b'n'), 0x44 => Key::Printable(b'o'),
+        0x4D => Key::Printable(b'p'), 0x15 => Key::Printable(b'q'), 0x2D => Key::Printable(b'r'),
+        0x1B => Key::Printable(b's'), 0x2C => Key::Printable(b't'), 0x3C => Key::Printable(b'u'),
+        0x2A => Key::Printable(b'v'), 0x1D => Key::Printable(b'w'), 0x22 => Key::Printable(b'x'),
+        0x35 => Key::Printable(b'y'), 0x1A => Key::Printable(b'z'),
+        0x45 => Key::Printable(b'0'), 0x16 => Key::Printable(b'1'), 0x1E => Key::Printable(b'2'),
+        0x26 => Key::Printable(b'3'), 0x25 => Key::Printable(b'4'), 0x2E => Key::Printable(b'5'),
+        0x36 => Key::Printable(b'6'), 0x3D => Key::Printable(b'7'), 0x3E => Key::Printable(b'8'),
+        0x46 => Key::Printable(b'9'),
+        0x0E => Key::Printable(b'`'), 0x4E => Key::Printable(b'-'), 0x55 => Key::Printable(b'='),
+        0x54 => Key::Printable(b'['), 0x5B => Key::Printable(b']'), 0x5D => Key::Printable(b'\\'),
+        0x4C => Key::Printable(b';'), 0x52 => Key::Printable(b'\''), 0x41 => Key::Printable(b','),
+        0x49 => Key::Printable(b'.'), 0x4A => Key::Printable(b'/'), 0x29 => Key::Printable(b' '),
+        0x5A => Key::Enter,
+        0x66 => Key::Backspace,
+        0x0D => Key::Tab,
+        0x76 => Key::Escape,
+        0x12 => Key::LeftShift,
+        0x59 => Key::RightShift,
+        0x14 => Key::LeftCtrl,
+        0x11 => Key::Alt,
+        0x58 => Key::CapsLock,
+        _ => Key::Unknown(code),
+    }
+}
+
+// Keys that were preceded by 0xE0
+fn extended_key(code: u8) -> Key {
+    match code {
+        0x75 => Key::Up,
+        0x72 => Key::Down,
+        0x6B => Key::Left,
+        0x74 => Key::Right,
+        0x6C => Key::Home,
+        0x69 => Key::End,
+        0x71 => Key::Delete,
+        0x14 => Key::RightCtrl,
+        0x11 => Key::Alt,           // Right alt
+        0x5A => Key::Enter,         // Keypad enter
+        0x4A => Key::Printable(b'/'),   // Keypad slash
+        _ => Key::Unknown(code),
+    }
+}
+
+// US keyboard layout
+fn shifted(c: u8) -> u8 {
+    match c {
+        b'1' => b'!', b'2' => b'@', b'3' => b'#', b'4' => b'$', b'5' => b'%',
+        b'6' => b'^', b'7' => b'&', b'8' => b'*', b'9' => b'(', b'0' => b')',
+        b'`' => b'~', b'-' => b'_', b'=' => b'+', b'[' => b'{', b']' => b'}',
+        b'\\' => b'|', b';' => b':', b'\'' => b'"', b',' => b'<', b'.' => b'>',
+        b'/' => b'?',
+        _ => c,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::io::scancode::{ScancodeDecoder, Key};
+    use alloc::vec::Vec;
+
+    fn type_bytes(decoder: &mut ScancodeDecoder, bytes: &[u8]) -> Vec<u8> {
+        bytes.iter().filter_map(|b| decoder.push(*b)).filter_map(|e| e.ascii).collect()
+    }
+
+    #[test]
+    fn press_release_test() {
+        let mut decoder = ScancodeDecoder::default();
+        let press = decoder.push(0x1C).unwrap();
+        assert_eq!(press.key, Key::Printable(b'a'));
+        assert!(press.pressed);
+        assert_eq!(press.ascii, Some(b'a'));
+        assert!(decoder.push(0xF0).is_none());
+        let release = decoder.push(0x1C).unwrap();
+        assert!(!release.pressed);
+        assert_eq!(release.ascii, None);
+    }
+
+    #[test]
+    fn shift_caps_test() {
+        let mut decoder = ScancodeDecoder::default();
+        // Shift held: a 1, then released: a
+        assert_eq!(type_bytes(&mut decoder, &[0x12, 0x1C, 0x16, 0xF0, 0x12, 0x1C]), b"A!a".to_vec());
+        // Caps lock on: a 1, with shift: a
+        assert_eq!(type_bytes(&mut decoder, &[0x58, 0xF0, 0x58, 0x1C, 0x16, 0x59, 0x1C]), b"A1a".to_vec());
+    }
+
+    #[test]
+    fn ctrl_test() {
+        let mut decoder = ScancodeDecoder::default();
+        assert_eq!(type_bytes(&mut decoder, &[0x14, 0x21, 0xF0, 0x14, 0x21]), vec![0x03, b'c']);
+        // Right ctrl is extended
+        assert_eq!(type_bytes(&mut decoder, &[0xE0, 0x14, 0x21]), vec![0x03]);
+        // Releasing left ctrl while right ctrl is still held keeps ctrl down
+        assert_eq!(type_bytes(&mut decoder, &[0x14, 0xF0, 0x14, 0x21, 0xE0, 0xF0, 0x14, 0x21]), vec![0x03, b'c']);
+    }
+
+    #[test]
+    fn extended_test() {
+        let mut decoder = ScancodeDecoder::default();
+        assert!(decoder.push(0xE0).is_none());
+        assert_eq!(decoder.push(0x75).unwrap().key, Key::Up);
+        assert!(decoder.push(0xE0).is_none());
+        assert!(decoder.push(0xF0).is_none());
+        let release = decoder.push(0x75).unwrap();
+        assert_eq!(release.key, Key::Up);
+        assert!(!release.pressed);
+        // The prefix state must be cleared, so this is the normal 0x75 code
+        assert_eq!(decoder.push(0x75).unwrap().key, Key::Unknown(0x75));
+    }
+}
@@ -0,0 +1,461 @@
+use crate::io::descriptor::{FileDescriptor, FileDescriptorBase, FileError, IOResult, StrongFileDescriptorRef};
+use crate::io::tasks::{ReadTask, WriteTask};
+use crate::process::{StrongPcbRef, WeakPcbRef, ProcessStatus};
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::rc::{Rc, Weak};
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::cell::RefCell;
+use num::range;
+
+// Socket types, must match libc.h
+pub const SOCK_STREAM: u32 = 0;
+pub const SOCK_DGRAM: u32 = 1;
+
+pub const SOCK_NAME_MAX: usize = 32;
+pub const SOCK_BACKLOG_MAX: u32 = 16;
+
+const SOCKET_BUFFER: usize = 4096;          // Bytes waiting to be read from a stream, and the largest datagram
+const DATAGRAM_QUEUE: usize = 16;
+const PASSED_FILES_MAX: usize = 16;
+
+pub type StrongSocketRef = Rc<RefCell<SocketDescriptor>>;
+pub type WeakSocketRef = Weak<RefCell<SocketDescriptor>>;
+
+// What has been sent to a socket and not yet read. It is shared with the sockets writing to it,
+// so that they don't need to borrow the socket itself
+#[derive(Debug, Default)]
+struct Channel {
+    bytes: VecDeque<u8>,
+    datagrams: VecDeque<Vec<u8>>,
+    files: VecDeque<StrongFileDescriptorRef>,   // Passed with sendmsg, and taken by recvmsg
+    blocked_writers: Vec<WeakSocketRef>,        // Datagram sockets waiting for room in the queue
+}
+
+#[derive(Debug)]
+enum State {
+    Unbound,
+    Bound,
+    Listening { backlog: VecDeque<StrongSocketRef>, max: usize, acceptors: VecDeque<WeakPcbRef> },
+    // A stream socket's peer is the other end of the connection, a datagram socket's is where it sends to
+    Connected { peer: WeakSocketRef, outgoing: Rc<RefCell<Channel>> },
+}
+
+#[derive(Debug)]
+pub struct SocketDescriptor {
+    kind: u32,
+    state: State,
+    this: WeakSocketRef,
+    incoming: Rc<RefCell<Channel>>,
+    receivers: VecDeque<(ReadTask, *mut i32)>,  // Blocked in recvmsg, with where to put the passed file's fid
+    base: FileDescriptorBase,
+}
+
+pub fn new_socket(kind: u32) -> Result<StrongSocketRef, FileError> {
+    if kind != SOCK_STREAM && kind != SOCK_DGRAM { return Err(FileError::InvalidArgument) }
+    Ok(Rc::new_cyclic(|this| RefCell::new(SocketDescriptor {
+        kind,
+        state: State::Unbound,
+        this: Weak::clone(this),
+        incoming: Default::default(),
+        receivers: VecDeque::new(),
+        base: Default::default(),
+    })))
+}
+
+// try_borrow_mut, as with pipes, as the peer may be the socket which caused this one to be notified
+fn notify_readers(socket: &WeakSocketRef) {
+    socket.upgrade().map(|x| x.try_borrow_mut().ok().map(|mut x| x.notify_pending_readers()));
+}
+
+fn notify_writers(socket: &WeakSocketRef) {
+    socket.upgrade().map(|x| x.try_borrow_mut().ok().map(|mut x| x.notify_pending_writers()));
+}
+
+impl SocketDescriptor {
+
+    pub fn listen(&mut self, backlog: u32) -> Result<(), FileError> {
+        match self.state {
+            State::Bound if self.kind == SOCK_STREAM => {
+                let max = backlog.max(1).min(SOCK_BACKLOG_MAX) as usize;
+                self.state = State::Listening { backlog: VecDeque::new(), max, acceptors: VecDeque::new() };
+                Ok(())
+            }
+            _ => Err(FileError::InvalidArgument)
+        }
+    }
+
+    // The next connection to a listening socket, otherwise the process is blocked until there is one
+    pub fn accept(&mut self, process: &StrongPcbRef) -> Result<Option<StrongSocketRef>, FileError> {
+        match &mut self.state {
+            State::Listening { backlog, acceptors, .. } => match backlog.pop_front() {
+                Some(x) => Ok(Some(x)),
+                None => {
+                    process.borrow_mut().set_blocked();
+                    acceptors.push_back(Rc::downgrade(process));
+                    Ok(None)
+                }
+            }
+            _ => Err(FileError::InvalidArgument)
+        }
+    }
+
+    // Give a new connection to a blocked acceptor, or add it to the backlog
+    fn connection(&mut self, socket: StrongSocketRef) -> Result<(), FileError> {
+        match &mut self.state {
+            State::Listening { backlog, max, acceptors } => {
+                while let Some(process) = acceptors.pop_front() {
+                    let process = match process.upgrade() { Some(x) => x, None => continue };
+                    let mut pcb = process.borrow_mut();
+                    if *pcb.status() != ProcessStatus::Blocked { continue }
+                    let fid = pcb.add_file(socket);
+                    pcb.set_unblocked(fid as u32);
+                    return Ok(())
+                }
+                if backlog.len() >= *max { return Err(FileError::WouldBlock) }
+                backlog.push_back(socket);
+                Ok(())
+            }
+            _ => Err(FileError::InvalidArgument)
+        }
+    }
+
+    fn peer(&self) -> Option<(&WeakSocketRef, &Rc<RefCell<Channel>>)> {
+        match &self.state {
+            State::Connected { peer, outgoing } => Some((peer, outgoing)),
+            _ => None,
+        }
+    }
+
+    // write, passing a file which the receiver gets with the data. Either end of the connection would
+    // be queued in a channel that it holds itself, so it could never be freed
+    pub fn send_message(&mut self, mut task: WriteTask, file: Option<StrongFileDescriptorRef>) -> Result<Option<u32>, FileError> {
+        let (peer, outgoing) = self.peer().ok_or(FileError::InvalidArgument)?;
+        let outgoing = Rc::clone(outgoing);
+        if let Some(file) = &file {
+            let file = Rc::as_ptr(file) as *const u8;
+            if file == self.this.as_ptr() as *const u8 || file == peer.as_ptr() as *const u8 {
+                return Err(FileError::InvalidArgument)
+            }
+            if outgoing.borrow().files.len() >= PASSED_FILES_MAX { return Err(FileError::WouldBlock) }
+        }
+        // The file is queued first so that a blocked receiver is given it with the data, but withdrawn
+        // if nothing could be sent
+        let passing = file.is_some();
+        file.map(|x| outgoing.borrow_mut().files.push_back(x));
+        let result = task.attempt(|x| self.write(x));
+        match result {
+            Some(r) if r == -1i32 as u32 && passing => { outgoing.borrow_mut().files.pop_back(); }
+            None => self.add_pending_write(task),
+            _ => {}
+        }
+        Ok(result)
+    }
+
+    // read, then take the oldest passed file if any data was read, storing its new fid (or -1) in file
+    pub fn receive_message(&mut self, mut task: ReadTask, file: *mut i32) -> Option<u32> {
+        let result = task.attempt(|x| self.read(x));
+        match result {
+            Some(r) => self.take_file(&task, r, file),
+            None => self.receivers.push_back((task, file)),
+        }
+        result
+    }
+
+    fn take_file(&mut self, task: &ReadTask, result: u32, fid: *mut i32) {
+        let passed = if result as i32 > 0 { self.incoming.borrow_mut().files.pop_front() } else { None };
+        let new_fid = match (passed, task.process()) {
+            (Some(file), Some(process)) => process.borrow_mut().add_file(file),
+            _ => -1,
+        };
+        if !fid.is_null() { unsafe { *fid = new_fid } }
+    }
+
+}
+
+impl FileDescriptor for SocketDescriptor {
+
+    fn base(&mut self) -> &mut FileDescriptorBase { &mut self.base }
+
+    // Streams return what is available, or nothing at the end of the stream once the peer has closed.
+    // Datagrams are read one at a time, and truncated to fit the buffer
+    fn read(&mut self, buffer: &mut [u8]) -> Result<IOResult, FileError> {
+        let mut incoming = self.incoming.borrow_mut();
+        if self.kind == SOCK_STREAM {
+            let (peer, _) = self.peer().ok_or(FileError::InvalidArgument)?;
+            let count = buffer.len().min(incoming.bytes.len());
+            incoming.bytes.drain(..count).zip(buffer.iter_mut()).for_each(|(x, y)| *y = x);
+            drop(incoming);
+            if count > 0 { notify_writers(peer) }
+            let closed = peer.strong_count() == 0;
+            Ok(IOResult { bytes: count, blocked: count == 0 && buffer.len() > 0 && !closed })
+        } else {
+            match incoming.datagrams.pop_front() {
+                Some(datagram) => {
+                    let count = buffer.len().min(datagram.len());
+                    buffer[..count].copy_from_slice(&datagram[..count]);
+                    let writers: Vec<WeakSocketRef> = incoming.blocked_writers.drain(..).collect();
+                    drop(incoming);
+                    writers.iter().for_each(notify_writers);
+                    Ok(IOResult { bytes: count, blocked: false })
+                }
+                None if matches!(self.state, State::Unbound) => Err(FileError::InvalidArgument),
+                None => Ok(IOResult { bytes: 0, blocked: true }),
+            }
+        }
+    }
+
+    // Writing fails once the peer has closed, or a datagram is too large
+    fn write(&mut self, data: &[u8]) -> Result<IOResult, FileError> {
+        let (peer, outgoing) = self.peer().ok_or(FileError::InvalidArgument)?;
+        if peer.strong_count() == 0 { return Err(FileError::InvalidDescriptor) }
+        let mut channel = outgoing.borrow_mut();
+        let result = if self.kind == SOCK_STREAM {
+            let count = data.len().min(SOCKET_BUFFER - channel.bytes.len());
+            channel.bytes.extend(&data[..count]);
+            IOResult { bytes: count, blocked: count < data.len() }
+        } else {
+            if data.len() > SOCKET_BUFFER { return Err(FileError::InvalidArgument) }
+            if channel.datagrams.len() < DATAGRAM_QUEUE {
+                channel.datagrams.push_back(data.to_vec());
+                IOResult { bytes: data.len(), blocked: false }
+            } else {
+                channel.blocked_writers.push(Weak::clone(&self.this));
+                IOResult { bytes: 0, blocked: true }
+            }
+        };
+        drop(channel);
+        if result.bytes > 0 || data.is_empty() { notify_readers(peer) }
+        Ok(result)
+    }
+
+    fn notify_pending_readers(&mut self) {
+        for _i in range(0, self.base().pending_reads.len()) {
+            let mut popped = self.base().pending_reads.pop_front().unwrap();
+            let result = popped.attempt(|x| self.read(x));
+            if result.is_none() { self.base().pending_reads.push_back(popped) }
+        }
+        for _i in range(0, self.receivers.len()) {
+            let (mut popped, fid) = self.receivers.pop_front().unwrap();
+            match popped.attempt(|x| self.read(x)) {
+                Some(r) => self.take_file(&popped, r, fid),
+                None => self.receivers.push_back((popped, fid)),
+            }
+        }
+    }
+
+    fn cancel_blocked(&mut self, process: &StrongPcbRef) {
+        self.base.cancel(process);
+        self.receivers.retain(|(x, _)| !x.is_for(process));
+        if let State::Listening { acceptors, .. } = &mut self.state {
+            acceptors.retain(|x| x.as_ptr() != Rc::as_ptr(process));
+        }
+    }
+
+    fn socket(&mut self) -> Option<&mut SocketDescriptor> {
+        Some(self)
+    }
+
+}
+
+// Readers see the end of the stream, and writers fail, once the other end has closed.
+// Processes blocked accepting on a closed socket fail
+impl Drop for SocketDescriptor {
+    fn drop(&mut self) {
+        match &self.state {
+            State::Connected { peer, .. } if self.kind == SOCK_STREAM => {
+                notify_readers(peer);
+                notify_writers(peer);
+            }
+            State::Listening { acceptors, .. } => {
+                acceptors.iter().filter_map(|x| x.upgrade()).for_each(|x| x.borrow_mut().set_unblocked(-1i32 as u32));
+            }
+            _ => {}
+        }
+    }
+}
+
+// The names bound to sockets, a name is released when its socket is closed
+#[derive(Default)]
+pub struct Sockets {
+    names: BTreeMap<String, WeakSocketRef>,
+}
+
+impl Sockets {
+
+    pub fn bind(&mut self, socket: &mut SocketDescriptor, name: &str) -> Result<(), FileError> {
+        if name.is_empty() || name.len() > SOCK_NAME_MAX { return Err(FileError::InvalidArgument) }
+        if !matches!(socket.state, State::Unbound) { return Err(FileError::InvalidArgument) }
+        self.names.retain(|_, x| x.strong_count() > 0);
+        if self.names.contains_key(name) { return Err(FileError::InvalidArgument) }
+        self.names.insert(name.to_string(), Weak::clone(&socket.this));
+        socket.state = State::Bound;
+        Ok(())
+    }
+
+    // Connect a stream socket to a listening socket, which can then accept the other end of the
+    // connection. A datagram socket is connected to a bound datagram socket, to send to it
+    pub fn connect(&mut self, socket: &mut SocketDescriptor, name: &str) -> Result<(), FileError> {
+        let target = self.names.get(name).and_then(|x| x.upgrade()).ok_or(FileError::InvalidArgument)?;
+        // Which fails if the socket is connecting to itself
+        let mut target = target.try_borrow_mut().map_err(|_| FileError::InvalidArgument)?;
+        if target.kind != socket.kind { return Err(FileError::InvalidArgument) }
+        if socket.kind == SOCK_STREAM {
+            if !matches!(socket.state, State::Unbound | State::Bound) { return Err(FileError::InvalidArgument) }
+            let accepted = new_socket(SOCK_STREAM)?;
+            {
+                let mut other_end = accepted.borrow_mut();
+                other_end.state = State::Connected { peer: Weak::clone(&socket.this), outgoing: Rc::clone(&socket.incoming) };
+                target.connection(Rc::clone(&accepted))?;
+            }
+            socket.state = State::Connected { peer: Rc::downgrade(&accepted), outgoing: Rc::clone(&accepted.borrow().incoming) };
+        } else {
+            if matches!(target.state, State::Unbound) { return Err(FileError::InvalidArgument) }
+            socket.state = State::Connected { peer: Weak::clone(&target.this), outgoing: Rc::clone(&target.incoming) };
+        }
+        Ok(())
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::io::socket::{new_socket, Sockets, SOCK_STREAM, SOCK_DGRAM};
+    use crate::io::descriptor::{FileDescriptor, StrongFileDescriptorRef};
+    use crate::io::pipe::new_pipe;
+    use crate::io::tasks::{ReadTask, WriteTask};
+    use crate::process::ProcessStatus;
+    use crate::process::testing::new_process;
+    use alloc::rc::Rc;
+
+    #[test]
+    fn stream_test() {
+        let (server, client) = (new_process(), new_process());
+        let mut sockets = Sockets::default();
+        let listener = new_socket(SOCK_STREAM).unwrap();
+        let connecting = new_socket(SOCK_STREAM).unwrap();
+        assert!(sockets.connect(&mut connecting.borrow_mut(), "s").is_err());
+        assert!(listener.borrow_mut().listen(1).is_err());
+        sockets.bind(&mut listener.borrow_mut(), "s").unwrap();
+        assert!(sockets.bind(&mut new_socket(SOCK_STREAM).unwrap().borrow_mut(), "s").is_err());
+        listener.borrow_mut().listen(1).unwrap();
+
+        // Accepting blocks until a connection arrives, which is then added to the acceptor's files
+        assert!(listener.borrow_mut().accept(&server).unwrap().is_none());
+        assert_eq!(*server.borrow().status(), ProcessStatus::Blocked);
+        sockets.connect(&mut connecting.borrow_mut(), "s").unwrap();
+        assert_eq!(*server.borrow().status(), ProcessStatus::Ready);
+        let fid = server.borrow().result() as i32;
+        let accepted = server.borrow().get_file(fid).unwrap();
+
+        // Data flows both ways, and a blocked reader is woken by a write
+        let mut buffer = [0u8; 4];
+        let mut task = ReadTask::new(&server, buffer.as_mut_ptr(), 4);
+        assert!(task.attempt(|x| accepted.borrow_mut().read(x)).is_none());
+        accepted.borrow_mut().add_pending_read(task);
+        let mut task = WriteTask::new(&client, b"ping".as_ptr(), 4);
+        assert_eq!(task.attempt(|x| connecting.borrow_mut().write(x)), Some(4));
+        assert_eq!((&buffer, server.borrow().status().clone()), (b"ping", ProcessStatus::Ready));
+        let mut task = WriteTask::new(&server, b"ok".as_ptr(), 2);
+        assert_eq!(task.attempt(|x| accepted.borrow_mut().write(x)), Some(2));
+        let mut task = ReadTask::new(&client, buffer.as_mut_ptr(), 4);
+        assert_eq!(task.attempt(|x| connecting.borrow_mut().read(x)), Some(2));
+
+        // Once one end is closed the other reads the end of the stream, and writes fail
+        server.borrow_mut().close_file(fid).unwrap();
+        drop(accepted);
+        let mut task = ReadTask::new(&client, buffer.as_mut_ptr(), 4);
+        assert_eq!(task.attempt(|x| connecting.borrow_mut().read(x)), Some(0));
+        let mut task = WriteTask::new(&client, b"x".as_ptr(), 1);
+        assert_eq!(task.attempt(|x| connecting.borrow_mut().write(x)), Some(-1i32 as u32));
+    }
+
+    #[test]
+    fn backlog_test() {
+        let process = new_process();
+        let mut sockets = Sockets::default();
+        let listener = new_socket(SOCK_STREAM).unwrap();
+        sockets.bind(&mut listener.borrow_mut(), "s").unwrap();
+        listener.borrow_mut().listen(1).unwrap();
+        let (first, second) = (new_socket(SOCK_STREAM).unwrap(), new_socket(SOCK_STREAM).unwrap());
+        sockets.connect(&mut first.borrow_mut(), "s").unwrap();
+        assert!(sockets.connect(&mut second.borrow_mut(), "s").is_err());
+        assert!(listener.borrow_mut().accept(&process).unwrap().is_some());
+
+        // Closing a listening socket fails its acceptors, and releases its name
+        assert!(listener.borrow_mut().accept(&process).unwrap().is_none());
+        drop(listener);
+        assert_eq!((process.borrow().status().clone(), process.borrow().result()), (ProcessStatus::Ready, -1i32 as u32));
+        sockets.bind(&mut second.borrow_mut(), "s").unwrap();
+    }
+
+    #[test]
+    fn datagram_test() {
+        let process = new_process();
+        let mut sockets = Sockets::default();
+        let (receiver, sender) = (new_socket(SOCK_DGRAM).unwrap(), new_socket(SOCK_DGRAM).unwrap());
+        sockets.bind(&mut receiver.borrow_mut(), "d").unwrap();
+        sockets.connect(&mut sender.borrow_mut(), "d").unwrap();
+        assert!(sockets.connect(&mut new_socket(SOCK_STREAM).unwrap().borrow_mut(), "d").is_err());
+        for message in [&b"first"[..], b"second"] {
+            let mut task = WriteTask::new(&process, message.as_ptr(), message.len());
+            assert_eq!(task.attempt(|x| sender.borrow_mut().write(x)), Some(message.len() as u32));
+        }
+
+        // Each read returns one datagram, truncated to fit
+        let mut buffer = [0u8; 8];
+        let mut task = ReadTask::new(&process, buffer.as_mut_ptr(), 3);
+        assert_eq!(task.attempt(|x| receiver.borrow_mut().read(x)), Some(3));
+        let mut task = ReadTask::new(&process, buffer.as_mut_ptr(), 8);
+        assert_eq!(task.attempt(|x| receiver.borrow_mut().read(x)), Some(6));
+        assert_eq!(&buffer[..6], b"second");
+        let mut task = ReadTask::new(&process, buffer.as_mut_ptr(), 8);
+        assert!(task.attempt(|x| receiver.borrow_mut().read(x)).is_none());
+    }
+
+    #[test]
+    fn pass_file_test() {
+        let (sender, receiver) = (new_process(), new_process());
+        let mut sockets = Sockets::default();
+        let listener = new_socket(SOCK_STREAM).unwrap();
+        sockets.bind(&mut listener.borrow_mut(), "s").unwrap();
+        listener.borrow_mut().listen(1).unwrap();
+        let connecting = new_socket(SOCK_STREAM).unwrap();
+        sockets.connect(&mut connecting.borrow_mut(), "s").unwrap();
+        let accepted = listener.borrow_mut().accept(&receiver).unwrap().unwrap();
+        let (pipe_read, pipe_write) = new_pipe();
+
+        // A receiver blocked in recvmsg gets the file with the data, as a new fid of its own
+        let mut buffer = [0u8; 4];
+        let mut fid = 0;
+        assert!(accepted.borrow_mut().receive_message(ReadTask::new(&receiver, buffer.as_mut_ptr(), 4), &mut fid).is_none());
+        let result = connecting.borrow_mut().send_message(WriteTask::new(&sender, b"fd".as_ptr(), 2), Some(Rc::clone(&pipe_read)));
+        assert_eq!(result.ok(), Some(Some(2)));
+        assert_eq!(*receiver.borrow().status(), ProcessStatus::Ready);
+        let received = receiver.borrow().get_file(fid).unwrap();
+        assert!(Rc::ptr_eq(&received, &pipe_read));
+
+        // The file is usable by the receiver
+        let mut task = WriteTask::new(&sender, b"!".as_ptr(), 1);
+        assert_eq!(task.attempt(|x| pipe_write.borrow_mut().write(x)), Some(1));
+        let mut task = ReadTask::new(&receiver, buffer.as_mut_ptr(), 1);
+        assert_eq!((task.attempt(|x| received.borrow_mut().read(x)), buffer[0]), (Some(1), b'!'));
+
+        // Data sent without a file gives -1
+        connecting.borrow_mut().send_message(WriteTask::new(&sender, b"no".as_ptr(), 2), None).unwrap();
+        assert_eq!(accepted.borrow_mut().receive_message(ReadTask::new(&receiver, buffer.as_mut_ptr(), 4), &mut fid), Some(2));
+        assert_eq!(fid, -1);
+
+        // Neither end of a connection can be passed over it
+        for end in [&connecting, &accepted].iter() {
+            let end = Rc::clone(*end) as StrongFileDescriptorRef;
+            assert!(connecting.borrow_mut().send_message(WriteTask::new(&sender, b"x".as_ptr(), 1), Some(end)).is_err());
+        }
+
+        // A file isn't left queued if the data can't be sent
+        drop(accepted);
+        let result = connecting.borrow_mut().send_message(WriteTask::new(&sender, b"x".as_ptr(), 1), Some(Rc::clone(&pipe_read)));
+        assert_eq!(result.ok(), Some(Some(-1i32 as u32)));
+        assert!(connecting.borrow().peer().unwrap().1.borrow().files.is_empty());
+    }
+}
@@ -10,6 +10,12 @@ pub struct TaskBase {
     length: usize,
 }
 
+impl TaskBase {
+    fn is_for(&self, process: &StrongPcbRef) -> bool {
+        self.process.as_ptr() == Rc::as_ptr(process)
+    }
+}
+
 #[derive(Debug)]
 pub struct ReadTask {
     base: TaskBase,
@@ -31,10 +37,19 @@ impl ReadTask {
         }, destination }
     }
 
+    // The process the task is for, if it still exists
+    pub fn process(&self) -> Option<StrongPcbRef> {
+        self.base.process.upgrade()
+    }
+
+    pub fn is_for(&self, process: &StrongPcbRef) -> bool {
+        self.base.is_for(process)
+    }
+
     pub fn attempt<R>(&mut self, mut reader: R) -> Option<u32>
         where R: FnMut(&mut [u8]) -> Result<IOResult, FileError>
     {
-        let process = self.base.process.upgrade();
+        let process = self.base.process.upgrade().filter(|x| !x.borrow().has_exited());
         // If the process is gone, then the task is complete
         process.map_or(Some(self.base.completed as u32), |x| {
             let mut borrow = (*x).borrow_mut();
@@ -74,10 +89,18 @@ impl WriteTask {
         }, source }
     }
 
+    pub fn process(&self) -> Option<StrongPcbRef> {
+        self.base.process.upgrade()
+    }
+
+    pub fn is_for(&self, process: &StrongPcbRef) -> bool {
+        self.base.is_for(process)
+    }
+
     pub fn attempt<W>(&mut self, mut writer: W) -> Option<u32>
         where W: FnMut(&[u8]) -> Result<IOResult, FileError>
     {
-        let process = self.base.process.upgrade();
+        let process = self.base.process.upgrade().filter(|x| !x.borrow().has_exited());
         // If the process is gone, then the task is complete
         process.map_or(Some(self.base.completed as u32), |x| {
             let mut borrow = (*x).borrow_mut();
@@ -0,0 +1,325 @@
+use crate::io::descriptor::{FileError, IOResult, read_from_buffer};
+use crate::process::PID;
+use crate::process::signal::{SIG_INT, SIG_TSTP};
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+
+// ioctl requests, must match libc.h
+pub const TTY_GET_FLAGS: u32 = 0x5401;
+pub const TTY_SET_FLAGS: u32 = 0x5402;
+pub const TTY_GET_PGRP: u32 = 0x540F;
+pub const TTY_SET_PGRP: u32 = 0x5410;
+
+// Canonical mode buffers input until a whole line is typed, and interprets the control characters
+pub const TTY_FLAG_CANONICAL: u32 = 0x01;
+pub const TTY_FLAG_ECHO: u32 = 0x02;
+
+const CTRL_C: u8 = 0x03;
+const BACKSPACE: u8 = 0x08;
+const CTRL_U: u8 = 0x15;
+const CTRL_Z: u8 = 0x1A;
+const ESCAPE: u8 = 0x1B;
+const DELETE: u8 = 0x7F;
+
+// What to do with input that arrives when the buffer is full
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OverflowPolicy {
+    DropNewest = 0,
+    DropOldest = 1,
+    FlowControl = 2,        // The device should stop receiving until there is room, otherwise drops the newest
+}
+
+impl OverflowPolicy {
+    pub fn from_u32(value: u32) -> Option<Self> {
+        match value {
+            0 => Some(OverflowPolicy::DropNewest),
+            1 => Some(OverflowPolicy::DropOldest),
+            2 => Some(OverflowPolicy::FlowControl),
+            _ => None
+        }
+    }
+}
+
+// A signal that should be sent to every process in a group
+#[derive(Debug, Clone, PartialEq)]
+pub struct TtySignal {
+    pub pgid: PID,
+    pub signal: i32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum EscapeState {
+    None,
+    Escape,         // Seen ESC
+    Sequence,       // Seen ESC [ or ESC O, waiting for the final byte
+}
+
+// Sits between a character device's interrupt handler and its readers
+#[derive(Debug)]
+pub struct LineDiscipline {
+    flags: u32,
+    foreground: Option<PID>,
+    line: Vec<u8>,              // The line currently being edited
+    ready: VecDeque<u8>,        // Input that can be given to readers
+    escape: EscapeState,
+    after_cr: bool,             // The last byte was a CR, so a LF straight after it is part of the same newline
+    capacity: usize,
+    overflow: OverflowPolicy,
+    dropped: u32,               // Bytes lost because the buffer was full
+}
+
+impl LineDiscipline {
+
+    pub fn new(capacity: usize) -> Self {
+        LineDiscipline {
+            flags: TTY_FLAG_CANONICAL | TTY_FLAG_ECHO,
+            foreground: None,
+            line: Vec::new(),
+            ready: VecDeque::new(),
+            escape: EscapeState::None,
+            after_cr: false,
+            capacity,
+            overflow: OverflowPolicy::DropNewest,
+            dropped: 0,
+        }
+    }
+
+    fn canonical(&self) -> bool { self.flags & TTY_FLAG_CANONICAL != 0 }
+
+    fn echo(&self) -> bool { self.flags & TTY_FLAG_ECHO != 0 }
+
+    fn full(&self) -> bool { self.line.len() + self.ready.len() >= self.capacity }
+
+    // Input should be paused until a reader makes room, which is only worthwhile if there is something to read
+    pub fn should_throttle(&self) -> bool {
+        let needed = if self.canonical() { 2 } else { 1 };
+        !self.ready.is_empty() && self.buffered() + needed > self.capacity
+    }
+
+    pub fn buffered(&self) -> usize { self.line.len() + self.ready.len() }
+
+    pub fn dropped(&self) -> u32 { self.dropped }
+
+    pub fn overflow(&self) -> OverflowPolicy { self.overflow }
+
+    pub fn set_overflow(&mut self, policy: OverflowPolicy) {
+        self.overflow = policy;
+    }
+
+    // Try to make space for needed more bytes, returns false if the new byte must be dropped instead
+    fn make_room(&mut self, needed: usize) -> bool {
+        while self.buffered() + needed > self.capacity {
+            let lost = if self.overflow != OverflowPolicy::DropOldest || self.buffered() == 0 {
+                0
+            } else if self.ready.is_empty() {
+                self.line.remove(0);
+                1
+            } else if self.canonical() {
+                // Completed lines are dropped whole, so a reader never gets part of one
+                let end = self.ready.iter().position(|x| *x == b'\n').map_or(self.ready.len(), |x| x + 1);
+                self.ready.drain(..end).count()
+            } else {
+                self.ready.pop_front();
+                1
+            };
+            if lost == 0 {
+                self.dropped = self.dropped.wrapping_add(1);
+                return false
+            }
+            self.dropped = self.dropped.wrapping_add(lost as u32);
+        }
+        true
+    }
+
+    pub fn set_foreground(&mut self, pgid: Option<PID>) {
+        self.foreground = pgid;
+    }
+
+    // Process an input byte, any output that should be echoed is appended to echo
+    pub fn receive(&mut self, byte: u8, echo: &mut Vec<u8>) -> Option<TtySignal> {
+        if !self.canonical() {
+            if self.make_room(1) {
+                self.ready.push_back(byte);
+                if self.echo() { echo.push(byte) }
+            }
+            return None
+        }
+
+        // Terminals such as telnet send CR LF for enter
+        let after_cr = core::mem::replace(&mut self.after_cr, byte == b'\r');
+        if byte == b'\n' && after_cr { return None }
+
+        // Swallow ANSI escape sequences such as the arrow keys, line editing only supports erasing.
+        // Anything else after an ESC is input as normal, so a stray ESC can't lose the line
+        match self.escape {
+            EscapeState::Escape if byte == b'[' || byte == b'O' => {
+                self.escape = EscapeState::Sequence;
+                return None
+            }
+            EscapeState::Escape => self.escape = EscapeState::None,
+            EscapeState::Sequence => {
+                if byte >= 0x40 && byte <= 0x7E { self.escape = EscapeState::None }
+                return None
+            }
+            EscapeState::None => {}
+        }
+
+        match byte {
+            ESCAPE => self.escape = EscapeState::Escape,
+            b'\r' | b'\n' => {
+                self.line.push(b'\n');
+                self.ready.extend(self.line.drain(..));
+                if self.echo() { echo.extend_from_slice(b"\r\n") }
+            }
+            BACKSPACE | DELETE => {
+                if self.line.pop().is_some() && self.echo() { echo.extend_from_slice(b"\x08 \x08") }
+            }
+            CTRL_U => {
+                let echo_erase = self.echo();
+                for _ in self.line.drain(..) {
+                    if echo_erase { echo.extend_from_slice(b"\x08 \x08") }
+                }
+            }
+            CTRL_C => return self.interrupt(b"^C\r\n", SIG_INT, echo),
+            CTRL_Z => return self.interrupt(b"^Z\r\n", SIG_TSTP, echo),
+            _ => {
+                // Leave room for the newline
+                if self.make_room(2) {
+                    self.line.push(byte);
+                    if self.echo() { echo.push(byte) }
+                }
+            }
+        }
+        None
+    }
+
+    // Discard the line being edited, and signal the foreground process group
+    fn interrupt(&mut self, text: &[u8], signal: i32, echo: &mut Vec<u8>) -> Option<TtySignal> {
+        self.line.clear();
+        if self.echo() { echo.extend_from_slice(text) }
+        self.foreground.map(|pgid| TtySignal { pgid, signal })
+    }
+
+    pub fn read(&mut self, buffer: &mut [u8]) -> IOResult {
+        read_from_buffer(&mut self.ready, buffer)
+    }
+
+    pub fn ioctl(&mut self, request: u32, arg: *mut u8) -> Result<u32, FileError> {
+        if arg.is_null() { return Err(FileError::UnsupportedOperation) }
+        let arg = arg as *mut u32;
+        match request {
+            TTY_GET_FLAGS => unsafe { *arg = self.flags },
+            TTY_SET_FLAGS => {
+                self.flags = unsafe { *arg };
+                // Anything half typed becomes available immediately
+                if !self.canonical() {
+                    self.ready.extend(self.line.drain(..));
+                    self.escape = EscapeState::None;
+                }
+            }
+            TTY_GET_PGRP => unsafe { *arg = self.foreground.unwrap_or(-1) as u32 },
+            TTY_SET_PGRP => {
+                let pgid = unsafe { *arg } as PID;
+                self.foreground = if pgid < 0 { None } else { Some(pgid) };
+            }
+            _ => return Err(FileError::UnsupportedOperation)
+        }
+        Ok(0)
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::io::tty::{LineDiscipline, TtySignal, OverflowPolicy, TTY_SET_FLAGS, TTY_SET_PGRP, TTY_FLAG_ECHO};
+    use crate::process::signal::SIG_INT;
+    use alloc::vec::Vec;
+
+    fn type_bytes(tty: &mut LineDiscipline, bytes: &[u8]) -> Vec<u8> {
+        let mut echo = Vec::new();
+        bytes.iter().for_each(|b| { tty.receive(*b, &mut echo); });
+        echo
+    }
+
+    fn read_all(tty: &mut LineDiscipline) -> Vec<u8> {
+        let mut buffer = [0u8; 64];
+        let result = tty.read(&mut buffer);
+        buffer[..result.bytes].to_vec()
+    }
+
+    #[test]
+    fn canonical_test() {
+        let mut tty = LineDiscipline::new(64);
+        let echo = type_bytes(&mut tty, b"lz\x7fs");
+        assert_eq!(echo, b"lz\x08 \x08s".to_vec());
+        // Nothing is readable until the line is finished
+        assert!(read_all(&mut tty).is_empty());
+        type_bytes(&mut tty, b"\x1b[D -l\r");
+        assert_eq!(read_all(&mut tty), b"ls -l\n".to_vec());
+        type_bytes(&mut tty, b"rm\x15ps\n");
+        assert_eq!(read_all(&mut tty), b"ps\n".to_vec());
+        // CR is mapped to NL, and a LF straight after it doesn't make another line
+        type_bytes(&mut tty, b"ls\r\n\r\r\n");
+        assert_eq!(read_all(&mut tty), b"ls\n\n\n".to_vec());
+    }
+
+    #[test]
+    fn escape_test() {
+        let mut tty = LineDiscipline::new(64);
+        // Cursor keys are sent as ESC [ A or ESC O A
+        type_bytes(&mut tty, b"a\x1b[Ab\x1bOAc\n");
+        assert_eq!(read_all(&mut tty), b"abc\n".to_vec());
+        // A stray ESC doesn't swallow the next byte
+        let echo = type_bytes(&mut tty, b"a\x1bb\x1b\r");
+        assert_eq!(echo, b"ab\r\n".to_vec());
+        assert_eq!(read_all(&mut tty), b"ab\n".to_vec());
+        type_bytes(&mut tty, b"\x1b\x1b[Dc\r");
+        assert_eq!(read_all(&mut tty), b"c\n".to_vec());
+    }
+
+    #[test]
+    fn raw_test() {
+        let mut tty = LineDiscipline::new(64);
+        type_bytes(&mut tty, b"ab");
+        let mut flags = 0u32;
+        tty.ioctl(TTY_SET_FLAGS, &mut flags as *mut _ as *mut u8).ok().unwrap();
+        let echo = type_bytes(&mut tty, b"\x7f\x03");
+        assert!(echo.is_empty());
+        assert_eq!(read_all(&mut tty), b"ab\x7f\x03".to_vec());
+        flags = TTY_FLAG_ECHO;
+        tty.ioctl(TTY_SET_FLAGS, &mut flags as *mut _ as *mut u8).ok().unwrap();
+        assert_eq!(type_bytes(&mut tty, b"x"), b"x".to_vec());
+    }
+
+    #[test]
+    fn signal_test() {
+        let mut tty = LineDiscipline::new(64);
+        let mut echo = Vec::new();
+        // There is no foreground group to signal yet
+        assert_eq!(tty.receive(0x03, &mut echo), None);
+        let mut pgid = 4;
+        tty.ioctl(TTY_SET_PGRP, &mut pgid as *mut _ as *mut u8).ok().unwrap();
+        type_bytes(&mut tty, b"abc");
+        assert_eq!(tty.receive(0x03, &mut echo), Some(TtySignal { pgid: 4, signal: SIG_INT }));
+        type_bytes(&mut tty, b"\n");
+        assert_eq!(read_all(&mut tty), b"\n".to_vec());
+    }
+
+    #[test]
+    fn capacity_test() {
+        let mut tty = LineDiscipline::new(4);
+        type_bytes(&mut tty, b"abcdef\n");
+        assert_eq!(read_all(&mut tty), b"abc\n".to_vec());
+        assert_eq!(tty.dropped(), 3);
+
+        tty.set_overflow(OverflowPolicy::DropOldest);
+        type_bytes(&mut tty, b"abcdef\n");
+        assert_eq!(read_all(&mut tty), b"def\n".to_vec());
+        // Completed lines are dropped whole, before the line being edited
+        type_bytes(&mut tty, b"ab\ncd");
+        assert_eq!(tty.buffered(), 2);
+        type_bytes(&mut tty, b"\n");
+        assert_eq!(read_all(&mut tty), b"cd\n".to_vec());
+        assert_eq!(tty.dropped(), 9);
+    }
+}
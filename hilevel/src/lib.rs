@@ -4,9 +4,14 @@
 #![feature(map_first_last)]
 #![feature(step_trait)]
 #![feature(asm)]
+#![cfg_attr(test, feature(test))]
 
 #[macro_use]
 extern crate alloc;
+#[macro_use]
+extern crate log;
+#[cfg(test)]
+extern crate test;
 
 #[allow(non_upper_case_globals)]
 #[allow(non_camel_case_types)]
@@ -15,23 +20,37 @@ extern crate alloc;
 mod bindings;
 
 mod allocator;
+mod fault;
+mod interrupt;
 mod io;
+mod logger;
 mod state;
 mod process;
 mod util;
 
 use core::panic::PanicInfo;
 use bindings::main_console;
-use bindings::{UART0, UART1, GICC0, GICD0, TIMER0};
-use bindings::{GIC_SOURCE_TIMER0, GIC_SOURCE_UART0, GIC_SOURCE_UART1};
+use bindings::TIMER0;
+use bindings::GIC_SOURCE_TIMER0;
 use core::slice;
 use core::fmt::Write;
 use crate::io::PL011;
-use crate::process::{ScheduleSource, Context};
+use crate::process::{ScheduleSource, Context, MLFQConfig};
+use crate::process::deadlock::DeadlockAction;
 use num_derive::FromPrimitive;
 use num_traits::FromPrimitive;
 use crate::io::tasks::{WriteTask, ReadTask};
 use crate::io::pipe::new_pipe;
+use crate::io::mqueue::{MqAttr, MQ_NAME_MAX};
+use crate::io::socket::{new_socket, SocketDescriptor, SOCK_NAME_MAX};
+use crate::io::descriptor::StrongFileDescriptorRef;
+use crate::util::read_name;
+use crate::io::descriptor::FileError;
+use crate::interrupt::InterruptManager;
+use crate::state::KernelState;
+use crate::fault::{FaultKind, FaultStatus, FaultReport};
+use crate::process::signal::{SIG_SEGV, SIG_ILL};
+use crate::process::info::{ProcessInfo, ProcessUsage, SchedulerStats};
 
 
 #[no_mangle]
@@ -39,6 +58,7 @@ use crate::io::pipe::new_pipe;
 pub extern fn hilevel_handler_rst(ctx: *mut Context) {
     unsafe { bindings::int_unable_irq(); }
     let ctx = unsafe { &mut *ctx};
+    logger::init();
     let state = state::init();
 
     unsafe {
@@ -48,19 +68,10 @@ pub extern fn hilevel_handler_rst(ctx: *mut Context) {
         (*TIMER0).Timer1Ctrl |= 0x00000040; // select periodic timer
         (*TIMER0).Timer1Ctrl |= 0x00000020; // enable          timer interrupt
         (*TIMER0).Timer1Ctrl |= 0x00000080; // enable          timer
-
-        (*UART0).IMSC       |= 0x00000010; // enable UART    (Rx) interrupt
-        (*UART0).CR          = 0x00000301; // enable UART (Tx+Rx)
-
-        (*UART1).IMSC       |= 0x00000010; // enable UART    (Rx) interrupt
-        (*UART1).CR          = 0x00000301; // enable UART (Tx+Rx)
-
-        (*GICC0).PMR          = 0x000000F0; // unmask all            interrupts
-        (*GICD0).ISENABLER1  |= 0x00003010; // enable timer (36) + UART0 (44) + UART1 (45)   interrupts
-
-        (*GICC0).CTLR         = 0x00000001; // enable GIC interface
-        (*GICD0).CTLR         = 0x00000001; // enable GIC distributor
     }
+    state.interrupt_manager.register(GIC_SOURCE_TIMER0, TIMER_PRIORITY, on_timer).unwrap();
+    state.io_manager.init(&mut state.interrupt_manager);
+    state.interrupt_manager.start();
 
     state.process_manager.create_process(main_console, state.io_manager.default_files());
     state.process_manager.dispatch(ctx, ScheduleSource::Reset);
@@ -68,37 +79,20 @@ pub extern fn hilevel_handler_rst(ctx: *mut Context) {
     unsafe { bindings::int_enable_irq(); }
 }
 
+// The timer is the highest priority so that pre-emption isn't delayed by IO
+const TIMER_PRIORITY: u8 = 0x40;
+
+fn on_timer(state: &mut KernelState, ctx: &mut Context) {
+    unsafe { (*TIMER0).Timer1IntClr = 0x01; }
+    state.process_manager.dispatch(ctx, ScheduleSource::Timer);
+}
+
 #[no_mangle]
 #[cfg(not(test))]
 pub extern fn hilevel_handler_irq(ctx: *mut Context) {
     let ctx = unsafe { &mut *ctx};
     let state = state::get();
-
-    unsafe {
-        // Read  the interrupt identifier so we know the source.
-        let id: u32 = (*GICC0).IAR;
-
-        match id {
-            GIC_SOURCE_TIMER0 => {
-                (*TIMER0).Timer1IntClr = 0x01;
-                state.process_manager.dispatch(ctx, ScheduleSource::Timer);
-            },
-            GIC_SOURCE_UART0 => {
-                let mut uart0 = state.io_manager.uart0_ro.borrow_mut();      // The UART0 FileDescriptor
-                (*uart0).on_interrupt();                                                          // Add char to the File buffer
-                state.process_manager.dispatch(ctx, ScheduleSource::Io);                         // Invoke scheduler
-            },
-            GIC_SOURCE_UART1 => {
-                let mut uart1 = state.io_manager.uart1_rw.borrow_mut();
-                (*uart1).on_interrupt();
-                state.process_manager.dispatch(ctx, ScheduleSource::Io);
-            }
-            _ => {}
-        }
-
-        // Write the interrupt identifier to signal we're done.
-        (*GICC0).EOIR = id;
-    }
+    InterruptManager::handle(state, ctx);
 }
 
 #[derive(FromPrimitive, PartialEq)]
@@ -113,10 +107,48 @@ pub enum SysCall {
     Nice = 7,
     Close = 8,
     Pipe = 9,
+    Ioctl = 10,
+    Mmap = 11,
+    SetPgid = 12,
+    Syslog = 13,
+    ProcList = 14,
+    Getrusage = 15,
+    Times = 16,
+    SchedCtl = 17,
+    Clone = 18,
+    ThreadExit = 19,
+    Futex = 20,
+    ShmCreate = 21,
+    ShmAttach = 22,
+    ShmDetach = 23,
+    Send = 24,
+    Receive = 25,
+    Reply = 26,
+    MqOpen = 27,
+    MqSend = 28,
+    MqReceive = 29,
+    Socket = 30,
+    Bind = 31,
+    Listen = 32,
+    Accept = 33,
+    Connect = 34,
+    SendMsg = 35,
+    RecvMsg = 36,
+    Deadlock = 37,
+    Wait = 38,
 }
 
 const MINUS_ONE: i32 = -1;
 
+fn get_current_file(state: &mut KernelState, fid: i32) -> Option<StrongFileDescriptorRef> {
+    state.process_manager.current_process().and_then(|x| x.borrow().get_file(fid))
+}
+
+// Run a socket system call, which fails if the file isn't a socket
+fn with_socket<T>(file: &StrongFileDescriptorRef, f: impl FnOnce(&mut SocketDescriptor) -> Result<T, FileError>) -> Result<T, FileError> {
+    file.borrow_mut().socket().ok_or(FileError::InvalidDescriptor).and_then(f)
+}
+
 #[no_mangle]
 #[cfg(not(test))]
 pub extern fn hilevel_handler_svc(ctx: *mut Context, id: u32) {
@@ -139,7 +171,10 @@ pub extern fn hilevel_handler_svc(ctx: *mut Context, id: u32) {
                         let mut task = WriteTask::new(&current, start_ptr, length);
                         match &task.attempt(|x| file.write(x) ) {
                             Some(r) => { ctx.gpr[0] = *r},          // If task completed in one attempt, then set result
-                            None => { file.add_pending_write(task) },      // Otherwise we must wait on the File to unblock
+                            None => {                                       // Otherwise we must wait on the File to unblock
+                                file.add_pending_write(task);
+                                if file.blocked_on().is_some() { state.process_manager.pipes_changed() }
+                            },
                         }
                     },
                 }
@@ -157,7 +192,10 @@ pub extern fn hilevel_handler_svc(ctx: *mut Context, id: u32) {
                         let mut task = ReadTask::new(&current, start_ptr, length);
                         match &task.attempt(|x| file.read(x) ) {
                             Some(r) => { ctx.gpr[0] = *r},         // If task completed in one attempt, then set result
-                            None => { file.add_pending_read(task) },      // Otherwise we must wait on the File to unblock
+                            None => {                                       // Otherwise we must wait on the File to unblock
+                                file.add_pending_read(task);
+                                if file.blocked_on().is_some() { state.process_manager.pipes_changed() }
+                            },
                         }
                     },
                 }
@@ -178,10 +216,16 @@ pub extern fn hilevel_handler_svc(ctx: *mut Context, id: u32) {
                 let signal = ctx.gpr[1] as i32;
                 ctx.gpr[0] = state.process_manager.signal(pid, signal).map_or(MINUS_ONE as u32, |_| 0);
             }
-            SysCall::Nice => {/* Unimplemented */}
+            SysCall::Nice => {
+                let pid = ctx.gpr[0] as i32;
+                let nice = ctx.gpr[1] as i32;
+                ctx.gpr[0] = state.process_manager.set_nice(pid, nice).map_or(MINUS_ONE as u32, |_| 0);
+            }
             SysCall::Close => {
                 let fid = ctx.gpr[0] as i32;
                 let current = state.process_manager.current_process().unwrap();
+                let file = current.borrow().get_file(fid);
+                if file.map_or(false, |x| x.borrow_mut().blocked_on().is_some()) { state.process_manager.pipes_changed() }
                 ctx.gpr[0] = current.borrow_mut().close_file(fid).map_or(MINUS_ONE as u32, |_| 0);
             }
             SysCall::Pipe => {
@@ -193,15 +237,266 @@ pub extern fn hilevel_handler_svc(ctx: *mut Context, id: u32) {
                 slice[1] = current.borrow_mut().add_file(write);
                 ctx.gpr[0] = 0;
             }
+            SysCall::Ioctl => {
+                let fid = ctx.gpr[0] as i32;
+                let request = ctx.gpr[1];
+                let arg = ctx.gpr[2] as *mut u8;
+                let current = state.process_manager.current_process().unwrap();
+                let file = current.borrow().get_file(fid);
+                ctx.gpr[0] = file.map_or(MINUS_ONE as u32, |file| {
+                    file.borrow_mut().ioctl(request, arg).unwrap_or(MINUS_ONE as u32)
+                });
+            }
+            SysCall::Mmap => {
+                let fid = ctx.gpr[0] as i32;
+                let current = state.process_manager.current_process().unwrap();
+                let file = current.borrow().get_file(fid);
+                ctx.gpr[0] = file.map_or(MINUS_ONE as u32, |file| {
+                    file.borrow_mut().mmap().map_or(MINUS_ONE as u32, |ptr| ptr as u32)
+                });
+            }
+            SysCall::SetPgid => {
+                let pid = ctx.gpr[0] as i32;
+                let pgid = ctx.gpr[1] as i32;
+                ctx.gpr[0] = state.process_manager.set_pgid(pid, pgid).map_or(MINUS_ONE as u32, |_| 0);
+            }
+            SysCall::Syslog => {
+                let action = ctx.gpr[0];
+                let start_ptr = ctx.gpr[1] as *mut u8;
+                let length = ctx.gpr[2] as usize;
+                let buffer = if start_ptr.is_null() { &mut [] } else { unsafe { slice::from_raw_parts_mut(start_ptr, length) } };
+                ctx.gpr[0] = logger::get().syslog(action, buffer, ctx.gpr[2]).unwrap_or(MINUS_ONE as u32);
+            }
+            SysCall::ProcList => {
+                let start_ptr = ctx.gpr[0] as *mut ProcessInfo;
+                let length = ctx.gpr[1] as usize;
+                let slice = unsafe { slice::from_raw_parts_mut(start_ptr, length) };
+                let list = state.process_manager.proc_list(ctx);
+                slice.iter_mut().zip(list.iter()).for_each(|(x, info)| *x = info.clone());
+                ctx.gpr[0] = list.len().min(length) as u32;
+            }
+            SysCall::Getrusage => {
+                let pid = ctx.gpr[0] as i32;
+                let usage_ptr = ctx.gpr[1] as *mut ProcessUsage;
+                ctx.gpr[0] = state.process_manager.usage(pid).map_or(MINUS_ONE as u32, |usage| {
+                    unsafe { *usage_ptr = usage };
+                    0
+                });
+            }
+            SysCall::Times => {
+                let stats_ptr = ctx.gpr[0] as *mut SchedulerStats;
+                unsafe { *stats_ptr = state.process_manager.scheduler_stats() };
+                ctx.gpr[0] = 0;
+            }
+            SysCall::SchedCtl => {
+                let request = ctx.gpr[0];
+                let config = unsafe { &mut *(ctx.gpr[1] as *mut MLFQConfig) };
+                ctx.gpr[0] = state.process_manager.sched_ctl(request, config).map_or(MINUS_ONE as u32, |_| 0);
+            }
+            SysCall::Clone => {
+                let entry = ctx.gpr[0];
+                let arg = ctx.gpr[1];
+                let exit_to = ctx.gpr[2];
+                ctx.gpr[0] = state.process_manager.clone_thread(entry, arg, exit_to).map_or(MINUS_ONE as u32, |x| x as u32);
+            }
+            SysCall::ThreadExit => {
+                let code = ctx.gpr[0];
+                state.process_manager.exit_thread(code);
+            }
+            SysCall::Futex => {
+                let address = ctx.gpr[0] as *const u32;
+                let op = ctx.gpr[1];
+                let value = ctx.gpr[2];
+                ctx.gpr[0] = state.process_manager.futex(op, address, value).unwrap_or(MINUS_ONE as u32);
+            }
+            SysCall::ShmCreate => {
+                let size = ctx.gpr[0] as usize;
+                ctx.gpr[0] = state.process_manager.shm_create(size).map_or(MINUS_ONE as u32, |id| id as u32);
+            }
+            SysCall::ShmAttach => {
+                let id = ctx.gpr[0] as i32;
+                ctx.gpr[0] = state.process_manager.shm_attach(id).map_or(MINUS_ONE as u32, |address| address as u32);
+            }
+            SysCall::ShmDetach => {
+                let address = ctx.gpr[0] as usize;
+                ctx.gpr[0] = state.process_manager.shm_detach(address).map_or(MINUS_ONE as u32, |_| 0);
+            }
+            SysCall::Send => {
+                let pid = ctx.gpr[0] as i32;
+                let message = ctx.gpr[1] as *const u8;
+                let reply = ctx.gpr[2] as *mut u8;
+                // On success the sender is blocked, and the size of the reply is returned once it arrives
+                if state.process_manager.send(pid, message, reply).is_err() { ctx.gpr[0] = MINUS_ONE as u32 }
+            }
+            SysCall::Receive => {
+                let buffer = ctx.gpr[0] as *mut u8;
+                match state.process_manager.receive(buffer) {
+                    Ok(Some(pid)) => { ctx.gpr[0] = pid as u32 },
+                    Ok(None) => {},         // Blocked until a message arrives
+                    Err(_) => { ctx.gpr[0] = MINUS_ONE as u32 },
+                }
+            }
+            SysCall::Reply => {
+                let pid = ctx.gpr[0] as i32;
+                let message = ctx.gpr[1] as *const u8;
+                ctx.gpr[0] = state.process_manager.reply(pid, message).map_or(MINUS_ONE as u32, |_| 0);
+            }
+            SysCall::MqOpen => {
+                let name = read_name(ctx.gpr[0] as *const u8, MQ_NAME_MAX);
+                let flags = ctx.gpr[1];
+                let attr_ptr = ctx.gpr[2] as *const MqAttr;
+                let attr = if attr_ptr.is_null() { None } else { Some(unsafe { *attr_ptr }) };
+                let current = state.process_manager.current_process().unwrap();
+                let file = name.ok_or(FileError::InvalidArgument).and_then(|name| state.io_manager.message_queues.open(&name, flags, attr));
+                ctx.gpr[0] = file.map_or(MINUS_ONE as u32, |file| current.borrow_mut().add_file(file) as u32);
+            }
+            SysCall::MqSend => {
+                let fid = ctx.gpr[0] as i32;
+                let start_ptr = ctx.gpr[1] as *const u8;
+                let length = ctx.gpr[2] as usize;
+                let priority = ctx.gpr[3];
+                let current = state.process_manager.current_process().unwrap();
+                let file = current.borrow().get_file(fid);
+                match file.map(|file| file.borrow_mut().send(WriteTask::new(&current, start_ptr, length), priority)) {
+                    Some(Ok(Some(r))) => { ctx.gpr[0] = r },
+                    Some(Ok(None)) => {},       // Blocked until there is room in the queue
+                    _ => { ctx.gpr[0] = MINUS_ONE as u32 },
+                }
+            }
+            SysCall::MqReceive => {
+                let fid = ctx.gpr[0] as i32;
+                let start_ptr = ctx.gpr[1] as *mut u8;
+                let length = ctx.gpr[2] as usize;
+                let priority = ctx.gpr[3] as *mut u32;
+                let current = state.process_manager.current_process().unwrap();
+                let file = current.borrow().get_file(fid);
+                match file.map(|file| file.borrow_mut().receive(ReadTask::new(&current, start_ptr, length), priority)) {
+                    Some(Ok(Some(r))) => { ctx.gpr[0] = r },
+                    Some(Ok(None)) => {},       // Blocked until a message arrives
+                    _ => { ctx.gpr[0] = MINUS_ONE as u32 },
+                }
+            }
+            SysCall::Socket => {
+                let kind = ctx.gpr[0];
+                let current = state.process_manager.current_process().unwrap();
+                ctx.gpr[0] = new_socket(kind).map_or(MINUS_ONE as u32, |socket| current.borrow_mut().add_file(socket) as u32);
+            }
+            SysCall::Bind | SysCall::Connect => {
+                let file = get_current_file(state, ctx.gpr[0] as i32);
+                let name = read_name(ctx.gpr[1] as *const u8, SOCK_NAME_MAX);
+                let sockets = &mut state.io_manager.sockets;
+                let result = file.zip(name).ok_or(FileError::InvalidArgument).and_then(|(file, name)| {
+                    with_socket(&file, |socket| if id == SysCall::Bind { sockets.bind(socket, &name) } else { sockets.connect(socket, &name) })
+                });
+                ctx.gpr[0] = result.map_or(MINUS_ONE as u32, |_| 0);
+            }
+            SysCall::Listen => {
+                let file = get_current_file(state, ctx.gpr[0] as i32);
+                let backlog = ctx.gpr[1];
+                let result = file.ok_or(FileError::InvalidDescriptor).and_then(|file| with_socket(&file, |socket| socket.listen(backlog)));
+                ctx.gpr[0] = result.map_or(MINUS_ONE as u32, |_| 0);
+            }
+            SysCall::Accept => {
+                let current = state.process_manager.current_process().unwrap();
+                let file = get_current_file(state, ctx.gpr[0] as i32);
+                match file.ok_or(FileError::InvalidDescriptor).and_then(|file| with_socket(&file, |socket| socket.accept(&current))) {
+                    Ok(Some(socket)) => { ctx.gpr[0] = current.borrow_mut().add_file(socket) as u32 },
+                    Ok(None) => {},         // Blocked until a connection arrives
+                    Err(_) => { ctx.gpr[0] = MINUS_ONE as u32 },
+                }
+            }
+            SysCall::SendMsg => {
+                let file = get_current_file(state, ctx.gpr[0] as i32);
+                let start_ptr = ctx.gpr[1] as *const u8;
+                let length = ctx.gpr[2] as usize;
+                let pass_fid = ctx.gpr[3] as i32;
+                let current = state.process_manager.current_process().unwrap();
+                // A fid of -1 sends the data alone
+                let passed = if pass_fid == MINUS_ONE { Some(None) } else { current.borrow().get_file(pass_fid).map(Some) };
+                let result = file.zip(passed).ok_or(FileError::InvalidDescriptor).and_then(|(file, passed)| {
+                    with_socket(&file, |socket| socket.send_message(WriteTask::new(&current, start_ptr, length), passed))
+                });
+                match result {
+                    Ok(Some(r)) => { ctx.gpr[0] = r },
+                    Ok(None) => {},         // Blocked until the peer has read enough
+                    Err(_) => { ctx.gpr[0] = MINUS_ONE as u32 },
+                }
+            }
+            SysCall::RecvMsg => {
+                let file = get_current_file(state, ctx.gpr[0] as i32);
+                let start_ptr = ctx.gpr[1] as *mut u8;
+                let length = ctx.gpr[2] as usize;
+                let fid_ptr = ctx.gpr[3] as *mut i32;
+                let current = state.process_manager.current_process().unwrap();
+                let result = file.ok_or(FileError::InvalidDescriptor).and_then(|file| {
+                    with_socket(&file, |socket| Ok(socket.receive_message(ReadTask::new(&current, start_ptr, length), fid_ptr)))
+                });
+                match result {
+                    Ok(Some(r)) => { ctx.gpr[0] = r },
+                    Ok(None) => {},         // Blocked until there is data
+                    Err(_) => { ctx.gpr[0] = MINUS_ONE as u32 },
+                }
+            }
+            SysCall::Deadlock => {
+                let action = DeadlockAction::new(ctx.gpr[0], ctx.gpr[1] as i32);
+                let result = action.and_then(|x| state.process_manager.set_deadlock_action(x));
+                ctx.gpr[0] = result.map_or(MINUS_ONE as u32, |_| 0);
+            }
+            SysCall::Wait => {
+                let pid = ctx.gpr[0] as i32;
+                let status = ctx.gpr[1] as *mut i32;
+                let options = ctx.gpr[2];
+                match state.process_manager.wait(pid, status, options) {
+                    Ok(Some(child)) => { ctx.gpr[0] = child as u32 },
+                    Ok(None) => {},         // Blocked until a child exits
+                    Err(_) => { ctx.gpr[0] = MINUS_ONE as u32 },
+                }
+            }
         }
         state.process_manager.dispatch(ctx, ScheduleSource::Svc {id});
     });
 }
 
+#[no_mangle]
+#[cfg(not(test))]
+pub extern fn hilevel_handler_fault(ctx: *mut Context, kind: u32) {
+    let ctx = unsafe { &mut *ctx};
+    let kind = FaultKind::from_u32(kind).unwrap();
+    let mut state = state::try_get();
+    let current = state.as_mut().and_then(|x| x.process_manager.current_process());
+    let pid = current.and_then(|x| x.try_borrow().ok().map(|x| x.pid()));
+    let report = FaultReport { kind, ctx, status: FaultStatus::read(), pid };
+
+    // Faults in the kernel, or in the idle thread (which has a negative PID), have no process to kill
+    let pid = match pid {
+        Some(pid) if report.user_mode() && pid >= 0 => pid,
+        _ => {
+            // Written directly, since the kernel log might not be going to a UART
+            writeln!(PL011::UART0(), "\n{}", report).ok();
+            panic!("{} in kernel mode", kind);
+        }
+    };
+
+    // The faulting process can't continue, so kill it and run another
+    error!("{}", report);
+    let signal = if kind == FaultKind::UndefinedInstruction { SIG_ILL } else { SIG_SEGV };
+    let state = state.unwrap();
+    // Returning to the faulting instruction would only fault again
+    if let Err(e) = state.process_manager.signal(pid, signal) {
+        panic!("Failed to kill faulting process {}: {}", pid, e);
+    }
+    state.process_manager.dispatch(ctx, ScheduleSource::Fault);
+}
+
 #[panic_handler]
 #[cfg(not(test))]
 fn handle_panic(info: &PanicInfo) -> ! {
-    writeln!(PL011::UART0(), "\n{}", info).ok();
+    // Interrupts will not drain the transmit buffer again, so write everything out now
+    let mut uart = PL011::UART0();
+    writeln!(uart, "\n{}", info).ok();
+    let (fp, pc) = fault::current_frame();
+    fault::write_backtrace(&mut uart, pc, fp).ok();
+    uart.flush();
     abort()
 }
 
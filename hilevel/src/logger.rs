@@ -0,0 +1,188 @@
+use crate::io::PL011::{UART0, UART1};
+use core::cell::UnsafeCell;
+use core::fmt::Write;
+use log::{Log, Metadata, Record, LevelFilter};
+
+const DMESG_SIZE: usize = 8192;
+
+// Syslog actions, must match libc.h
+pub const SYSLOG_READ_ALL: u32 = 0;
+pub const SYSLOG_CLEAR: u32 = 1;
+pub const SYSLOG_SET_LEVEL: u32 = 2;
+pub const SYSLOG_SET_SINK: u32 = 3;
+
+// Where messages are written as well as the dmesg buffer
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogSink {
+    Memory = 0,
+    Uart0 = 1,
+    Uart1 = 2,
+}
+
+// The most recent kernel messages, the oldest are overwritten once full
+#[derive(Debug)]
+pub struct LogBuffer {
+    data: [u8; DMESG_SIZE],
+    head: usize,
+    len: usize,
+}
+
+impl LogBuffer {
+
+    pub const fn new() -> Self {
+        LogBuffer { data: [0; DMESG_SIZE], head: 0, len: 0 }
+    }
+
+    pub fn len(&self) -> usize { self.len }
+
+    pub fn push(&mut self, byte: u8) {
+        self.data[(self.head + self.len) % DMESG_SIZE] = byte;
+        if self.len == DMESG_SIZE {
+            self.head = (self.head + 1) % DMESG_SIZE;
+        } else {
+            self.len = self.len + 1;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.head = 0;
+        self.len = 0;
+    }
+
+    // Copy out the oldest messages first, without removing them
+    pub fn copy_to(&self, buffer: &mut [u8]) -> usize {
+        let count = self.len.min(buffer.len());
+        (0..count).for_each(|i| buffer[i] = self.data[(self.head + i) % DMESG_SIZE]);
+        count
+    }
+
+}
+
+impl Write for LogBuffer {
+
+    fn write_str(&mut self, s: &str) -> Result<(), core::fmt::Error> {
+        s.as_bytes().iter().for_each(|b| self.push(*b));
+        Ok(())
+    }
+}
+
+pub struct KernelLogger {
+    buffer: UnsafeCell<LogBuffer>,
+    sink: UnsafeCell<LogSink>,
+}
+
+// Only accessed from the kernel, which is never executed concurrently, see state.rs
+unsafe impl Sync for KernelLogger {}
+
+static LOGGER: KernelLogger = KernelLogger::new();
+
+impl KernelLogger {
+
+    pub const fn new() -> Self {
+        KernelLogger {
+            buffer: UnsafeCell::new(LogBuffer::new()),
+            sink: UnsafeCell::new(LogSink::Uart0),
+        }
+    }
+
+    fn buffer(&self) -> &mut LogBuffer {
+        unsafe { &mut *self.buffer.get() }
+    }
+
+    pub fn sink(&self) -> LogSink {
+        unsafe { *self.sink.get() }
+    }
+
+    pub fn set_sink(&self, sink: LogSink) {
+        unsafe { *self.sink.get() = sink }
+    }
+
+    // Handle a Syslog call, buffer and length are only used by SYSLOG_READ_ALL, value by the setters
+    pub fn syslog(&self, action: u32, buffer: &mut [u8], value: u32) -> Result<u32, ()> {
+        match action {
+            SYSLOG_READ_ALL => Ok(self.buffer().copy_to(buffer) as u32),
+            SYSLOG_CLEAR => { self.buffer().clear(); Ok(0) }
+            SYSLOG_SET_LEVEL => {
+                let level = match value {
+                    0 => LevelFilter::Off,
+                    1 => LevelFilter::Error,
+                    2 => LevelFilter::Warn,
+                    3 => LevelFilter::Info,
+                    4 => LevelFilter::Debug,
+                    5 => LevelFilter::Trace,
+                    _ => return Err(())
+                };
+                log::set_max_level(level);
+                Ok(0)
+            }
+            SYSLOG_SET_SINK => {
+                self.set_sink(match value {
+                    0 => LogSink::Memory,
+                    1 => LogSink::Uart0,
+                    2 => LogSink::Uart1,
+                    _ => return Err(())
+                });
+                Ok(0)
+            }
+            _ => Err(())
+        }
+    }
+
+}
+
+impl Log for KernelLogger {
+
+    fn enabled(&self, _metadata: &Metadata) -> bool { true }
+
+    fn log(&self, record: &Record) {
+        writeln!(self.buffer(), "[{}] {}", record.level(), record.args()).ok();
+        #[cfg(not(test))]
+        match self.sink() {
+            LogSink::Memory => {}
+            LogSink::Uart0 => { writeln!(UART0(), "[{}] {}", record.level(), record.args()).ok(); }
+            LogSink::Uart1 => { writeln!(UART1(), "[{}] {}", record.level(), record.args()).ok(); }
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+// Debug messages, such as the context switch trace, are off until enabled with Syslog
+pub fn init() {
+    log::set_logger(&LOGGER).ok();
+    log::set_max_level(LevelFilter::Info);
+}
+
+pub fn get() -> &'static KernelLogger {
+    &LOGGER
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::logger::{KernelLogger, LogBuffer, LogSink, DMESG_SIZE, SYSLOG_READ_ALL, SYSLOG_CLEAR, SYSLOG_SET_SINK};
+    use log::{Log, Record, Level};
+
+    #[test]
+    fn buffer_test() {
+        let mut buffer = LogBuffer::new();
+        (0..DMESG_SIZE + 2).for_each(|i| buffer.push((i % 251) as u8));
+        assert_eq!(buffer.len(), DMESG_SIZE);
+        // The two oldest bytes were overwritten
+        let mut out = [0u8; 2];
+        assert_eq!(buffer.copy_to(&mut out), 2);
+        assert_eq!(out, [2, 3]);
+    }
+
+    #[test]
+    fn syslog_test() {
+        let logger = KernelLogger::new();
+        logger.syslog(SYSLOG_SET_SINK, &mut [], LogSink::Memory as u32).ok().unwrap();
+        logger.log(&Record::builder().level(Level::Info).args(format_args!("Killed {}", 3)).build());
+        let mut out = [0u8; 64];
+        let bytes = logger.syslog(SYSLOG_READ_ALL, &mut out, 0).ok().unwrap() as usize;
+        assert_eq!(&out[..bytes], b"[INFO] Killed 3\n");
+        logger.syslog(SYSLOG_CLEAR, &mut [], 0).ok().unwrap();
+        assert_eq!(logger.syslog(SYSLOG_READ_ALL, &mut out, 0).ok(), Some(0));
+        assert!(logger.syslog(SYSLOG_SET_SINK, &mut [], 9).is_err());
+    }
+}
@@ -1,5 +1,7 @@
 
 const CPSR_USR: u32 = 0x50;
+const CPSR_SVC: u32 = 0x53;             // IRQ interrupts are enabled, as kernel threads are preempted
+const CPSR_MODE: u32 = 0x1F;
 
 #[repr(C)]
 #[derive(Debug, Copy, Clone)]
@@ -23,4 +25,13 @@ impl Context {
         }
     }
 
+    // For a kernel thread, which runs in SVC mode
+    pub fn kernel(pc: u32, sp: u32) -> Context {
+        Context { cpsr: CPSR_SVC, ..Context::new(pc, sp) }
+    }
+
+    pub fn is_kernel(&self) -> bool {
+        self.cpsr & CPSR_MODE == CPSR_SVC & CPSR_MODE
+    }
+
 }
@@ -0,0 +1,94 @@
+use crate::process::{PID, TID};
+use crate::process::signal::{default_action, SignalAction};
+use alloc::collections::BTreeSet;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+// What to do about a deadlock, must match libc.h
+pub const DEADLOCK_REPORT: u32 = 0;
+pub const DEADLOCK_FAIL: u32 = 1;
+pub const DEADLOCK_SIGNAL: u32 = 2;
+
+// Returned by a read or write which was failed to break a deadlock
+pub const EDEADLK: i32 = -35;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DeadlockAction {
+    Report,             // Only log it
+    Fail,               // Fail the victim's read or write with EDEADLK
+    Signal(i32),        // Kill the victim's process
+}
+
+impl Default for DeadlockAction {
+    fn default() -> Self {
+        DeadlockAction::Report
+    }
+}
+
+impl DeadlockAction {
+    pub fn new(action: u32, signal: i32) -> Result<DeadlockAction, String> {
+        match action {
+            DEADLOCK_REPORT => Ok(DeadlockAction::Report),
+            DEADLOCK_FAIL => Ok(DeadlockAction::Fail),
+            // Stopping the victim wouldn't free anything it holds
+            DEADLOCK_SIGNAL if default_action(signal) == Some(SignalAction::Terminate) => Ok(DeadlockAction::Signal(signal)),
+            _ => Err("invalid action".to_string()),
+        }
+    }
+}
+
+// A thread blocked on a pipe end, which only one of the holders of the other end can unblock
+#[derive(Debug, Clone)]
+pub struct PipeWait {
+    pub tid: TID,
+    pub pid: PID,
+    pub holders: Vec<PID>,
+}
+
+// The waits which can never complete. A process can make progress if it has a thread which isn't
+// waiting on a pipe, or waiting on one that a process which can make progress holds the other end of.
+// Whatever is left is in a wait-for cycle, waits on an end which no process holds, or waits on them
+pub fn deadlocked(waits: &[PipeWait], progressing: &BTreeSet<PID>) -> Vec<PipeWait> {
+    let mut progressing = progressing.clone();
+    let mut stuck: Vec<&PipeWait> = waits.iter().collect();
+    loop {
+        let (free, still): (Vec<&PipeWait>, Vec<&PipeWait>) = stuck.into_iter()
+            .partition(|x| x.holders.iter().any(|pid| progressing.contains(pid)));
+        stuck = still;
+        if free.is_empty() { break }
+        free.iter().for_each(|x| { progressing.insert(x.pid); });
+    }
+    stuck.into_iter().cloned().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::process::deadlock::{deadlocked, PipeWait, DeadlockAction, DEADLOCK_SIGNAL};
+    use crate::process::signal::{SIG_TERM, SIG_TSTP};
+    use alloc::collections::BTreeSet;
+    use alloc::vec::Vec;
+
+    fn wait(pid: i32, holders: &[i32]) -> PipeWait {
+        PipeWait { tid: pid, pid, holders: holders.to_vec() }
+    }
+
+    fn stuck(waits: &[PipeWait], progressing: &[i32]) -> Vec<i32> {
+        deadlocked(waits, &progressing.iter().cloned().collect::<BTreeSet<_>>()).iter().map(|x| x.tid).collect()
+    }
+
+    #[test]
+    fn deadlocked_test() {
+        // A cycle, and a process waiting on it
+        let cycle = [wait(1, &[2]), wait(2, &[3]), wait(3, &[1]), wait(4, &[3])];
+        assert_eq!(stuck(&cycle, &[5]), vec![1, 2, 3, 4]);
+        // Any holder that can run can unblock a waiter, and so everything waiting on that waiter
+        let chain = [wait(1, &[2, 5]), wait(2, &[3]), wait(3, &[1]), wait(4, &[3])];
+        assert_eq!(stuck(&chain, &[5]), Vec::<i32>::new());
+        // Nobody holds the other end
+        assert_eq!(stuck(&[wait(1, &[]), wait(2, &[1, 3])], &[]), vec![1, 2]);
+        // Or only the waiter itself does
+        assert_eq!(stuck(&[wait(1, &[1])], &[2]), vec![1]);
+        assert_eq!(DeadlockAction::new(DEADLOCK_SIGNAL, SIG_TERM), Ok(DeadlockAction::Signal(SIG_TERM)));
+        assert!(DeadlockAction::new(DEADLOCK_SIGNAL, SIG_TSTP).is_err());
+    }
+}
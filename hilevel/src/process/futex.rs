@@ -0,0 +1,83 @@
+use crate::process::{StrongPcbRef, WeakPcbRef, ProcessStatus};
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::rc::Rc;
+
+// Futex operations, must match libc.h
+pub const FUTEX_WAIT: u32 = 0;
+pub const FUTEX_WAKE: u32 = 1;
+
+// The threads blocked on each futex word, keyed by its address, in the order they started waiting
+#[derive(Default)]
+pub struct FutexTable {
+    queues: BTreeMap<usize, VecDeque<WeakPcbRef>>,
+}
+
+impl FutexTable {
+
+    // Block a thread until the word at address is woken
+    pub fn wait(&mut self, address: usize, thread: &StrongPcbRef) {
+        thread.borrow_mut().set_blocked();
+        self.queues.entry(address).or_default().push_back(Rc::downgrade(thread));
+    }
+
+    // Unblock up to count threads waiting on address, returning how many were woken
+    pub fn wake(&mut self, address: usize, count: u32) -> u32 {
+        let mut woken = 0;
+        if let Some(queue) = self.queues.get_mut(&address) {
+            while woken < count {
+                let thread = match queue.pop_front() {
+                    Some(x) => x,
+                    None => break,
+                };
+                // Threads that have been killed since they started waiting are skipped
+                if let Some(thread) = thread.upgrade() {
+                    let mut pcb = thread.borrow_mut();
+                    if pcb.status == ProcessStatus::Blocked {
+                        pcb.set_unblocked(0);
+                        woken = woken + 1;
+                    }
+                }
+            }
+            if queue.is_empty() { self.queues.remove(&address); }
+        }
+        woken
+    }
+
+    // The number of threads waiting on address, including any that have been killed
+    #[cfg(test)]
+    pub fn waiters(&self, address: usize) -> usize {
+        self.queues.get(&address).map_or(0, |x| x.len())
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::process::futex::FutexTable;
+    use crate::process::{ProcessControlBlock, Context, ProcessStatus, StrongPcbRef};
+    use alloc::rc::Rc;
+    use alloc::vec::Vec;
+    use core::cell::RefCell;
+
+    fn new_thread() -> StrongPcbRef {
+        Rc::new(RefCell::new(ProcessControlBlock::new(0, Vec::new(), Context::new(0, 0), Default::default())))
+    }
+
+    #[test]
+    fn wake_test() {
+        let threads: Vec<StrongPcbRef> = (0..4).map(|_| new_thread()).collect();
+        let mut futexes = FutexTable::default();
+        threads.iter().for_each(|x| futexes.wait(0x100, x));
+        futexes.wait(0x200, &new_thread());
+        assert!(threads.iter().all(|x| x.borrow().status == ProcessStatus::Blocked));
+
+        // Threads are woken in the order they waited, skipping any that have gone
+        threads[1].borrow_mut().status = ProcessStatus::Terminated;
+        assert_eq!(futexes.wake(0x100, 2), 2);
+        assert_eq!(threads.iter().map(|x| x.borrow().status == ProcessStatus::Blocked).collect::<Vec<_>>(), vec![false, false, false, true]);
+        assert_eq!(futexes.wake(0x200, 1), 0);
+        assert_eq!(futexes.waiters(0x200), 0);
+        assert_eq!(futexes.wake(0x100, 5), 1);
+        assert_eq!(futexes.waiters(0x100), 0);
+    }
+}
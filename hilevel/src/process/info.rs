@@ -0,0 +1,114 @@
+use crate::process::{ProcessControlBlock, ProcessStatus, PID};
+
+// Status codes, must match libc.h
+pub const PROC_READY: u32 = 0;
+pub const PROC_EXECUTING: u32 = 1;
+pub const PROC_BLOCKED: u32 = 2;
+pub const PROC_STOPPED: u32 = 3;
+pub const PROC_EXITED: u32 = 4;
+
+// A snapshot of one process for ProcList, must match proc_info_t in libc.h
+#[repr(C)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProcessInfo {
+    pub pid: PID,
+    pub ppid: PID,              // -1 if the process has no parent
+    pub pgid: PID,
+    pub status: u32,
+    pub queue_level: i32,       // 0 is the top MLFQ queue, -1 if the process isn't scheduled
+    pub run_count: u32,         // Quanta used since it was last dispatched
+    pub ticks: u32,             // Total quanta used by every thread
+    pub fids: u32,              // Open file descriptors
+    pub stack_used: u32,
+    pub stack_size: u32,
+    pub threads: u32,
+}
+
+impl ProcessInfo {
+
+    // sp is passed separately as the saved context is out of date for the current process
+    pub fn new(pcb: &ProcessControlBlock, sp: u32, queue_level: Option<usize>, run_count: u32) -> Self {
+        let status = match pcb.status {
+            _ if pcb.stopped => PROC_STOPPED,
+            ProcessStatus::Ready => PROC_READY,
+            ProcessStatus::Executing => PROC_EXECUTING,
+            ProcessStatus::Blocked => PROC_BLOCKED,
+            ProcessStatus::Exited | ProcessStatus::Terminated => PROC_EXITED,
+        };
+        let stack_used = pcb.stack.last()
+            .map_or(0, |tos| (tos as *const u8 as u32).saturating_sub(sp));
+        ProcessInfo {
+            pid: pcb.pid,
+            ppid: pcb.parent.unwrap_or(-1),
+            pgid: pcb.pgid,
+            status,
+            queue_level: queue_level.map_or(-1, |x| x as i32),
+            run_count,
+            ticks: pcb.usage.user_ticks,
+            fids: pcb.file_descriptors.borrow().len() as u32,
+            stack_used,
+            stack_size: pcb.stack.len() as u32,
+            threads: 1,
+        }
+    }
+
+}
+
+// CPU accounting for one process, in timer ticks, must match rusage_t in libc.h
+#[repr(C)]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProcessUsage {
+    pub user_ticks: u32,                // Spent executing
+    pub ready_ticks: u32,               // Spent waiting to be scheduled
+    pub blocked_ticks: u32,             // Spent blocked or stopped
+    pub voluntary_switches: u32,        // Switched out after yielding, blocking or exiting
+    pub involuntary_switches: u32,      // Switched out by preemption or a signal
+    pub demotions: u32,                 // Moves to a lower MLFQ queue
+    pub boosts: u32,                    // Moves to the top MLFQ queue by a priority boost
+}
+
+// Totals for the whole scheduler, must match sched_stats_t in libc.h
+#[repr(C)]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SchedulerStats {
+    pub ticks: u32,
+    pub idle_ticks: u32,
+    pub voluntary_switches: u32,
+    pub involuntary_switches: u32,
+    pub demotions: u32,
+    pub boosts: u32,                    // Priority boosts, each of which may move many processes
+}
+
+// When a waiting process started waiting, so that it can be charged once it stops instead of on every tick
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WaitingSince {
+    Ready(u32),
+    Blocked(u32),           // Or stopped
+}
+
+impl ProcessUsage {
+
+    pub fn charge(&mut self, since: WaitingSince, now: u32) {
+        match since {
+            WaitingSince::Ready(tick) => self.ready_ticks = self.ready_ticks.wrapping_add(now.wrapping_sub(tick)),
+            WaitingSince::Blocked(tick) => self.blocked_ticks = self.blocked_ticks.wrapping_add(now.wrapping_sub(tick)),
+        }
+    }
+
+    // Record the process being switched out, in both its own and the scheduler's counters
+    pub fn switched_out(&mut self, stats: &mut SchedulerStats, voluntary: bool) {
+        if voluntary {
+            self.voluntary_switches = self.voluntary_switches.wrapping_add(1);
+            stats.voluntary_switches = stats.voluntary_switches.wrapping_add(1);
+        } else {
+            self.involuntary_switches = self.involuntary_switches.wrapping_add(1);
+            stats.involuntary_switches = stats.involuntary_switches.wrapping_add(1);
+        }
+    }
+
+    pub fn demoted(&mut self, stats: &mut SchedulerStats) {
+        self.demotions = self.demotions.wrapping_add(1);
+        stats.demotions = stats.demotions.wrapping_add(1);
+    }
+
+}
@@ -0,0 +1,42 @@
+use crate::process::WeakPcbRef;
+use alloc::collections::VecDeque;
+#[cfg(not(test))]
+use crate::state::{self, KernelState};
+
+// Kernel threads wait on one of these until something, such as an interrupt handler, has work for them
+#[derive(Default)]
+pub struct KernelWaitQueue {
+    threads: VecDeque<WeakPcbRef>,
+}
+
+impl KernelWaitQueue {
+
+    pub fn push(&mut self, thread: WeakPcbRef) {
+        self.threads.push_back(thread);
+    }
+
+    // Wake every waiting thread that is still alive, returns how many were woken
+    #[allow(dead_code)]
+    pub fn wake_all(&mut self) -> usize {
+        self.threads.drain(..)
+            .filter_map(|x| x.upgrade())
+            .map(|x| x.borrow_mut().set_unblocked(0))
+            .count()
+    }
+
+}
+
+// Kernel threads can't make service calls, so this is how they give up the CPU. block is run with IRQs
+// masked, and should call ProcessManager::block_kernel_thread if there is nothing to do. A masked IRQ
+// still ends the wfi, then is taken once IRQs are unmasked, and the scheduler switches away from a
+// blocked thread until it is woken. So a wake up between the two can't be missed, and if the thread
+// didn't block it has only waited for the next interrupt
+#[cfg(not(test))]
+#[allow(dead_code)]
+pub fn kernel_wait(block: impl FnOnce(&mut KernelState)) {
+    unsafe { crate::bindings::int_unable_irq(); }
+    block(state::get());
+    #[cfg(target_arch = "arm")]
+    unsafe { asm!("wfi"); }
+    unsafe { crate::bindings::int_enable_irq(); }
+}
@@ -0,0 +1,91 @@
+use crate::process::{StrongPcbRef, PID};
+use crate::io::tasks::ReadTask;
+use crate::io::descriptor::{IOResult, FileError};
+use alloc::collections::VecDeque;
+use alloc::string::String;
+use core::slice;
+
+// Every message and reply is this size, must match libc.h
+pub const MSG_BYTES: usize = 64;
+
+// A thread blocked in send, its reply task copies the reply back and unblocks it
+struct PendingSend {
+    pid: PID,
+    message: *const u8,
+    reply: ReadTask,
+}
+
+// The messages sent to one process, which any of its threads can receive
+#[derive(Default)]
+pub struct Endpoint {
+    senders: VecDeque<PendingSend>,         // Waiting to be received
+    receivers: VecDeque<ReadTask>,          // Threads blocked in receive
+    replies: VecDeque<PendingSend>,         // Received, waiting for a reply
+}
+
+// Copy a message into a task's buffer, unblocking its thread
+fn deliver(task: &mut ReadTask, message: *const u8) -> Option<u32> {
+    let message = unsafe { slice::from_raw_parts(message, MSG_BYTES) };
+    task.attempt(|buffer| {
+        buffer.copy_from_slice(message);
+        Ok(IOResult { bytes: MSG_BYTES, blocked: false })
+    })
+}
+
+// A task whose thread hasn't been killed since it blocked
+fn live(task: &ReadTask) -> bool {
+    task.process().map_or(false, |x| !x.borrow().has_exited())
+}
+
+impl Endpoint {
+
+    // Block the sender until its message has been received and replied to
+    pub fn send(&mut self, sender: &StrongPcbRef, message: *const u8, reply: *mut u8) {
+        let pid = sender.borrow().pid;
+        sender.borrow_mut().set_blocked();
+        self.senders.push_back(PendingSend { pid, message, reply: ReadTask::new(sender, reply, MSG_BYTES) });
+        while let Some(mut receiver) = self.receivers.pop_front() {
+            if live(&receiver) { self.receive_from(&mut receiver); break }
+        }
+    }
+
+    // The pid of the first sender if there is one, otherwise the receiver is blocked until there is
+    pub fn receive(&mut self, receiver: &StrongPcbRef, buffer: *mut u8) -> Option<u32> {
+        let mut task = ReadTask::new(receiver, buffer, MSG_BYTES);
+        let pid = self.receive_from(&mut task);
+        if pid.is_none() {
+            receiver.borrow_mut().set_blocked();
+            self.receivers.push_back(task);
+        }
+        pid
+    }
+
+    // Copy the first live sender's message to the receiver, which returns the sender's pid
+    fn receive_from(&mut self, receiver: &mut ReadTask) -> Option<u32> {
+        while let Some(sender) = self.senders.pop_front() {
+            if !live(&sender.reply) { continue }
+            deliver(receiver, sender.message);
+            let pid = sender.pid as u32;
+            receiver.process().map(|x| x.borrow_mut().context.gpr[0] = pid);
+            self.replies.push_back(sender);
+            return Some(pid)
+        }
+        None
+    }
+
+    // Unblock the first received sender from pid, its send returns the size of the reply
+    pub fn reply(&mut self, pid: PID, message: *const u8) -> Result<(), String> {
+        let index = self.replies.iter().position(|x| x.pid == pid).ok_or("not waiting for a reply")?;
+        let mut sender = self.replies.remove(index).unwrap();
+        if live(&sender.reply) { deliver(&mut sender.reply, message); }
+        Ok(())
+    }
+
+    // The process has gone, so fail every send to it
+    pub fn close(&mut self) {
+        for mut sender in self.senders.drain(..).chain(self.replies.drain(..)) {
+            if live(&sender.reply) { sender.reply.attempt(|_| Err(FileError::InvalidDescriptor)); }
+        }
+    }
+
+}
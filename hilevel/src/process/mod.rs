@@ -1,28 +1,77 @@
 mod scheduler;
 mod context;
+pub mod signal;
+pub mod info;
+pub mod futex;
+pub mod shm;
+pub mod message;
+pub mod deadlock;
+pub mod kernel;
+#[cfg(test)]
+pub mod testing;
 
 pub use context::Context;
+pub use scheduler::MLFQConfig;
 
-use crate::SysCall;
-use crate::io::PL011::{UART0};
-use core::fmt::Write;
+use crate::{SysCall, MINUS_ONE};
 use alloc::string::{ToString, String};
 use alloc::vec::Vec;
 use alloc::rc::{Rc, Weak};
 use core::cell::RefCell;
-use crate::process::scheduler::MLFQScheduler;
+use crate::process::scheduler::{Scheduler, SchedulerKind, Parked, new_scheduler, SCHED_GET_MLFQ, SCHED_SET_MLFQ};
+use alloc::boxed::Box;
 use crate::util::IdTable;
 use crate::io::descriptor::StrongFileDescriptorRef;
+use crate::process::signal::{default_action, SignalAction, SIGNAL_EXIT_BASE};
+use crate::process::info::{ProcessInfo, ProcessUsage, SchedulerStats, WaitingSince};
+use crate::process::futex::{FutexTable, FUTEX_WAIT, FUTEX_WAKE};
+use crate::process::shm::{SharedMemory, SharedShmTable, ShmId};
+use crate::process::message::Endpoint;
+use crate::process::deadlock::{deadlocked, DeadlockAction, PipeWait, EDEADLK};
+use crate::process::kernel::KernelWaitQueue;
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::collections::VecDeque;
 
 pub type PID = i32;
+pub type TID = i32;
 pub type FidTable = IdTable<i32, StrongFileDescriptorRef>;
+pub type SharedFidTable = Rc<RefCell<FidTable>>;
+
+// waitpid options, must match libc.h
+pub const WAIT_NOHANG: u32 = 0x01;
 
 const DEFAULT_STACK_BYTES: usize = 0x00001000; // = 4 KiB
+const KERNEL_STACK_BYTES: usize = 0x00000400; // = 1 KiB, kernel threads only run kernel code
+
+// Lower nice values are given more CPU time by the schedulers which support it
+pub const NICE_MIN: i32 = -20;
+pub const NICE_MAX: i32 = 19;
 
-#[derive(Default)]
+// Every process is a group of one or more threads sharing a pid, file table, shared memory and address space
 pub struct ProcessManager {
-    table: IdTable<PID, StrongPcbRef>,
-    scheduler: MLFQScheduler,
+    table: IdTable<PID, StrongPcbRef>,      // One live thread of each process, the first unless it has exited
+    threads: IdTable<TID, StrongPcbRef>,
+    futexes: FutexTable,
+    shared_memory: SharedMemory,
+    endpoints: BTreeMap<PID, Endpoint>,     // Created when a process first sends or receives
+    deadlock_action: DeadlockAction,
+    deadlocked: BTreeSet<TID>,              // Already reported
+    deadlock_check_due: bool,
+    waiting: Vec<Waiter>,
+    scheduler: Box<dyn Scheduler>,
+}
+
+// A thread blocked in waitpid, for pid or any child if it is -1
+struct Waiter {
+    thread: WeakPcbRef,
+    pid: PID,
+    status: *mut i32,
+}
+
+impl Default for ProcessManager {
+    fn default() -> Self {
+        ProcessManager::new(SchedulerKind::default())
+    }
 }
 
 #[derive(Clone, PartialEq, Debug)]
@@ -39,18 +88,35 @@ pub enum ScheduleSource {
     Timer,
     Reset,
     Io,
+    Fault,
 }
 
 pub type StrongPcbRef = Rc<RefCell<ProcessControlBlock>>;
 pub type WeakPcbRef = Weak<RefCell<ProcessControlBlock>>;
 
+// Blocked threads which have become ready, for the scheduler to move back to its run queue
+pub type WakeQueue = Rc<RefCell<VecDeque<WeakPcbRef>>>;
+
+// One thread, which is the unit that is scheduled
 #[derive(Debug)]
 pub struct ProcessControlBlock {
     pid: PID,
+    tid: TID,
+    pgid: PID,
+    parent: Option<PID>,
     status: ProcessStatus,
+    stopped: bool,      // Stopped processes are not scheduled, regardless of their status
+    nice: i32,
     stack: Vec<u8>,
     context: Context,
-    file_descriptors: FidTable,
+    exit_status: i32,                       // Once the process has exited, for its parent to wait for
+    file_descriptors: SharedFidTable,       // Shared by every thread of the process
+    shm_segments: SharedShmTable,           // Likewise
+    usage: ProcessUsage,
+    waiting_since: Option<WaitingSince>,    // Only set by schedulers which don't charge waiting processes every tick
+    queue_slot: Option<usize>,              // Index into the MultiLevelQueue, if the process is in it
+    parked: Option<Parked>,                 // Set while the MLFQ is keeping the process off its queues
+    wake_queue: Option<(WakeQueue, WeakPcbRef)>,    // Where to put this thread when it becomes ready
 }
 
 impl ProcessControlBlock {
@@ -62,13 +128,44 @@ impl ProcessControlBlock {
         // assert!(context.sp >= bos as u32);
         ProcessControlBlock{
             pid,
+            tid: pid,
+            pgid: pid,
+            parent: None,
             status: ProcessStatus::Ready,
+            stopped: false,
+            nice: 0,
             stack,
             context,
-            file_descriptors,
+            exit_status: 0,
+            file_descriptors: Rc::new(RefCell::new(file_descriptors)),
+            shm_segments: Default::default(),
+            usage: Default::default(),
+            waiting_since: None,
+            queue_slot: None,
+            parked: None,
+            wake_queue: None,
         }
     }
 
+    pub fn pid(&self) -> PID {
+        self.pid
+    }
+
+    pub fn status(&self) -> &ProcessStatus {
+        &self.status
+    }
+
+    // Exited processes are zombies until they are waited for, and have no threads, files or memory
+    pub fn has_exited(&self) -> bool {
+        self.status == ProcessStatus::Exited || self.status == ProcessStatus::Terminated
+    }
+
+    // What a completed syscall returned
+    #[cfg(test)]
+    pub fn result(&self) -> u32 {
+        self.context.gpr[0]
+    }
+
     // A syscall that hasn't completed should call this
     pub fn set_blocked(&mut self) {
         self.status = ProcessStatus::Blocked;
@@ -77,21 +174,23 @@ impl ProcessControlBlock {
     pub fn set_unblocked(&mut self, result: u32) {
         if self.status == ProcessStatus::Blocked {
             self.status = ProcessStatus::Ready;
+            self.wake_queue.as_ref().map(|(queue, this)| queue.borrow_mut().push_back(Weak::clone(this)));
         }
         self.context.gpr[0] = result;
     }
 
     pub fn get_file(&self, fid: i32) -> Option<StrongFileDescriptorRef> {
-        self.file_descriptors.get(&fid).map(|x| Rc::clone(x))
+        self.file_descriptors.borrow().get(&fid).map(|x| Rc::clone(x))
     }
 
     pub fn close_file(&mut self, fid: i32) -> Result<(), String> {
-        self.file_descriptors.remove(&fid).map(|_| ()).ok_or("invalid fid".to_string())
+        self.file_descriptors.borrow_mut().remove(&fid).map(|_| ()).ok_or("invalid fid".to_string())
     }
 
     pub fn add_file(&mut self, file: StrongFileDescriptorRef) -> i32 {
-        let fid = self.file_descriptors.new_key().unwrap();
-        self.file_descriptors.insert(fid, file);
+        let mut table = self.file_descriptors.borrow_mut();
+        let fid = table.new_key().unwrap();
+        table.insert(fid, file);
         fid
     }
 
@@ -99,30 +198,237 @@ impl ProcessControlBlock {
 
 impl ProcessManager {
 
+    pub fn new(kind: SchedulerKind) -> Self {
+        ProcessManager {
+            table: Default::default(),
+            threads: Default::default(),
+            futexes: Default::default(),
+            shared_memory: Default::default(),
+            endpoints: Default::default(),
+            deadlock_action: Default::default(),
+            deadlocked: Default::default(),
+            deadlock_check_due: false,
+            waiting: Vec::new(),
+            scheduler: new_scheduler(kind),
+        }
+    }
+
     // Create a new process
     pub fn create_process(&mut self, main: unsafe extern fn(), file_descriptors: FidTable) -> PID {
-        let pid = self.table.new_key().unwrap();
         let stack = uninit_bytes(DEFAULT_STACK_BYTES);
         let tos = stack.last().unwrap() as *const _;         // last() because the stack grows downwards from higher -> lower addresses
-        let pcb = ProcessControlBlock::new(pid, stack, Context::new(main as u32, tos as u32), file_descriptors);
+        self.start_process(stack, Context::new(main as u32, tos as u32), file_descriptors)
+    }
+
+    // Create a process with a single thread that runs in SVC mode, for background work in the kernel.
+    // It is preempted like any other thread, but must not make service calls, so it waits for work with
+    // kernel_wait, and must disable interrupts while it uses the kernel state as the kernel isn't reentrant
+    #[allow(dead_code)]
+    pub fn spawn_kernel_thread(&mut self, main: extern fn() -> !) -> PID {
+        let stack = uninit_bytes(KERNEL_STACK_BYTES);
+        let tos = stack.last().unwrap() as *const _;
+        self.start_process(stack, Context::kernel(main as u32, tos as u32), FidTable::default())
+    }
+
+    // Block the current thread, which must be a kernel thread, until queue is woken. It keeps running
+    // until the next interrupt, see kernel_wait
+    #[allow(dead_code)]
+    pub fn block_kernel_thread(&mut self, queue: &mut KernelWaitQueue) -> Result<(), String> {
+        let current = self.current_process().ok_or("no current process")?;
+        if !current.borrow().context.is_kernel() { return Err("not a kernel thread".to_string()) }
+        current.borrow_mut().set_blocked();
+        queue.push(Rc::downgrade(&current));
+        Ok(())
+    }
+
+    fn start_process(&mut self, stack: Vec<u8>, context: Context, file_descriptors: FidTable) -> PID {
+        let pid = self.table.new_key().unwrap();
+        let mut pcb = ProcessControlBlock::new(pid, stack, context, file_descriptors);
+        pcb.tid = self.threads.new_key().unwrap();
         let process = Rc::new(RefCell::new(pcb));
         self.table.insert(pid, Rc::clone(&process));
-        self.scheduler.insert_process(Rc::clone(&process));
+        self.add_thread(process);
         pid
     }
 
-    // Signals sending not implemented, just does SIGKILL regardless of code
-    pub fn signal(&mut self, pid: PID, _signal: i32) -> Result<(), String> {
-        let x = self.table.remove(&pid).ok_or("PID not found")?;
-        let mut borrow = x.borrow_mut();
-        borrow.status = ProcessStatus::Terminated;
-        self.table.remove(&borrow.pid);
-        self.scheduler.remove_process(&x);
-        write!(UART0(), "[Killed {}]", borrow.pid).ok();
+    fn add_thread(&mut self, thread: StrongPcbRef) {
+        let tid = thread.borrow().tid;
+        self.threads.insert(tid, Rc::clone(&thread));
+        self.scheduler.insert_process(thread);
+    }
+
+    // Every live thread of a process
+    fn thread_group(&self, pid: PID) -> Vec<StrongPcbRef> {
+        self.threads.values().filter(|x| x.borrow().pid == pid).map(|x| Rc::clone(x)).collect()
+    }
+
+    // Terminate threads of a process other than the current one, which is left for the caller
+    fn terminate_threads(&mut self, pid: PID, keep: Option<&StrongPcbRef>) {
+        for thread in self.thread_group(pid) {
+            if keep.map_or(false, |x| Rc::ptr_eq(x, &thread)) { continue }
+            let tid = thread.borrow().tid;
+            thread.borrow_mut().status = ProcessStatus::Terminated;
+            self.threads.remove(&tid);
+            self.scheduler.remove_process(&thread);
+        }
+    }
+
+    // Free everything a process holds, other than the current thread which the scheduler switches away
+    // from. It is then left as a zombie for its parent to wait for, or removed if its parent has exited
+    fn release(&mut self, pid: PID, status: ProcessStatus, exit_status: i32, current: Option<&StrongPcbRef>) {
+        let process = match self.table.get(&pid) {
+            Some(x) => Rc::clone(x),
+            None => return,
+        };
+        let threads = self.thread_group(pid);
+        // Queued reads and writes would otherwise be completed into the freed stacks
+        let files = core::mem::take(&mut *process.borrow().file_descriptors.borrow_mut());
+        files.values().for_each(|file| threads.iter().for_each(|x| file.borrow_mut().cancel_blocked(x)));
+        self.terminate_threads(pid, current);
+        for thread in threads.iter() {
+            let mut pcb = thread.borrow_mut();
+            pcb.status = status.clone();
+            pcb.stack = Vec::new();
+            self.threads.remove(&pcb.tid);
+        }
+        process.borrow().shm_segments.borrow_mut().clear();
+        self.endpoints.remove(&pid).map(|mut x| x.close());
+        // Closing the files wakes processes waiting on the other ends of pipes and sockets
+        drop(files);
+        self.deadlock_check_due = true;
+
+        // Exited children can't be waited for once their parent has exited
+        let children: Vec<PID> = self.table.values()
+            .filter(|x| { let x = x.borrow(); x.parent == Some(pid) && x.has_exited() })
+            .map(|x| x.borrow().pid)
+            .collect();
+        children.iter().for_each(|x| { self.table.remove(x); });
+        // Live children are orphaned, so a new process given this pid can't wait for them
+        self.threads.values().filter(|x| x.borrow().parent == Some(pid)).for_each(|x| x.borrow_mut().parent = None);
+        process.borrow_mut().exit_status = exit_status;
+        match process.borrow().parent.and_then(|x| self.table.get(&x)) {
+            Some(parent) if !parent.borrow().has_exited() => {}
+            _ => { self.table.remove(&pid); }
+        }
+        self.wake_waiting();
+    }
+
+    // Remove an exited child of parent, or of pid if it isn't -1, and return its pid and exit status
+    fn reap(&mut self, parent: PID, pid: PID) -> Option<(PID, i32)> {
+        let child = self.table.values().find(|x| {
+            let x = x.borrow();
+            x.parent == Some(parent) && (pid == -1 || x.pid == pid) && x.has_exited()
+        }).map(|x| (x.borrow().pid, x.borrow().exit_status))?;
+        self.table.remove(&child.0);
+        Some(child)
+    }
+
+    // Complete the waits of threads with a child that has exited
+    fn wake_waiting(&mut self) {
+        for waiter in core::mem::take(&mut self.waiting) {
+            let thread = match waiter.thread.upgrade() {
+                Some(x) if *x.borrow().status() == ProcessStatus::Blocked => x,
+                _ => continue,
+            };
+            let parent = thread.borrow().pid;
+            match self.reap(parent, waiter.pid) {
+                Some((child, status)) => {
+                    if !waiter.status.is_null() { unsafe { *waiter.status = status } }
+                    thread.borrow_mut().set_unblocked(child as u32);
+                }
+                // Another thread of the parent reaped the child first
+                None if !self.has_child(parent, waiter.pid) => thread.borrow_mut().set_unblocked(MINUS_ONE as u32),
+                None => self.waiting.push(waiter),
+            }
+        }
+    }
+
+    // Whether parent has a child, or one with pid if it isn't -1, including exited children not yet reaped
+    fn has_child(&self, parent: PID, pid: PID) -> bool {
+        self.table.values().any(|x| x.borrow().parent == Some(parent) && (pid == -1 || x.borrow().pid == pid))
+    }
+
+    // The pid of an exited child, or of pid if it isn't -1, with its exit status stored at status. None
+    // if the current thread has been blocked until one exits, or Some(0) if it would block with WAIT_NOHANG
+    pub fn wait(&mut self, pid: PID, status: *mut i32, options: u32) -> Result<Option<PID>, String> {
+        let current = self.current_process().ok_or("no current process")?;
+        let parent = current.borrow().pid;
+        if !self.has_child(parent, pid) { return Err("no such child".to_string()) }
+        if let Some((child, exit_status)) = self.reap(parent, pid) {
+            if !status.is_null() { unsafe { *status = exit_status } }
+            self.wake_waiting();
+            return Ok(Some(child))
+        }
+        if options & WAIT_NOHANG != 0 { return Ok(Some(0)) }
+        current.borrow_mut().set_blocked();
+        self.waiting.push(Waiter { thread: Rc::downgrade(&current), pid, status });
+        Ok(None)
+    }
+
+    // The process with pid, unless it has exited
+    fn live_process(&self, pid: PID) -> Option<StrongPcbRef> {
+        self.table.get(&pid).filter(|x| !x.borrow().has_exited()).map(|x| Rc::clone(x))
+    }
+
+    // There are no signal handlers, so signals can only stop, continue or kill every thread of a process
+    pub fn signal(&mut self, pid: PID, signal: i32) -> Result<(), String> {
+        let process = self.live_process(pid).ok_or("PID not found")?;
+        if process.borrow().context.is_kernel() { return Err("permission denied".to_string()) }
+        match default_action(signal).ok_or("invalid signal")? {
+            SignalAction::Terminate => {
+                self.release(pid, ProcessStatus::Terminated, SIGNAL_EXIT_BASE + signal, None);
+                info!("Killed {}", pid);
+            }
+            SignalAction::Stop => {
+                for thread in self.thread_group(pid) {
+                    thread.borrow_mut().stopped = true;
+                    self.scheduler.status_changed(&thread);
+                }
+                info!("Stopped {}", pid);
+            }
+            SignalAction::Continue => {
+                for thread in self.thread_group(pid) {
+                    thread.borrow_mut().stopped = false;
+                    self.scheduler.status_changed(&thread);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Signal every process in a group, returns an error if the group is empty
+    pub fn signal_group(&mut self, pgid: PID, signal: i32) -> Result<(), String> {
+        let members: Vec<PID> = self.table.values()
+            .filter(|x| x.borrow().pgid == pgid && !x.borrow().has_exited())
+            .map(|x| x.borrow().pid)
+            .collect();
+        if members.is_empty() { return Err("process group not found".to_string()) }
+        members.into_iter().for_each(|pid| { self.signal(pid, signal).ok(); });
         Ok(())
     }
 
-    // Forks current process, returns the child PID
+    // A pid of 0 means the current process, and a pgid of 0 means use the pid as the pgid
+    pub fn set_pgid(&mut self, pid: PID, pgid: PID) -> Result<(), String> {
+        if pgid < 0 { return Err("invalid pgid".to_string()) }
+        let process = if pid == 0 {
+            self.current_process().ok_or("no current process")?
+        } else {
+            self.live_process(pid).ok_or("PID not found")?
+        };
+        let pid = process.borrow().pid;
+        let pgid = if pgid == 0 { pid } else { pgid };
+        self.thread_group(pid).iter().for_each(|x| x.borrow_mut().pgid = pgid);
+        Ok(())
+    }
+
+    // Values outside of NICE_MIN to NICE_MAX are clamped
+    pub fn set_nice(&mut self, pid: PID, nice: i32) -> Result<(), String> {
+        self.live_process(pid).ok_or("PID not found")?;
+        self.thread_group(pid).iter().for_each(|x| x.borrow_mut().nice = nice.max(NICE_MIN).min(NICE_MAX));
+        Ok(())
+    }
+
+    // Forks the current thread into a new single threaded process, returns the child PID
     pub fn fork(&mut self, ctx: &Context) -> PID {
         let current = self.scheduler.current_process().unwrap();
         let borrowed = current.borrow();
@@ -132,38 +438,285 @@ impl ProcessManager {
         let mut new_ctx = ctx.clone();
         new_ctx.sp = remapped_sp;
         new_ctx.gpr[0] = 0;
-        let pcb = ProcessControlBlock::new(new_pid, new_stack, new_ctx, borrowed.file_descriptors.clone());
+        let mut pcb = ProcessControlBlock::new(new_pid, new_stack, new_ctx, borrowed.file_descriptors.borrow().clone());
+        pcb.tid = self.threads.new_key().unwrap();
+        pcb.pgid = borrowed.pgid;
+        pcb.nice = borrowed.nice;
+        pcb.parent = Some(borrowed.pid);
+        pcb.shm_segments = Rc::new(RefCell::new(borrowed.shm_segments.borrow().clone()));
         let process = Rc::new(RefCell::new(pcb));
         self.table.insert(new_pid, Rc::clone(&process));
-        self.scheduler.insert_process(Rc::clone(&process));
+        self.add_thread(process);
         return new_pid
     }
 
-    // Change current process to new executable address
+    // Start a thread in the current process with its own stack, which calls entry(arg) then returns to exit_to
+    pub fn clone_thread(&mut self, entry: u32, arg: u32, exit_to: u32) -> Result<TID, String> {
+        let current = self.scheduler.current_process().ok_or("no current process")?;
+        let borrowed = current.borrow();
+        let tid = self.threads.new_key().ok_or("too many threads")?;
+        let stack = uninit_bytes(DEFAULT_STACK_BYTES);
+        let tos = stack.last().unwrap() as *const _;
+        let mut ctx = Context::new(entry, tos as u32);
+        ctx.gpr[0] = arg;
+        ctx.lr = exit_to;
+        let mut pcb = ProcessControlBlock::new(borrowed.pid, stack, ctx, FidTable::default());
+        pcb.tid = tid;
+        pcb.pgid = borrowed.pgid;
+        pcb.nice = borrowed.nice;
+        pcb.parent = borrowed.parent;
+        pcb.file_descriptors = Rc::clone(&borrowed.file_descriptors);
+        pcb.shm_segments = Rc::clone(&borrowed.shm_segments);
+        drop(borrowed);
+        self.add_thread(Rc::new(RefCell::new(pcb)));
+        Ok(tid)
+    }
+
+    // Change current process to new executable address, any other threads are terminated
     pub fn exec(&mut self, ctx: &mut Context, address: u32) {
         let current = self.scheduler.current_process().unwrap();
+        let pid = current.borrow().pid;
+        self.terminate_threads(pid, Some(&current));
+        self.table.insert(pid, Rc::clone(&current));
         let borrowed = current.borrow_mut();
         let tos = borrowed.stack.last().unwrap() as *const _;
         *ctx = Context::new(address, tos as u32);
     }
 
-    // Exits current process
-    pub fn exit(&mut self, _code: u32) {
+    // Exits current process, along with all of its threads
+    pub fn exit(&mut self, code: u32) {
         let current = self.scheduler.current_process().unwrap();
-        let mut borrowed = current.borrow_mut();
-        borrowed.status = ProcessStatus::Exited;
-        self.table.remove(&borrowed.pid);
-        write!(UART0(), "[{} Exited]", borrowed.pid).ok();
+        let pid = current.borrow().pid;
+        self.release(pid, ProcessStatus::Exited, code as i32, Some(&current));
+        info!("{} Exited", pid);
+    }
+
+    // Exits only the current thread, the process exits once its last thread has
+    pub fn exit_thread(&mut self, code: u32) {
+        let current = self.scheduler.current_process().unwrap();
+        let (pid, tid) = { let x = current.borrow(); (x.pid, x.tid) };
+        let others: Vec<StrongPcbRef> = self.thread_group(pid).into_iter().filter(|x| !Rc::ptr_eq(x, &current)).collect();
+        if others.is_empty() { return self.exit(code) }
+        current.borrow_mut().status = ProcessStatus::Exited;
+        current.borrow_mut().stack = Vec::new();
+        self.threads.remove(&tid);
+        self.deadlock_check_due = true;
+        if self.table.get(&pid).map_or(false, |x| Rc::ptr_eq(x, &current)) {
+            self.table.insert(pid, Rc::clone(&others[0]));
+        }
+        info!("{}:{} Exited", pid, tid);
     }
 
     pub fn current_process(&mut self) -> Option<StrongPcbRef> {
         self.scheduler.current_process()
     }
 
+    // A snapshot of every process, ctx is the context of the current process
+    pub fn proc_list(&self, ctx: &Context) -> Vec<ProcessInfo> {
+        let current = self.scheduler.current_process();
+        self.table.values().map(|process| {
+            let is_current = current.as_ref().map_or(false, |x| Rc::ptr_eq(x, process));
+            let borrow = process.borrow();
+            let sp = if is_current { ctx.sp } else { borrow.context.sp };
+            let (queue_level, run_count) = self.scheduler.placement(process);
+            let mut info = ProcessInfo::new(&borrow, sp, queue_level, run_count);
+            let threads = self.thread_group(borrow.pid);
+            info.threads = threads.len() as u32;
+            info.ticks = threads.iter().map(|x| x.borrow().usage.user_ticks).fold(0, u32::wrapping_add);
+            info
+        }).collect()
+    }
+
+    // CPU accounting for a process, a negative pid means the current process
+    pub fn usage(&mut self, pid: PID) -> Option<ProcessUsage> {
+        let process = if pid < 0 { self.current_process()? } else { Rc::clone(self.table.get(&pid)?) };
+        let pcb = process.borrow();
+        let mut usage = pcb.usage.clone();
+        pcb.waiting_since.map(|since| usage.charge(since, self.scheduler.stats().ticks));
+        Some(usage)
+    }
+
+    pub fn scheduler_stats(&self) -> SchedulerStats {
+        self.scheduler.stats().clone()
+    }
+
+    // Read or change the scheduler's parameters, only processes started by the kernel may change them
+    pub fn sched_ctl(&mut self, request: u32, config: &mut MLFQConfig) -> Result<(), String> {
+        match request {
+            SCHED_GET_MLFQ => {
+                *config = self.scheduler.mlfq_config().ok_or("not an MLFQ scheduler")?;
+                Ok(())
+            }
+            SCHED_SET_MLFQ => {
+                let privileged = self.current_process().map_or(true, |x| x.borrow().parent.is_none());
+                if !privileged { return Err("permission denied".to_string()) }
+                self.scheduler.set_mlfq_config(config)
+            }
+            _ => Err("invalid request".to_string())
+        }
+    }
+
+    // FUTEX_WAIT blocks the current thread if the word at address still holds value, so that a wake
+    // between the caller reading the word and waiting isn't lost. FUTEX_WAKE unblocks up to value threads
+    pub fn futex(&mut self, op: u32, address: *const u32, value: u32) -> Result<u32, String> {
+        if address.is_null() || address as usize % 4 != 0 { return Err("invalid address".to_string()) }
+        match op {
+            FUTEX_WAIT => {
+                let current = self.current_process().ok_or("no current process")?;
+                if unsafe { core::ptr::read_volatile(address) } != value { return Err("value changed".to_string()) }
+                self.futexes.wait(address as usize, &current);
+                Ok(0)
+            }
+            FUTEX_WAKE => Ok(self.futexes.wake(address as usize, value)),
+            _ => Err("invalid operation".to_string())
+        }
+    }
+
+    // Create a shared memory segment of at least size bytes, with the current process attached to it
+    pub fn shm_create(&mut self, size: usize) -> Result<ShmId, String> {
+        let current = self.current_process().ok_or("no current process")?;
+        let segment = self.shared_memory.create(size)?;
+        let id = segment.id();
+        current.borrow().shm_segments.borrow_mut().insert(id, segment);
+        Ok(id)
+    }
+
+    // Attach the current process to a segment, if it isn't already, and return the segment's address
+    pub fn shm_attach(&mut self, id: ShmId) -> Result<usize, String> {
+        let current = self.current_process().ok_or("no current process")?;
+        let segment = self.shared_memory.get(id).ok_or("segment not found")?;
+        let address = segment.address();
+        current.borrow().shm_segments.borrow_mut().insert(id, segment);
+        Ok(address)
+    }
+
+    // Detach the current process from the segment at address, which is freed if it was the last one attached
+    pub fn shm_detach(&mut self, address: usize) -> Result<(), String> {
+        let current = self.current_process().ok_or("no current process")?;
+        let current = current.borrow();
+        let mut segments = current.shm_segments.borrow_mut();
+        let id = segments.values().find(|x| x.address() == address).map(|x| x.id()).ok_or("not attached")?;
+        segments.remove(&id);
+        Ok(())
+    }
+
+    // Block the current thread until process pid has received message and replied into reply
+    pub fn send(&mut self, pid: PID, message: *const u8, reply: *mut u8) -> Result<(), String> {
+        let current = self.current_process().ok_or("no current process")?;
+        if self.live_process(pid).is_none() { return Err("PID not found".to_string()) }
+        if current.borrow().pid == pid { return Err("can't send to self".to_string()) }
+        self.endpoints.entry(pid).or_default().send(&current, message, reply);
+        Ok(())
+    }
+
+    // The pid of a process which has sent to the current one, with its message copied into buffer.
+    // None if the current thread has been blocked until there is one
+    pub fn receive(&mut self, buffer: *mut u8) -> Result<Option<PID>, String> {
+        let current = self.current_process().ok_or("no current process")?;
+        let pid = current.borrow().pid;
+        Ok(self.endpoints.entry(pid).or_default().receive(&current, buffer).map(|x| x as PID))
+    }
+
+    // Unblock process pid, which is waiting for the current process to reply to its message
+    pub fn reply(&mut self, pid: PID, message: *const u8) -> Result<(), String> {
+        let current = self.current_process().ok_or("no current process")?;
+        let own = current.borrow().pid;
+        self.endpoints.get_mut(&own).ok_or("not waiting for a reply")?.reply(pid, message)
+    }
+
+    // Only processes started by the kernel may change what is done about deadlocks
+    pub fn set_deadlock_action(&mut self, action: DeadlockAction) -> Result<(), String> {
+        let privileged = self.current_process().map_or(true, |x| x.borrow().parent.is_none());
+        if !privileged { return Err("permission denied".to_string()) }
+        self.deadlock_action = action;
+        Ok(())
+    }
+
+    // Every thread blocked on a pipe, with the file it is blocked on. Also returns the processes with a
+    // thread that isn't, which can make progress as far as pipes are concerned
+    fn pipe_waits(&self) -> (Vec<(PipeWait, StrongFileDescriptorRef)>, BTreeSet<PID>) {
+        let address = |x: &StrongFileDescriptorRef| Rc::as_ptr(x) as *const u8 as usize;
+        let mut files: BTreeMap<usize, StrongFileDescriptorRef> = BTreeMap::new();
+        let mut held: BTreeMap<usize, Vec<PID>> = BTreeMap::new();
+        for process in self.table.values() {
+            let pcb = process.borrow();
+            for file in pcb.file_descriptors.borrow().values() {
+                let holders = held.entry(address(file)).or_default();
+                if !holders.contains(&pcb.pid) { holders.push(pcb.pid) }
+                files.insert(address(file), Rc::clone(file));
+            }
+        }
+        let mut waits = Vec::new();
+        for file in files.values() {
+            let (waiters, other) = match file.try_borrow_mut().ok().and_then(|mut x| x.blocked_on()) {
+                Some(x) => x,
+                None => continue,
+            };
+            let holders = match other.upgrade() {
+                Some(other) => match held.get(&address(&other)) {
+                    Some(x) => x.clone(),
+                    None => continue,       // Held by the kernel, e.g. passed over a socket
+                },
+                None => Vec::new(),
+            };
+            for waiter in waiters {
+                let pcb = waiter.borrow();
+                if pcb.status != ProcessStatus::Blocked || !self.threads.contains_key(&pcb.tid) { continue }
+                waits.push((PipeWait { tid: pcb.tid, pid: pcb.pid, holders: holders.clone() }, Rc::clone(file)));
+            }
+        }
+        let progressing = self.threads.values()
+            .filter(|x| waits.iter().all(|(wait, _)| wait.tid != x.borrow().tid))
+            .map(|x| x.borrow().pid)
+            .collect();
+        (waits, progressing)
+    }
+
+    // Processes only become deadlocked by blocking on a pipe, closing a pipe end or exiting, so the
+    // check is left until the next dispatch after one of those
+    pub fn pipes_changed(&mut self) {
+        self.deadlock_check_due = true;
+    }
+
+    // Log threads which can never be unblocked from pipes, then act on one of them. Acting on the
+    // youngest first until there are none left, as it is likely to have the least work to lose
+    pub fn check_deadlocks(&mut self) {
+        for _ in 0..=self.threads.len() {
+            let (waits, progressing) = self.pipe_waits();
+            let stuck = deadlocked(&waits.iter().map(|(x, _)| x.clone()).collect::<Vec<_>>(), &progressing);
+            for wait in stuck.iter().filter(|x| !self.deadlocked.contains(&x.tid)) {
+                if wait.holders.is_empty() {
+                    warn!("Deadlock: {}:{} is blocked on a pipe with no other end", wait.pid, wait.tid);
+                } else {
+                    warn!("Deadlock: {}:{} is blocked on a pipe held by {:?}", wait.pid, wait.tid, wait.holders);
+                }
+            }
+            self.deadlocked = stuck.iter().map(|x| x.tid).collect();
+            let victim = match stuck.iter().max_by_key(|x| x.tid) {
+                Some(x) => x,
+                None => return,
+            };
+            match self.deadlock_action {
+                DeadlockAction::Report => return,
+                DeadlockAction::Fail => {
+                    let (_, file) = waits.iter().find(|(x, _)| x.tid == victim.tid).unwrap();
+                    let thread = Rc::clone(self.threads.get(&victim.tid).unwrap());
+                    file.borrow_mut().cancel_blocked(&thread);
+                    thread.borrow_mut().set_unblocked(EDEADLK as u32);
+                    warn!("Failed {}:{} to break a deadlock", victim.pid, victim.tid);
+                }
+                DeadlockAction::Signal(signal) => {
+                    if self.signal(victim.pid, signal).is_err() { return }
+                }
+            }
+        }
+    }
+
     pub fn dispatch(&mut self, ctx: &mut Context, src: ScheduleSource) {
-        self.scheduler.schedule(src, |prev, mut next| {
+        if core::mem::replace(&mut self.deadlock_check_due, false) { self.check_deadlocks() }
+        self.scheduler.schedule(src, &mut |prev, next| {
             let prev_pid_str = match prev {
-                Some(mut x) => {
+                Some(x) => {
                     x.context = *ctx;
                     if x.status == ProcessStatus::Executing {   //Only if the previous was still in an executing state, e.g. not waiting
                         x.status = ProcessStatus::Ready;
@@ -177,7 +730,7 @@ impl ProcessManager {
             *ctx = next.context;
             next.status = ProcessStatus::Executing;
             let next_pid_str = if next.pid == -1 { "I".to_string() } else { next.pid.to_string() };
-            write!(UART0(), "[{}->{}]", prev_pid_str, next_pid_str).ok();
+            debug!("{}->{}", prev_pid_str, next_pid_str);
         });
     }
 
@@ -196,3 +749,630 @@ fn adjust_sp(old_stack: &Vec<u8>, new_stack: &Vec<u8>, old_sp: u32) -> u32 {
     let new_tos = new_stack.last().unwrap() as *const _;
     new_tos as u32 - diff
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::process::{ProcessManager, Context, ScheduleSource, FidTable, PID, TID, ProcessStatus, WAIT_NOHANG};
+    use crate::process::testing::{main_test, new_process, current_pid};
+    use crate::process::info::{PROC_EXECUTING, PROC_READY, PROC_STOPPED, PROC_EXITED};
+    use crate::process::signal::{SIG_TERM, SIG_TSTP, SIG_CONT, SIGNAL_EXIT_BASE};
+    use crate::process::futex::{FUTEX_WAIT, FUTEX_WAKE};
+    use crate::process::message::MSG_BYTES;
+    use crate::process::deadlock::{DeadlockAction, EDEADLK};
+    use crate::process::kernel::KernelWaitQueue;
+    use crate::io::pipe::new_pipe;
+    use crate::io::tasks::ReadTask;
+    use crate::{SysCall, MINUS_ONE};
+    use alloc::vec::Vec;
+    use alloc::rc::{Rc, Weak};
+    use alloc::collections::BTreeMap;
+
+    #[test]
+    fn proc_list_test() {
+        let mut manager = ProcessManager::default();
+        let first = manager.create_process(main_test, FidTable::default());
+        let mut ctx = Context::new(0, 0);
+        manager.dispatch(&mut ctx, ScheduleSource::Reset);
+        ctx.sp = ctx.sp - 64;
+        let child = manager.fork(&ctx);
+
+        let list = manager.proc_list(&ctx);
+        assert_eq!(list.len(), 2);
+        assert_eq!((list[0].pid, list[0].ppid, list[0].status), (first, -1, PROC_EXECUTING));
+        assert_eq!((list[1].pid, list[1].ppid, list[1].status), (child, first, PROC_READY));
+        assert_eq!(list[0].queue_level, 0);
+        assert_eq!(list[0].stack_used, 64);
+        assert_eq!(list[1].stack_used, 64);
+
+        // Using a whole quantum is counted
+        manager.dispatch(&mut ctx, ScheduleSource::Timer);
+        assert_eq!(manager.proc_list(&ctx)[0].ticks, 1);
+    }
+
+    #[test]
+    fn fairness_test() {
+        let mut manager = ProcessManager::default();
+        let pids: Vec<PID> = (0..3).map(|_| manager.create_process(main_test, FidTable::default())).collect();
+        let mut ctx = Context::new(0, 0);
+        manager.dispatch(&mut ctx, ScheduleSource::Reset);
+        (0..600).for_each(|_| manager.dispatch(&mut ctx, ScheduleSource::Timer));
+
+        let stats = manager.scheduler_stats();
+        assert_eq!((stats.ticks, stats.idle_ticks, stats.voluntary_switches), (600, 0, 0));
+        assert_eq!(stats.boosts, 600 / 51);
+        let usages: Vec<_> = pids.iter().map(|pid| manager.usage(*pid).unwrap()).collect();
+        // Every tick is charged to exactly one state of every process
+        assert_eq!(usages.iter().map(|x| x.user_ticks).sum::<u32>(), 600);
+        for usage in usages.iter() {
+            assert_eq!(usage.user_ticks + usage.ready_ticks + usage.blocked_ticks, 600);
+            assert!(usage.user_ticks >= 180 && usage.user_ticks <= 220, "{:?}", usage);
+            assert!(usage.demotions > 0 && usage.boosts > 0);
+            assert_eq!(usage.voluntary_switches, 0);
+        }
+        assert_eq!(usages.iter().map(|x| x.involuntary_switches).sum::<u32>(), stats.involuntary_switches);
+    }
+
+    #[test]
+    fn interactive_test() {
+        let mut manager = ProcessManager::default();
+        let interactive = manager.create_process(main_test, FidTable::default());
+        let batch: Vec<PID> = (0..2).map(|_| manager.create_process(main_test, FidTable::default())).collect();
+        let mut ctx = Context::new(0, 0);
+        manager.dispatch(&mut ctx, ScheduleSource::Reset);
+
+        // The interactive process blocks after each tick it runs for, and its IO completes 3 ticks later
+        let mut unblock_at = None;
+        let mut last_ticks = 0;
+        for tick in 0..400 {
+            manager.dispatch(&mut ctx, ScheduleSource::Timer);
+            if unblock_at == Some(tick) {
+                manager.table.get(&interactive).unwrap().borrow_mut().set_unblocked(0);
+                manager.dispatch(&mut ctx, ScheduleSource::Io);
+                unblock_at = None;
+            } else if current_pid(&mut manager) == Some(interactive) && manager.usage(interactive).unwrap().user_ticks > last_ticks {
+                last_ticks = last_ticks + 1;
+                manager.current_process().unwrap().borrow_mut().set_blocked();
+                manager.dispatch(&mut ctx, ScheduleSource::Svc { id: SysCall::Read });
+                unblock_at = Some(tick + 3);
+            }
+        }
+
+        let usage = manager.usage(interactive).unwrap();
+        assert_eq!(usage.user_ticks + usage.ready_ticks + usage.blocked_ticks, 400);
+        assert_eq!(usage.voluntary_switches, usage.user_ticks);
+        assert!(usage.user_ticks >= 20, "{:?}", usage);
+        assert!(usage.blocked_ticks >= usage.voluntary_switches * 2);
+        // Blocking before its quantum is used keeps it in the top queue
+        assert_eq!(usage.demotions, 0);
+        for pid in batch.iter() {
+            let other = manager.usage(*pid).unwrap();
+            assert_eq!(other.voluntary_switches, 0);
+            assert!(other.demotions > 0);
+            assert!(other.user_ticks > usage.user_ticks, "{:?} {:?}", usage, other);
+        }
+        let stats = manager.scheduler_stats();
+        assert_eq!(stats.voluntary_switches, usage.voluntary_switches);
+    }
+
+    fn current_tid(manager: &mut ProcessManager) -> Option<TID> {
+        manager.current_process().map(|x| x.borrow().tid)
+    }
+
+    // Yield until the given thread is running
+    fn switch_to(manager: &mut ProcessManager, ctx: &mut Context, tid: TID) {
+        for _ in 0..10 {
+            if current_tid(manager) == Some(tid) { return }
+            manager.dispatch(ctx, ScheduleSource::Svc { id: SysCall::Yield });
+        }
+        panic!("thread {} never ran", tid);
+    }
+
+    // A process with a main thread and count more threads, the main thread is running
+    fn threaded(count: usize) -> (ProcessManager, PID, Vec<TID>, Context) {
+        let mut manager = ProcessManager::default();
+        let pid = manager.create_process(main_test, FidTable::default());
+        let mut ctx = Context::new(0, 0);
+        manager.dispatch(&mut ctx, ScheduleSource::Reset);
+        let mut tids = vec![current_tid(&mut manager).unwrap()];
+        (0..count).for_each(|i| tids.push(manager.clone_thread(0x1000, i as u32, 0x2000).unwrap()));
+        (manager, pid, tids, ctx)
+    }
+
+    #[test]
+    fn clone_test() {
+        let (mut manager, pid, tids, mut ctx) = threaded(2);
+        assert_eq!(tids, vec![0, 1, 2]);
+        let thread = Rc::clone(manager.threads.get(&tids[2]).unwrap());
+        {
+            let main = manager.current_process().unwrap();
+            let (main, thread) = (main.borrow(), thread.borrow());
+            assert_eq!((thread.pid, thread.pgid, thread.parent), (pid, main.pgid, main.parent));
+            assert_eq!((thread.context.pc, thread.context.gpr[0], thread.context.lr), (0x1000, 1, 0x2000));
+            // Each thread has its own stack, but shares the file table
+            assert!(thread.stack.as_ptr() != main.stack.as_ptr());
+            assert!(Rc::ptr_eq(&thread.file_descriptors, &main.file_descriptors));
+        }
+
+        // Threads are scheduled separately, but listed as one process
+        (0..60).for_each(|_| manager.dispatch(&mut ctx, ScheduleSource::Timer));
+        assert!(manager.threads.values().all(|x| x.borrow().usage.user_ticks > 0));
+        let list = manager.proc_list(&ctx);
+        assert_eq!(list.len(), 1);
+        assert_eq!((list[0].pid, list[0].threads, list[0].ticks), (pid, 3, 60));
+    }
+
+    #[test]
+    fn thread_exit_test() {
+        let (mut manager, pid, tids, mut ctx) = threaded(1);
+        // The process outlives its first thread
+        manager.exit_thread(0);
+        manager.dispatch(&mut ctx, ScheduleSource::Svc { id: SysCall::ThreadExit });
+        assert_eq!(current_tid(&mut manager), Some(tids[1]));
+        let list = manager.proc_list(&ctx);
+        assert_eq!((list.len(), list[0].pid, list[0].threads), (1, pid, 1));
+
+        // Until its last thread exits
+        manager.exit_thread(0);
+        manager.dispatch(&mut ctx, ScheduleSource::Svc { id: SysCall::ThreadExit });
+        assert!(manager.proc_list(&ctx).is_empty());
+        assert!(manager.threads.is_empty());
+        assert_eq!(current_tid(&mut manager), None);
+    }
+
+    #[test]
+    fn group_exit_test() {
+        let (mut manager, pid, tids, mut ctx) = threaded(3);
+        let other = manager.fork(&ctx);
+        switch_to(&mut manager, &mut ctx, tids[2]);
+        let threads: Vec<_> = tids.iter().map(|x| Rc::clone(manager.threads.get(x).unwrap())).collect();
+        manager.exit(0);
+        manager.dispatch(&mut ctx, ScheduleSource::Svc { id: SysCall::Exit });
+        assert!(threads.iter().all(|x| x.borrow().status == ProcessStatus::Terminated || x.borrow().status == ProcessStatus::Exited));
+        assert_eq!(manager.proc_list(&ctx).iter().map(|x| x.pid).collect::<Vec<_>>(), vec![other]);
+        assert!(manager.threads.values().all(|x| x.borrow().pid != pid));
+        (0..20).for_each(|_| manager.dispatch(&mut ctx, ScheduleSource::Timer));
+        assert_eq!(current_pid(&mut manager), Some(other));
+    }
+
+    #[test]
+    fn signal_threads_test() {
+        let (mut manager, pid, tids, mut ctx) = threaded(2);
+        let other = manager.fork(&ctx);
+        assert!(manager.signal(pid, 7).is_err());
+        manager.signal(pid, SIG_TSTP).unwrap();
+        assert!(tids.iter().all(|x| manager.threads.get(x).unwrap().borrow().stopped));
+        manager.dispatch(&mut ctx, ScheduleSource::Io);
+        (0..20).for_each(|_| manager.dispatch(&mut ctx, ScheduleSource::Timer));
+        assert_eq!(current_pid(&mut manager), Some(other));
+        assert_eq!(manager.proc_list(&ctx)[0].status, PROC_STOPPED);
+
+        manager.signal(pid, SIG_CONT).unwrap();
+        manager.signal(pid, SIG_TERM).unwrap();
+        assert!(manager.threads.values().all(|x| x.borrow().pid == other));
+        assert!(manager.signal(pid, SIG_TERM).is_err());
+    }
+
+    #[test]
+    fn exec_test() {
+        let (mut manager, pid, tids, mut ctx) = threaded(2);
+        switch_to(&mut manager, &mut ctx, tids[1]);
+        manager.exec(&mut ctx, 0x3000);
+        assert_eq!(ctx.pc, 0x3000);
+        // Only the thread that called exec is left, and it now represents the process
+        assert_eq!(manager.threads.keys().cloned().collect::<Vec<_>>(), vec![tids[1]]);
+        assert_eq!(manager.table.get(&pid).unwrap().borrow().tid, tids[1]);
+    }
+
+    extern fn kernel_main() -> ! { loop {} }
+
+    #[test]
+    fn kernel_thread_test() {
+        let mut manager = ProcessManager::default();
+        let user = manager.create_process(main_test, FidTable::default());
+        let kernel = manager.spawn_kernel_thread(kernel_main);
+        let mut ctx = Context::new(0, 0);
+        manager.dispatch(&mut ctx, ScheduleSource::Reset);
+        (0..40).for_each(|_| manager.dispatch(&mut ctx, ScheduleSource::Timer));
+        assert!(manager.usage(kernel).unwrap().user_ticks > 0);
+        assert!(manager.usage(user).unwrap().user_ticks > 0);
+        let thread = Rc::clone(manager.table.get(&kernel).unwrap());
+        assert!(thread.borrow().context.is_kernel());
+        assert!(!manager.table.get(&user).unwrap().borrow().context.is_kernel());
+        assert_eq!(thread.borrow().parent, None);
+
+        // A blocked kernel thread is switched out by the next interrupt, and doesn't run until woken
+        let mut queue = KernelWaitQueue::default();
+        switch_to_process(&mut manager, &mut ctx, kernel);
+        manager.block_kernel_thread(&mut queue).unwrap();
+        manager.dispatch(&mut ctx, ScheduleSource::Timer);
+        assert_eq!(current_pid(&mut manager), Some(user));
+        let ticks = manager.usage(kernel).unwrap().user_ticks;
+        (0..40).for_each(|_| manager.dispatch(&mut ctx, ScheduleSource::Timer));
+        assert_eq!(manager.usage(kernel).unwrap().user_ticks, ticks);
+        assert_eq!(queue.wake_all(), 1);
+        (0..40).for_each(|_| manager.dispatch(&mut ctx, ScheduleSource::Timer));
+        assert!(manager.usage(kernel).unwrap().user_ticks > ticks);
+
+        // User processes can't block as a kernel thread, or signal one
+        switch_to_process(&mut manager, &mut ctx, user);
+        assert!(manager.block_kernel_thread(&mut queue).is_err());
+        assert!(manager.signal(kernel, SIG_TERM).is_err());
+    }
+
+    #[test]
+    fn futex_test() {
+        let (mut manager, _, tids, mut ctx) = threaded(1);
+        let mut word = 1u32;
+        let address = &mut word as *mut u32;
+        assert!(manager.futex(FUTEX_WAIT, core::ptr::null(), 0).is_err());
+        assert!(manager.futex(FUTEX_WAIT, (address as usize + 1) as *const u32, 1).is_err());
+        assert!(manager.futex(2, address, 1).is_err());
+
+        // The main thread reads 1 and decides to wait, but is preempted before it does
+        manager.dispatch(&mut ctx, ScheduleSource::Svc { id: SysCall::Yield });
+        assert_eq!(current_tid(&mut manager), Some(tids[1]));
+        // The other thread changes the word and wakes its waiters, of which there are none yet
+        unsafe { *address = 0 };
+        assert_eq!(manager.futex(FUTEX_WAKE, address, 1), Ok(0));
+        switch_to(&mut manager, &mut ctx, tids[0]);
+        // So the wait returns straight away instead of missing the wakeup
+        assert!(manager.futex(FUTEX_WAIT, address, 1).is_err());
+        assert_eq!(manager.current_process().unwrap().borrow().status, ProcessStatus::Executing);
+
+        // Waiting while the word is unchanged blocks until woken
+        manager.futex(FUTEX_WAIT, address, 0).unwrap();
+        manager.dispatch(&mut ctx, ScheduleSource::Svc { id: SysCall::Futex });
+        assert_eq!(current_tid(&mut manager), Some(tids[1]));
+        (0..10).for_each(|_| manager.dispatch(&mut ctx, ScheduleSource::Timer));
+        assert_eq!(current_tid(&mut manager), Some(tids[1]));
+        assert_eq!(manager.futex(FUTEX_WAKE, address, u32::MAX), Ok(1));
+        assert_eq!(manager.futex(FUTEX_WAKE, address, u32::MAX), Ok(0));
+        switch_to(&mut manager, &mut ctx, tids[0]);
+    }
+
+    // One step of mutex_lock and mutex_unlock from sync.c, so that a thread can be preempted between any two
+    #[derive(Clone, Copy, PartialEq, Debug)]
+    enum MutexStep { Lock, Exchange, Wait, Read, Write(u32), Unlock, Store, Wake, Done }
+
+    // Threads increment a counter under the futex mutex, with a timer interrupt before a random number of
+    // steps. Returns how many times a thread blocked on the mutex
+    fn mutex_simulation(seed: u32, threads: usize, iterations: u32) -> u32 {
+        let (mut manager, _, tids, mut ctx) = threaded(threads - 1);
+        let mut steps: BTreeMap<TID, (MutexStep, u32)> = tids.iter().map(|x| (*x, (MutexStep::Lock, 0))).collect();
+        let mut word = 0u32;
+        let address = &mut word as *mut u32;
+        let (mut counter, mut holder, mut waits) = (0, None, 0);
+        let mut random = seed;
+
+        for _ in 0..1_000_000 {
+            if steps.values().all(|x| x.0 == MutexStep::Done) { break }
+            random = random ^ (random << 13);
+            random = random ^ (random >> 17);
+            random = random ^ (random << 5);
+            if random % 4 == 0 {
+                manager.dispatch(&mut ctx, ScheduleSource::Timer);
+                continue;
+            }
+            let tid = current_tid(&mut manager).expect("every thread is blocked, so a wakeup was lost");
+            let (step, done) = steps.get_mut(&tid).unwrap();
+            let mut syscall = None;
+            *step = match *step {
+                MutexStep::Lock => match unsafe { *address } {
+                    0 => { unsafe { *address = 1 }; MutexStep::Read }
+                    2 => MutexStep::Wait,
+                    _ => MutexStep::Exchange,
+                },
+                MutexStep::Exchange => match core::mem::replace(unsafe { &mut *address }, 2) {
+                    0 => MutexStep::Read,
+                    _ => MutexStep::Wait,
+                },
+                MutexStep::Wait => {
+                    if manager.futex(FUTEX_WAIT, address, 2).is_ok() { waits = waits + 1 }
+                    syscall = Some(SysCall::Futex);
+                    MutexStep::Exchange
+                }
+                MutexStep::Read => {
+                    assert_eq!(holder.replace(tid), None, "two threads hold the mutex");
+                    MutexStep::Write(counter)
+                }
+                MutexStep::Write(x) => { counter = x + 1; MutexStep::Unlock }
+                MutexStep::Unlock => {
+                    holder = None;
+                    unsafe { *address = *address - 1 };
+                    match unsafe { *address } {
+                        0 => { *done = *done + 1; MutexStep::Lock }
+                        _ => MutexStep::Store,
+                    }
+                }
+                MutexStep::Store => { unsafe { *address = 0 }; MutexStep::Wake }
+                MutexStep::Wake => {
+                    manager.futex(FUTEX_WAKE, address, 1).unwrap();
+                    syscall = Some(SysCall::Futex);
+                    *done = *done + 1;
+                    MutexStep::Lock
+                }
+                MutexStep::Done => unreachable!(),
+            };
+            if *done == iterations && *step == MutexStep::Lock {
+                *step = MutexStep::Done;
+                manager.exit_thread(0);
+                syscall = Some(SysCall::ThreadExit);
+            }
+            if let Some(id) = syscall { manager.dispatch(&mut ctx, ScheduleSource::Svc { id }) }
+        }
+
+        assert!(steps.values().all(|x| x.0 == MutexStep::Done), "threads never finished");
+        assert_eq!(counter, threads as u32 * iterations);
+        assert!(manager.threads.is_empty());
+        waits
+    }
+
+    #[test]
+    fn futex_mutex_test() {
+        let waits: u32 = (1..=20).map(|seed| mutex_simulation(seed * 7919, 2 + seed as usize % 4, 50)).sum();
+        // Preemption inside the critical section must have made threads wait on the futex
+        assert!(waits > 0);
+    }
+
+    #[test]
+    fn shm_test() {
+        let mut manager = ProcessManager::default();
+        let first = manager.create_process(main_test, FidTable::default());
+        let other = manager.create_process(main_test, FidTable::default());
+        let mut ctx = Context::new(0, 0);
+        let tid = |manager: &ProcessManager, pid: PID| manager.table.get(&pid).unwrap().borrow().tid;
+        let (first_tid, other_tid) = (tid(&manager, first), tid(&manager, other));
+        manager.dispatch(&mut ctx, ScheduleSource::Reset);
+        switch_to(&mut manager, &mut ctx, first_tid);
+        assert!(manager.shm_create(0).is_err());
+        let id = manager.shm_create(64).unwrap();
+        let address = manager.shm_attach(id).unwrap();
+        let segment = Rc::downgrade(&manager.shared_memory.get(id).unwrap());
+        unsafe { *(address as *mut u32) = 42 };
+
+        // A forked child inherits the attachment, and an unrelated process can attach by id
+        ctx.sp = ctx.sp - 64;
+        let child = manager.fork(&ctx);
+        assert!(manager.table.get(&child).unwrap().borrow().shm_segments.borrow().contains_key(&id));
+        switch_to(&mut manager, &mut ctx, other_tid);
+        assert_eq!(manager.shm_attach(id), Ok(address));
+        assert_eq!(unsafe { *(address as *const u32) }, 42);
+        manager.shm_detach(address).unwrap();
+        assert!(manager.shm_detach(address).is_err());
+
+        // The segment lives until the last attached process has detached or exited
+        let child_tid = tid(&manager, child);
+        switch_to(&mut manager, &mut ctx, child_tid);
+        manager.exit(0);
+        manager.dispatch(&mut ctx, ScheduleSource::Svc { id: SysCall::Exit });
+        assert!(segment.upgrade().is_some());
+        manager.signal(first, SIG_TERM).unwrap();
+        assert!(segment.upgrade().is_none());
+        manager.dispatch(&mut ctx, ScheduleSource::Svc { id: SysCall::Exit });
+        assert!(manager.shm_attach(id).is_err());
+    }
+
+    #[test]
+    fn message_test() {
+        let mut manager = ProcessManager::default();
+        let server = manager.create_process(main_test, FidTable::default());
+        let client = manager.create_process(main_test, FidTable::default());
+        let mut ctx = Context::new(0, 0);
+        manager.dispatch(&mut ctx, ScheduleSource::Reset);
+        switch_to(&mut manager, &mut ctx, server);
+        let (mut request, mut received, response, mut reply) = ([1u8; MSG_BYTES], [0u8; MSG_BYTES], [2u8; MSG_BYTES], [0u8; MSG_BYTES]);
+        assert!(manager.send(server, request.as_ptr(), reply.as_mut_ptr()).is_err());
+        assert!(manager.send(7, request.as_ptr(), reply.as_mut_ptr()).is_err());
+
+        // The server blocks until a message arrives
+        assert_eq!(manager.receive(received.as_mut_ptr()), Ok(None));
+        manager.dispatch(&mut ctx, ScheduleSource::Svc { id: SysCall::Receive });
+        assert_eq!(current_pid(&mut manager), Some(client));
+        manager.send(server, request.as_ptr(), reply.as_mut_ptr()).unwrap();
+        manager.dispatch(&mut ctx, ScheduleSource::Svc { id: SysCall::Send });
+        // Which returns the client's pid, and the client blocks until it is replied to
+        assert_eq!(current_pid(&mut manager), Some(server));
+        assert_eq!((ctx.gpr[0], received), (client as u32, request));
+        (0..10).for_each(|_| manager.dispatch(&mut ctx, ScheduleSource::Timer));
+        assert_eq!(current_pid(&mut manager), Some(server));
+        assert!(manager.reply(server, response.as_ptr()).is_err());
+        manager.reply(client, response.as_ptr()).unwrap();
+        assert!(manager.reply(client, response.as_ptr()).is_err());
+        switch_to(&mut manager, &mut ctx, client);
+        assert_eq!((ctx.gpr[0], reply), (MSG_BYTES as u32, response));
+
+        // A message sent first is received straight away
+        request[0] = 3;
+        manager.send(server, request.as_ptr(), reply.as_mut_ptr()).unwrap();
+        manager.dispatch(&mut ctx, ScheduleSource::Svc { id: SysCall::Send });
+        assert_eq!(manager.receive(received.as_mut_ptr()), Ok(Some(client)));
+        assert_eq!(received[0], 3);
+
+        // The sender fails if the server exits before replying
+        manager.exit(0);
+        manager.dispatch(&mut ctx, ScheduleSource::Svc { id: SysCall::Exit });
+        assert_eq!(current_pid(&mut manager), Some(client));
+        assert_eq!((ctx.gpr[0], reply[0]), (MINUS_ONE as u32, 2));
+    }
+
+    #[test]
+    fn pipe_deadlock_test() {
+        let mut manager = ProcessManager::default();
+        let (read, write) = new_pipe();
+        let mut files = FidTable::default();
+        files.insert(0, Rc::clone(&read));
+        files.insert(1, write);
+        let first = manager.create_process(main_test, files.clone());
+        manager.create_process(main_test, files);
+        let mut ctx = Context::new(0, 0);
+        manager.dispatch(&mut ctx, ScheduleSource::Reset);
+
+        // Both processes read from a pipe which only they can write to, as the read syscall does
+        let mut buffers = [[0u8; 1]; 2];
+        for (i, buffer) in buffers.iter_mut().enumerate() {
+            let mut task = ReadTask::new(&manager.current_process().unwrap(), buffer.as_mut_ptr(), 1);
+            assert!(task.attempt(|x| read.borrow_mut().read(x)).is_none());
+            read.borrow_mut().add_pending_read(task);
+            // Deadlocks are only looked for after a change to the pipes has been noted
+            if i == 0 { manager.pipes_changed() }
+            manager.dispatch(&mut ctx, ScheduleSource::Svc { id: SysCall::Read });
+            assert!(manager.deadlocked.is_empty());
+        }
+        manager.pipes_changed();
+        manager.dispatch(&mut ctx, ScheduleSource::Io);
+        assert_eq!(manager.deadlocked.len(), 2);
+
+        // Failing the youngest read lets the other process be woken by it
+        manager.set_deadlock_action(DeadlockAction::Fail).unwrap();
+        manager.check_deadlocks();
+        manager.dispatch(&mut ctx, ScheduleSource::Io);
+        assert!(manager.deadlocked.is_empty());
+        assert_eq!((current_pid(&mut manager), ctx.gpr[0]), (Some(first + 1), EDEADLK as u32));
+        assert_eq!(manager.table.get(&first).unwrap().borrow().status, ProcessStatus::Blocked);
+
+        // Once it exits, the remaining reader only waits on itself, and is killed
+        manager.set_deadlock_action(DeadlockAction::Signal(SIG_TERM)).unwrap();
+        manager.exit(0);
+        manager.dispatch(&mut ctx, ScheduleSource::Svc { id: SysCall::Exit });
+        assert!(manager.table.is_empty() && manager.deadlocked.is_empty());
+    }
+
+    // Yield until the first thread of the given process is running
+    fn switch_to_process(manager: &mut ProcessManager, ctx: &mut Context, pid: PID) {
+        let tid = manager.table.get(&pid).unwrap().borrow().tid;
+        switch_to(manager, ctx, tid)
+    }
+
+    #[test]
+    fn wait_test() {
+        let mut manager = ProcessManager::default();
+        let parent = manager.create_process(main_test, FidTable::default());
+        let mut ctx = Context::new(0, 0);
+        manager.dispatch(&mut ctx, ScheduleSource::Reset);
+        let parent_tid = current_tid(&mut manager).unwrap();
+
+        // An exited child is a zombie until it is waited for
+        let child = manager.fork(&ctx);
+        let mut status = 0;
+        assert_eq!(manager.wait(-1, &mut status, WAIT_NOHANG), Ok(Some(0)));
+        switch_to_process(&mut manager, &mut ctx, child);
+        manager.exit(3);
+        manager.dispatch(&mut ctx, ScheduleSource::Svc { id: SysCall::Exit });
+        let list = manager.proc_list(&ctx);
+        assert_eq!((list[1].pid, list[1].status, list[1].stack_size, list[1].fids), (child, PROC_EXITED, 0, 0));
+        assert!(manager.signal(child, SIG_TERM).is_err());
+        assert!(manager.set_nice(child, 1).is_err() && manager.set_pgid(child, 0).is_err());
+        switch_to(&mut manager, &mut ctx, parent_tid);
+        assert_eq!((manager.wait(child, &mut status, 0), status), (Ok(Some(child)), 3));
+        assert!(manager.wait(-1, &mut status, 0).is_err());
+
+        // A parent waiting for any child is woken by one being killed
+        let child = manager.fork(&ctx);
+        assert_eq!(manager.wait(-1, &mut status, 0), Ok(None));
+        manager.dispatch(&mut ctx, ScheduleSource::Svc { id: SysCall::Wait });
+        assert_eq!(current_pid(&mut manager), Some(child));
+        manager.signal(child, SIG_TERM).unwrap();
+        manager.dispatch(&mut ctx, ScheduleSource::Svc { id: SysCall::Kill });
+        assert_eq!((current_pid(&mut manager), ctx.gpr[0], status), (Some(parent), child as u32, SIGNAL_EXIT_BASE + SIG_TERM));
+        assert_eq!(manager.proc_list(&ctx).len(), 1);
+
+        // A thread waiting for a child which another thread reaped first fails
+        let child = manager.fork(&ctx);
+        let sibling = manager.clone_thread(0, 0, 0).unwrap();
+        assert_eq!(manager.wait(-1, &mut status, 0), Ok(None));
+        manager.dispatch(&mut ctx, ScheduleSource::Svc { id: SysCall::Wait });
+        switch_to(&mut manager, &mut ctx, sibling);
+        let mut sibling_status = 0;
+        assert_eq!(manager.wait(child, &mut sibling_status, 0), Ok(None));
+        manager.dispatch(&mut ctx, ScheduleSource::Svc { id: SysCall::Wait });
+        assert_eq!(current_pid(&mut manager), Some(child));
+        manager.exit(4);
+        manager.dispatch(&mut ctx, ScheduleSource::Svc { id: SysCall::Exit });
+        let thread = |tid| Rc::clone(manager.threads.get(&tid).unwrap());
+        assert_eq!((thread(parent_tid).borrow().result(), status), (child as u32, 4));
+        assert_eq!(thread(sibling).borrow().result(), MINUS_ONE as u32);
+        assert!(manager.waiting.is_empty());
+        switch_to(&mut manager, &mut ctx, sibling);
+        manager.exit_thread(0);
+        manager.dispatch(&mut ctx, ScheduleSource::Svc { id: SysCall::ThreadExit });
+
+        // Exited children of an exited parent are removed, as nothing can wait for them
+        let child = manager.fork(&ctx);
+        switch_to_process(&mut manager, &mut ctx, child);
+        manager.exit(0);
+        manager.dispatch(&mut ctx, ScheduleSource::Svc { id: SysCall::Exit });
+        manager.exit(0);
+        manager.dispatch(&mut ctx, ScheduleSource::Svc { id: SysCall::Exit });
+        assert!(manager.table.is_empty() && manager.threads.is_empty());
+    }
+
+    #[test]
+    fn orphan_test() {
+        let mut manager = ProcessManager::default();
+        let parent = manager.create_process(main_test, FidTable::default());
+        let mut ctx = Context::new(0, 0);
+        manager.dispatch(&mut ctx, ScheduleSource::Reset);
+        let child = manager.fork(&ctx);
+        manager.exit(0);
+        manager.dispatch(&mut ctx, ScheduleSource::Svc { id: SysCall::Exit });
+        assert_eq!(current_pid(&mut manager), Some(child));
+        assert_eq!(manager.proc_list(&ctx)[0].ppid, -1);
+
+        // Once pids wrap around, a new process can be given the pid of the exited parent
+        manager.table.insert(PID::max_value(), new_process());
+        let reused = manager.create_process(main_test, FidTable::default());
+        manager.table.remove(&PID::max_value());
+        assert_eq!(reused, parent);
+        switch_to_process(&mut manager, &mut ctx, reused);
+        assert!(manager.wait(-1, &mut 0, 0).is_err());
+
+        // So the orphan isn't left as a zombie of the new process when it exits
+        switch_to_process(&mut manager, &mut ctx, child);
+        manager.exit(0);
+        manager.dispatch(&mut ctx, ScheduleSource::Svc { id: SysCall::Exit });
+        assert!(manager.table.get(&child).is_none());
+    }
+
+    #[test]
+    fn exit_leak_test() {
+        let mut manager = ProcessManager::default();
+        let (read, write) = new_pipe();
+        let mut files = FidTable::default();
+        files.insert(0, Rc::clone(&read));
+        files.insert(1, Rc::clone(&write));
+        manager.create_process(main_test, files);
+        let mut ctx = Context::new(0, 0);
+        manager.dispatch(&mut ctx, ScheduleSource::Reset);
+        let parent_tid = current_tid(&mut manager).unwrap();
+
+        let mut buffer = [0u8; 1];
+        let mut children: Vec<Weak<_>> = Vec::new();
+        for i in 0..50 {
+            let child = manager.fork(&ctx);
+            children.push(Rc::downgrade(manager.table.get(&child).unwrap()));
+            switch_to_process(&mut manager, &mut ctx, child);
+            if i % 2 == 0 {
+                manager.exit(0);
+                manager.dispatch(&mut ctx, ScheduleSource::Svc { id: SysCall::Exit });
+            } else {
+                // Killed while blocked reading from the pipe, as the read syscall does
+                let mut task = ReadTask::new(&manager.current_process().unwrap(), buffer.as_mut_ptr(), 1);
+                assert!(task.attempt(|x| read.borrow_mut().read(x)).is_none());
+                read.borrow_mut().add_pending_read(task);
+                manager.dispatch(&mut ctx, ScheduleSource::Svc { id: SysCall::Read });
+                manager.signal(child, SIG_TERM).unwrap();
+                manager.dispatch(&mut ctx, ScheduleSource::Svc { id: SysCall::Kill });
+                // The zombie's read is cancelled, rather than left to be completed into its freed stack
+                assert!(read.borrow_mut().blocked_on().unwrap().0.is_empty());
+            }
+            assert_eq!(current_tid(&mut manager), Some(parent_tid));
+            assert_eq!(manager.wait(child, core::ptr::null_mut(), 0), Ok(Some(child)));
+        }
+
+        // Nothing refers to the children, or to the parent's files on their behalf
+        assert!(children.iter().all(|x| x.upgrade().is_none()));
+        assert_eq!((Rc::strong_count(&read), Rc::strong_count(&write)), (2, 2));
+        assert_eq!((manager.table.len(), manager.threads.len()), (1, 1));
+        assert!(manager.waiting.is_empty() && manager.endpoints.is_empty());
+    }
+}
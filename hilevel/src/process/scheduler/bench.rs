@@ -0,0 +1,134 @@
+// Compares the MultiLevelQueue against the scanning queue it replaced, run with cargo bench
+use crate::process::{ProcessControlBlock, Context, StrongPcbRef};
+use crate::process::scheduler::queues::{MultiLevelQueue, QUEUE_QUANTUM};
+use crate::process::scheduler::ready;
+use alloc::collections::VecDeque;
+use alloc::rc::Rc;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+use test::{Bencher, black_box};
+
+// The previous implementation, every queue holds blocked processes too so finding one to run is a scan
+struct ScanningQueue {
+    levels: Vec<VecDeque<StrongPcbRef>>,
+}
+
+impl ScanningQueue {
+
+    fn new() -> Self {
+        ScanningQueue { levels: QUEUE_QUANTUM.iter().map(|_| VecDeque::new()).collect() }
+    }
+
+    fn pop_process(&mut self) -> Option<(StrongPcbRef, usize)> {
+        for (level, queue) in self.levels.iter_mut().enumerate() {
+            if let Some(i) = queue.iter().position(|x| ready(&x.borrow())) {
+                return queue.remove(i).map(|x| (x, level))
+            }
+        }
+        None
+    }
+
+    fn remove_process(&mut self, process: &StrongPcbRef) -> Option<StrongPcbRef> {
+        for queue in self.levels.iter_mut() {
+            if let Some(i) = queue.iter().position(|x| Rc::ptr_eq(x, process)) {
+                return queue.remove(i)
+            }
+        }
+        None
+    }
+}
+
+// Half the processes are blocked, and as they rarely use their quantum they sit in the top queue
+fn processes(count: usize) -> Vec<(StrongPcbRef, usize)> {
+    (0..count).map(|i| {
+        let mut pcb = ProcessControlBlock::new(i as i32, Vec::new(), Context::new(0, 0), Default::default());
+        if i % 2 == 0 { pcb.set_blocked() }
+        let level = if i % 2 == 0 { 0 } else { QUEUE_QUANTUM.len() - 1 };
+        (Rc::new(RefCell::new(pcb)), level)
+    }).collect()
+}
+
+fn scanning(count: usize) -> (ScanningQueue, Vec<StrongPcbRef>) {
+    let mut queue = ScanningQueue::new();
+    let all = processes(count);
+    all.iter().for_each(|(x, level)| queue.levels[*level].push_back(Rc::clone(x)));
+    (queue, all.into_iter().map(|x| x.0).collect())
+}
+
+// Blocked processes are left out, as the MLFQ parks them
+fn indexed(count: usize) -> (MultiLevelQueue, Vec<StrongPcbRef>) {
+    let mut queue = MultiLevelQueue::default();
+    let all = processes(count);
+    all.iter().filter(|(x, _)| ready(&x.borrow())).for_each(|(x, level)| queue.push_back(*level, Rc::clone(x)));
+    (queue, all.into_iter().map(|x| x.0).collect())
+}
+
+fn scanning_pop_push(b: &mut Bencher, count: usize) {
+    let (mut queue, _all) = scanning(count);
+    b.iter(|| {
+        let (process, level) = queue.pop_process().unwrap();
+        queue.levels[level].push_back(black_box(process));
+    });
+}
+
+fn indexed_pop_push(b: &mut Bencher, count: usize) {
+    let (mut queue, _all) = indexed(count);
+    b.iter(|| {
+        let (process, level) = queue.pop().unwrap();
+        queue.push_back(level, black_box(process));
+    });
+}
+
+fn scanning_remove(b: &mut Bencher, count: usize) {
+    let (mut queue, all) = scanning(count);
+    let last = Rc::clone(all.iter().filter(|x| ready(&x.borrow())).last().unwrap());
+    b.iter(|| {
+        let process = queue.remove_process(&last).unwrap();
+        queue.levels[QUEUE_QUANTUM.len() - 1].push_back(black_box(process));
+    });
+}
+
+fn indexed_remove(b: &mut Bencher, count: usize) {
+    let (mut queue, all) = indexed(count);
+    let last = Rc::clone(all.iter().filter(|x| ready(&x.borrow())).last().unwrap());
+    b.iter(|| {
+        let (process, level) = queue.remove_process(&last).unwrap();
+        queue.push_back(level, black_box(process));
+    });
+}
+
+#[bench]
+fn scanning_pop_push_16(b: &mut Bencher) { scanning_pop_push(b, 16) }
+
+#[bench]
+fn scanning_pop_push_64(b: &mut Bencher) { scanning_pop_push(b, 64) }
+
+#[bench]
+fn scanning_pop_push_256(b: &mut Bencher) { scanning_pop_push(b, 256) }
+
+#[bench]
+fn indexed_pop_push_16(b: &mut Bencher) { indexed_pop_push(b, 16) }
+
+#[bench]
+fn indexed_pop_push_64(b: &mut Bencher) { indexed_pop_push(b, 64) }
+
+#[bench]
+fn indexed_pop_push_256(b: &mut Bencher) { indexed_pop_push(b, 256) }
+
+#[bench]
+fn scanning_remove_16(b: &mut Bencher) { scanning_remove(b, 16) }
+
+#[bench]
+fn scanning_remove_64(b: &mut Bencher) { scanning_remove(b, 64) }
+
+#[bench]
+fn scanning_remove_256(b: &mut Bencher) { scanning_remove(b, 256) }
+
+#[bench]
+fn indexed_remove_16(b: &mut Bencher) { indexed_remove(b, 16) }
+
+#[bench]
+fn indexed_remove_64(b: &mut Bencher) { indexed_remove(b, 64) }
+
+#[bench]
+fn indexed_remove_256(b: &mut Bencher) { indexed_remove(b, 256) }
@@ -1,11 +1,21 @@
-use crate::process::{ProcessControlBlock, Context};
-use alloc::vec::Vec;
+use crate::process::{ProcessControlBlock, Context, uninit_bytes};
 
+const IDLE_STACK_BYTES: usize = 0x40;
+
+// Waiting for an interrupt needs SVC mode, so idle is a kernel thread
+#[cfg(target_arch = "arm")]
+extern fn idle_fn() -> ! {
+    loop { unsafe { asm!("wfi"); } }
+}
+
+#[cfg(not(target_arch = "arm"))]
 extern fn idle_fn() -> ! {
-    loop { unsafe { asm!("nop"); } }
+    loop {}
 }
 
-// A process that does nothing, implementation does not require a stack
+// A kernel thread that does nothing, it only needs enough stack to be switched to and from
 pub fn idle_process() -> ProcessControlBlock {
-    ProcessControlBlock::new(-1, Vec::new(), Context::new(idle_fn as u32, 0 as u32), Default::default() )
+    let stack = uninit_bytes(IDLE_STACK_BYTES);
+    let tos = stack.last().unwrap() as *const _;
+    ProcessControlBlock::new(-1, stack, Context::kernel(idle_fn as u32, tos as u32), Default::default())
 }
@@ -0,0 +1,673 @@
+use crate::process::{StrongPcbRef, ScheduleSource, ProcessStatus, TID, WakeQueue};
+use alloc::rc::Rc;
+use alloc::vec::Vec;
+use alloc::collections::BTreeMap;
+use crate::process::scheduler::queues::{MultiLevelQueue, QUEUE_QUANTUM};
+use crate::process::scheduler::{Scheduler, Dispatch, ready};
+use crate::process::info::WaitingSince;
+use crate::SysCall;
+use core::cell::RefCell;
+use crate::process::scheduler::idle::idle_process;
+use crate::process::info::SchedulerStats;
+use alloc::string::{String, ToString};
+
+const BOOST_QUANTUM: u32 = 50;
+
+pub const MLFQ_MAX_LEVELS: usize = 8;
+
+// MLFQConfig flags, must match libc.h
+pub const MLFQ_FLAG_BOOST: u32 = 0x01;
+pub const MLFQ_FLAG_YIELD_DEMOTES: u32 = 0x02;
+pub const MLFQ_FLAG_WAKEUP_PREEMPT: u32 = 0x04;
+
+// The tunable parameters, must match mlfq_config_t in libc.h
+#[repr(C)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct MLFQConfig {
+    pub levels: u32,
+    pub quantum: [u32; MLFQ_MAX_LEVELS],    // Of each queue from the top down, only the first levels are used
+    pub boost_interval: u32,
+    pub flags: u32,
+    pub preempt_delay: u32,                 // Ticks a woken process in a higher queue may wait for the current one
+}
+
+// A blocked or stopped thread isn't held by the scheduler at all, only by the process manager and whatever
+// it is waiting on, which puts it on the wake queue once it is ready. This is kept in its PCB meanwhile
+#[derive(Debug, Clone, Copy)]
+pub struct Parked {
+    level: usize,       // The level to return to
+    epoch: u32,         // The number of boosts when it was parked, if there have been more it returns to the top
+}
+
+pub struct MLFQScheduler {
+    queues: MultiLevelQueue,        // Only ready processes
+    wake_queue: WakeQueue,
+    current: Option<Current>,
+    boost_epoch: u32,
+    boost_tracker: u32,
+    boost_quantum: u32,     // Ticks between moving every process to the top queue
+    boost_enabled: bool,
+    yield_demotes: bool,
+    wakeup_preempt: bool,
+    preempt_delay: u32,
+    preempt_in: Option<u32>,    // Ticks until the current process is preempted by a woken process in a higher queue
+    preempted: BTreeMap<TID, u32>,  // Run counts of preempted processes, which resume rather than restart their quantum
+    idle_process: StrongPcbRef,
+    stats: SchedulerStats,
+}
+
+impl Default for MLFQScheduler {
+    fn default() -> Self {
+        MLFQScheduler::new(QUEUE_QUANTUM.to_vec(), BOOST_QUANTUM)
+    }
+}
+
+// Info about the process which is currently being executed in user mode
+struct Current {
+    process: StrongPcbRef,
+    // The level of the queue that the process was taken from
+    level: usize,
+    // The number of time quantum the current process has already been running for
+    run_count: u32
+}
+
+impl Current {
+
+    fn new(process: StrongPcbRef, level: usize) -> Current {
+        Current {
+            process,
+            level,
+            run_count: 0
+        }
+    }
+
+    fn incr_run_count(&mut self) {
+        self.run_count = self.run_count.saturating_add(1);         // Don't overflow
+    }
+}
+
+impl MLFQScheduler {
+
+    // quantums are the time quanta of each queue, from the top queue down
+    pub fn new(quantums: Vec<u32>, boost_quantum: u32) -> Self {
+        MLFQScheduler{
+            queues: MultiLevelQueue::new(quantums),
+            wake_queue: Default::default(),
+            current: None,
+            boost_epoch: 0,
+            boost_tracker: 0,
+            boost_quantum,
+            boost_enabled: true,
+            yield_demotes: true,
+            wakeup_preempt: true,
+            preempt_delay: 0,
+            preempt_in: None,
+            preempted: BTreeMap::new(),
+            idle_process: Rc::new(RefCell::new(idle_process())),
+            stats: Default::default(),
+        }
+    }
+
+    fn incr_boost_counter(&mut self) {
+        if !self.boost_enabled { return }
+        self.boost_tracker = self.boost_tracker + 1;
+        if self.boost_tracker > self.boost_quantum {
+            self.queues.boost();
+            // Parked processes are moved to the top when they are unparked
+            self.boost_epoch = self.boost_epoch.wrapping_add(1);
+            self.boost_tracker = 0;
+            self.stats.boosts = self.stats.boosts.wrapping_add(1);
+        }
+    }
+
+    // Queue a process at level if it can run, otherwise park it until it is woken
+    fn enqueue(&mut self, process: StrongPcbRef, level: usize, front: bool) {
+        let runnable = {
+            let mut pcb = process.borrow_mut();
+            let runnable = ready(&pcb);
+            let now = self.stats.ticks;
+            pcb.waiting_since = Some(if runnable { WaitingSince::Ready(now) } else { WaitingSince::Blocked(now) });
+            if !runnable { pcb.parked = Some(Parked { level, epoch: self.boost_epoch }) }
+            runnable
+        };
+        if runnable && front {
+            self.queues.push_front(level, process);
+        } else if runnable {
+            self.queues.push_back(level, process);
+        }
+    }
+
+    // The level a parked process returns to, it may have missed a boost or the queues may have been resized since
+    fn parked_level(&self, parked: &Parked) -> usize {
+        let level = if parked.epoch != self.boost_epoch { 0 } else { parked.level };
+        level.min(self.queues.levels() - 1)
+    }
+
+    // Take the next process to run, charging it for the time it spent waiting
+    fn dequeue(&mut self) -> Option<Current> {
+        let (process, level) = self.queues.pop()?;
+        settle(&process, self.stats.ticks);
+        let mut next = Current::new(process, level);
+        next.run_count = self.preempted.remove(&next.process.borrow().tid).unwrap_or(0);
+        Some(next)
+    }
+
+    // Move a parked process back to its queue, if it can now run
+    fn unpark(&mut self, process: &StrongPcbRef) {
+        let level = {
+            let mut pcb = process.borrow_mut();
+            if !ready(&pcb) { return }
+            let parked = match pcb.parked.take() {
+                Some(parked) => parked,
+                None => return,
+            };
+            let level = self.parked_level(&parked);
+            if level < parked.level { pcb.usage.boosts = pcb.usage.boosts.wrapping_add(1) }
+            level
+        };
+        settle(process, self.stats.ticks);
+        self.enqueue(Rc::clone(process), level, false);
+        self.woken(level);
+    }
+
+    // A process woken into a higher queue than the current process's preempts it, after at most preempt_delay ticks
+    fn woken(&mut self, level: usize) {
+        let higher = self.current.as_ref().map_or(false, |x| level < x.level);
+        if !self.wakeup_preempt || !higher { return }
+        self.preempt_in = Some(self.preempt_in.map_or(self.preempt_delay, |x| x.min(self.preempt_delay)));
+    }
+
+    fn preempt_due(&self) -> bool {
+        self.preempt_in == Some(0)
+    }
+
+    // Requeue every process which has been unblocked since the last time the scheduler ran
+    fn drain_wakeups(&mut self) {
+        loop {
+            let woken = self.wake_queue.borrow_mut().pop_front();
+            match woken {
+                // It may have been removed since
+                Some(woken) => if let Some(process) = woken.upgrade() { self.unpark(&process) },
+                None => break,
+            }
+        }
+    }
+
+    // The current process has been switched out, if it can't run again it is parked rather than queued
+    fn requeue(&mut self, process: StrongPcbRef, level: usize, front: bool) {
+        let status = process.borrow().status.clone();
+        if status == ProcessStatus::Exited || status == ProcessStatus::Terminated { return }
+        self.enqueue(process, level, front);
+    }
+
+    // Switch from the current process to next, or to idle if there is no next
+    fn switch(&mut self, current: Current, next: Option<Current>, level: usize, voluntary: bool, dispatch: Dispatch) {
+        self.switch_to(current, next, level, voluntary, false, dispatch)
+    }
+
+    // Give the CPU to a woken process from a higher queue, the current process goes back to the front of its queue
+    fn preempt(&mut self, dispatch: Dispatch) {
+        self.preempt_in = None;
+        // The woken process may have been killed since
+        let next = match self.dequeue() {
+            Some(next) => next,
+            None => return,
+        };
+        let current = self.current.take().unwrap();
+        let level = current.level;
+        self.preempted.insert(current.process.borrow().tid, current.run_count);
+        self.switch_to(current, Some(next), level, false, true, dispatch);
+    }
+
+    fn switch_to(&mut self, current: Current, next: Option<Current>, level: usize, voluntary: bool, front: bool, dispatch: Dispatch) {
+        {
+            let mut pcb = current.process.borrow_mut();
+            pcb.usage.switched_out(&mut self.stats, voluntary);
+            match &next {
+                Some(next) => dispatch(Some(&mut pcb), &mut next.process.borrow_mut()),
+                None => dispatch(Some(&mut pcb), &mut self.idle_process.borrow_mut()),
+            }
+        }
+        // Only after dispatch, as that is when an executing process becomes ready
+        self.requeue(current.process, level, front);
+        self.current = next;
+        self.preempt_in = None;
+    }
+
+    pub fn config(&self) -> MLFQConfig {
+        let quantums = self.queues.quantums();
+        let mut quantum = [0; MLFQ_MAX_LEVELS];
+        quantum[..quantums.len()].copy_from_slice(&quantums);
+        let mut flags = 0;
+        if self.boost_enabled { flags = flags | MLFQ_FLAG_BOOST }
+        if self.yield_demotes { flags = flags | MLFQ_FLAG_YIELD_DEMOTES }
+        if self.wakeup_preempt { flags = flags | MLFQ_FLAG_WAKEUP_PREEMPT }
+        MLFQConfig { levels: quantums.len() as u32, quantum, boost_interval: self.boost_quantum, flags, preempt_delay: self.preempt_delay }
+    }
+
+    // Processes keep their queue level, unless there are now fewer queues in which case they move to the bottom
+    pub fn configure(&mut self, config: &MLFQConfig) -> Result<(), String> {
+        let levels = config.levels as usize;
+        if levels == 0 || levels > MLFQ_MAX_LEVELS { return Err("invalid number of levels".to_string()) }
+        let quantums = config.quantum[..levels].to_vec();
+        if quantums.contains(&0) { return Err("invalid quantum".to_string()) }
+        if config.boost_interval == 0 { return Err("invalid boost interval".to_string()) }
+
+        if quantums != self.queues.quantums() {
+            self.queues.resize(quantums);
+            self.current.as_mut().map(|x| x.level = x.level.min(levels - 1));
+        }
+        self.boost_quantum = config.boost_interval;
+        self.boost_enabled = config.flags & MLFQ_FLAG_BOOST != 0;
+        self.yield_demotes = config.flags & MLFQ_FLAG_YIELD_DEMOTES != 0;
+        self.wakeup_preempt = config.flags & MLFQ_FLAG_WAKEUP_PREEMPT != 0;
+        self.preempt_delay = config.preempt_delay;
+        self.preempt_in = None;
+        Ok(())
+    }
+
+}
+
+impl Scheduler for MLFQScheduler {
+
+    fn stats(&self) -> &SchedulerStats {
+        &self.stats
+    }
+
+    fn mlfq_config(&self) -> Option<MLFQConfig> {
+        Some(self.config())
+    }
+
+    fn set_mlfq_config(&mut self, config: &MLFQConfig) -> Result<(), String> {
+        self.configure(config)
+    }
+
+    // Add new process to the scheduler
+    fn insert_process(&mut self, process: StrongPcbRef) {
+        if self.queues.contains(&process) || process.borrow().parked.is_some() { panic!("Process already in scheduler") }
+        process.borrow_mut().wake_queue = Some((Rc::clone(&self.wake_queue), Rc::downgrade(&process)));
+        process.borrow_mut().waiting_since = Some(WaitingSince::Ready(self.stats.ticks));
+        self.queues.push_front(0, process)
+    }
+
+    // Remove a process from the scheduler, will return None if process == current_process()
+    fn remove_process(&mut self, process: &StrongPcbRef) -> Option<StrongPcbRef> {
+        let tid = process.borrow().tid;
+        let removed = match self.queues.remove_process(process) {
+            Some((removed, _)) => removed,
+            None => {
+                if process.borrow_mut().parked.take().is_none() { return None }
+                Rc::clone(process)
+            }
+        };
+        settle(&removed, self.stats.ticks);
+        self.preempted.remove(&tid);
+        removed.borrow_mut().wake_queue = None;
+        Some(removed)
+    }
+
+    fn current_process(&self) -> Option<StrongPcbRef> {
+        self.current.as_ref().map(|x| Rc::clone(&x.process))
+    }
+
+    // The queue level of a process, and its run count if it is current
+    fn placement(&self, process: &StrongPcbRef) -> (Option<usize>, u32) {
+        match &self.current {
+            Some(current) if Rc::ptr_eq(&current.process, process) => (Some(current.level), current.run_count),
+            _ => {
+                let parked = process.borrow().parked.as_ref().map(|x| self.parked_level(x));
+                (self.queues.level_of(process).or(parked), 0)
+            }
+        }
+    }
+
+    // A stopped process can't wait in a queue, and a continued one may be able to run again
+    fn status_changed(&mut self, process: &StrongPcbRef) {
+        if ready(&process.borrow()) {
+            self.unpark(process);
+        } else if let Some((process, level)) = self.queues.remove_process(process) {
+            settle(&process, self.stats.ticks);
+            self.enqueue(process, level, false);
+        }
+    }
+
+    fn schedule(&mut self, src: ScheduleSource, dispatch: Dispatch) {
+        self.drain_wakeups();
+        match src {
+            // A reset means no process is currently running
+            ScheduleSource::Reset => {
+                let next = self.dequeue().expect("No process found");
+                dispatch(None, &mut next.process.borrow_mut());
+                self.current = Some(next);
+            }
+
+            // Timer preemption
+            ScheduleSource::Timer => {
+                self.stats.ticks = self.stats.ticks.wrapping_add(1);
+                self.incr_boost_counter();
+                match self.current.as_mut() {
+                    Some(current) => {
+                        current.incr_run_count();
+                        {
+                            let mut pcb = current.process.borrow_mut();
+                            pcb.usage.user_ticks = pcb.usage.user_ticks.wrapping_add(1);
+                        }
+                        // A kernel thread can block itself, it is switched out by the next interrupt
+                        let runnable = {
+                            let pcb = current.process.borrow();
+                            pcb.status == ProcessStatus::Executing && !pcb.stopped
+                        };
+                        if !runnable {
+                            let current = self.current.take().unwrap();
+                            let next = self.dequeue();
+                            let level = current.level;
+                            return self.switch(current, next, level, true, dispatch)
+                        }
+                        let quantum_used = current.run_count >= self.queues.quantum(current.level);
+                        self.preempt_in = self.preempt_in.map(|x| x.saturating_sub(1));
+                        if self.preempt_due() && !quantum_used { return self.preempt(dispatch) }
+
+                        // If it has used up its run count, move to the next top process, but only if one is ready
+                        if !quantum_used { return }
+                        let next = match self.dequeue() {
+                            Some(next) => next,
+                            None => return,
+                        };
+                        let current = self.current.take().unwrap();
+                        // Move the current to a lower/same queue
+                        let below = self.queues.below(current.level);
+                        if below != current.level {
+                            current.process.borrow_mut().usage.demoted(&mut self.stats);
+                        }
+                        self.switch(current, Some(next), below, false, dispatch);
+                    }
+                    None => self.stats.idle_ticks = self.stats.idle_ticks.wrapping_add(1),
+                }
+            },
+
+            ScheduleSource::Svc { id } => {
+                // There must have been a current process to have made a service call
+                let (status, stopped) = {
+                    let pcb = self.current.as_ref().unwrap().process.borrow();
+                    (pcb.status.clone(), pcb.stopped)
+                };
+                let runnable = status == ProcessStatus::Executing && !stopped;
+                // The call may have woken a process from a higher queue, e.g. by writing to a pipe
+                if runnable && id != SysCall::Yield && self.preempt_due() { return self.preempt(dispatch) }
+                if runnable && id != SysCall::Yield { return }
+
+                // Try to move to the next top process, if one exists
+                let next = self.dequeue();
+
+                // If there are no new processes, keep executing this one unless it can no longer run
+                if next.is_none() && runnable { return }
+
+                let current = self.current.take().unwrap();
+                // If Sys Yield then move down queue, unless disabled
+                // If below max quantum count then move up queue
+                // Otherwise stay at same queue level
+                let level = if id == SysCall::Yield && !self.yield_demotes {
+                    current.level
+                } else if id == SysCall::Yield {
+                    let below = self.queues.below(current.level);
+                    if below != current.level {
+                        current.process.borrow_mut().usage.demoted(&mut self.stats);
+                    }
+                    below
+                } else if current.run_count < self.queues.quantum(current.level) {
+                    self.queues.above(current.level)
+                } else {
+                    current.level
+                };
+
+                // Yielding, blocking and exiting give up the CPU, anything else was a preemption
+                let voluntary = id == SysCall::Yield || status != ProcessStatus::Executing;
+                self.switch(current, next, level, voluntary, dispatch);
+            }
+
+            ScheduleSource::Io | ScheduleSource::Fault => {
+                match self.current.take() {
+                    // Once IO has completed we may no longer need to idle
+                    None => {
+                        if let Some(next) = self.dequeue() {
+                            dispatch(Some(&mut self.idle_process.borrow_mut()), &mut next.process.borrow_mut());
+                            self.current = Some(next);
+                        }
+                    }
+                    // The IO or fault may have signalled the current process to stop or terminate
+                    Some(current) => {
+                        let runnable = {
+                            let pcb = current.process.borrow();
+                            pcb.status == ProcessStatus::Executing && !pcb.stopped
+                        };
+                        if runnable {
+                            self.current = Some(current);
+                            if self.preempt_due() { self.preempt(dispatch) }
+                            return
+                        }
+                        let next = self.dequeue();
+                        let level = current.level;
+                        self.switch(current, next, level, false, dispatch);
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Charge a process for the time it has been waiting, now that it has stopped
+fn settle(process: &StrongPcbRef, now: u32) {
+    let mut pcb = process.borrow_mut();
+    if let Some(since) = pcb.waiting_since.take() {
+        pcb.usage.charge(since, now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::process::scheduler::mlfq::{MLFQConfig, MLFQ_FLAG_BOOST, MLFQ_FLAG_YIELD_DEMOTES, MLFQ_FLAG_WAKEUP_PREEMPT};
+    use crate::process::scheduler::{SchedulerKind, SCHED_GET_MLFQ, SCHED_SET_MLFQ};
+    use crate::process::{ProcessManager, Context, ScheduleSource, FidTable};
+    use crate::process::testing::{main_test, current_pid};
+    use crate::process::signal::{SIG_TSTP, SIG_CONT};
+    use crate::SysCall;
+    use alloc::vec::Vec;
+
+    fn get_config(manager: &mut ProcessManager) -> MLFQConfig {
+        let mut config = MLFQConfig { levels: 0, quantum: [0; 8], boost_interval: 0, flags: 0, preempt_delay: 0 };
+        manager.sched_ctl(SCHED_GET_MLFQ, &mut config).unwrap();
+        config
+    }
+
+    #[test]
+    fn defaults_test() {
+        let mut manager = ProcessManager::new(SchedulerKind::MLFQ);
+        let config = get_config(&mut manager);
+        assert_eq!(config.levels, 4);
+        assert_eq!(&config.quantum[..5], &[2, 4, 8, 16, 0]);
+        assert_eq!(config.boost_interval, 50);
+        assert_eq!(config.flags, MLFQ_FLAG_BOOST | MLFQ_FLAG_YIELD_DEMOTES | MLFQ_FLAG_WAKEUP_PREEMPT);
+        assert_eq!(config.preempt_delay, 0);
+        // Other schedulers can't be tuned
+        let mut other = ProcessManager::new(SchedulerKind::RoundRobin);
+        assert!(other.sched_ctl(SCHED_GET_MLFQ, &mut config.clone()).is_err());
+    }
+
+    #[test]
+    fn configure_test() {
+        let mut manager = ProcessManager::new(SchedulerKind::MLFQ);
+        let first = manager.create_process(main_test, FidTable::default());
+        let mut ctx = Context::new(0, 0);
+        manager.dispatch(&mut ctx, ScheduleSource::Reset);
+        let child = manager.fork(&ctx);
+
+        // Demote both processes to the bottom queue
+        (0..40).for_each(|_| manager.dispatch(&mut ctx, ScheduleSource::Timer));
+        assert_eq!(manager.proc_list(&ctx).iter().map(|x| x.queue_level).collect::<Vec<_>>(), vec![3, 3]);
+
+        let mut config = get_config(&mut manager);
+        config.levels = 2;
+        config.quantum = [2, 3, 0, 0, 0, 0, 0, 0];
+        config.flags = MLFQ_FLAG_YIELD_DEMOTES;
+        manager.sched_ctl(SCHED_SET_MLFQ, &mut config).unwrap();
+        assert_eq!(manager.proc_list(&ctx).iter().map(|x| x.queue_level).collect::<Vec<_>>(), vec![1, 1]);
+
+        // Scheduling continues with the new quanta, and without boosts
+        let before = manager.usage(first).unwrap().user_ticks + manager.usage(child).unwrap().user_ticks;
+        (0..120).for_each(|_| manager.dispatch(&mut ctx, ScheduleSource::Timer));
+        assert_eq!(manager.usage(first).unwrap().user_ticks + manager.usage(child).unwrap().user_ticks, before + 120);
+        assert_eq!(manager.scheduler_stats().boosts, 0);
+        assert_eq!(get_config(&mut manager), config);
+
+        // Invalid configurations are rejected, leaving the current one
+        config.levels = 9;
+        assert!(manager.sched_ctl(SCHED_SET_MLFQ, &mut config.clone()).is_err());
+        config.levels = 2;
+        config.quantum[0] = 0;
+        assert!(manager.sched_ctl(SCHED_SET_MLFQ, &mut config.clone()).is_err());
+        assert_eq!(get_config(&mut manager).quantum[0], 2);
+    }
+
+    #[test]
+    fn parked_test() {
+        let mut manager = ProcessManager::new(SchedulerKind::MLFQ);
+        let first = manager.create_process(main_test, FidTable::default());
+        let mut ctx = Context::new(0, 0);
+        manager.dispatch(&mut ctx, ScheduleSource::Reset);
+        let child = manager.fork(&ctx);
+        (0..40).for_each(|_| manager.dispatch(&mut ctx, ScheduleSource::Timer));
+        let current = manager.current_process().unwrap().borrow().pid;
+        let other = if current == first { child } else { first };
+        let level = |manager: &ProcessManager, ctx: &Context| manager.proc_list(ctx).iter().find(|x| x.pid == other).unwrap().queue_level;
+
+        // A stopped process keeps its level, which follows the queues being resized
+        manager.signal(other, SIG_TSTP).unwrap();
+        assert_eq!(level(&manager, &ctx), 3);
+        let mut config = get_config(&mut manager);
+        config.levels = 2;
+        config.boost_interval = 5;
+        manager.sched_ctl(SCHED_SET_MLFQ, &mut config).unwrap();
+        assert_eq!(level(&manager, &ctx), 1);
+
+        // And returns to the top if it missed a boost
+        (0..6).for_each(|_| manager.dispatch(&mut ctx, ScheduleSource::Timer));
+        assert_eq!(manager.scheduler_stats().boosts, 1);
+        assert_eq!(level(&manager, &ctx), 0);
+        manager.signal(other, SIG_CONT).unwrap();
+        manager.dispatch(&mut ctx, ScheduleSource::Io);
+        assert_eq!((level(&manager, &ctx), manager.usage(other).unwrap().boosts), (0, 1));
+    }
+
+    #[test]
+    fn yield_test() {
+        let mut manager = ProcessManager::new(SchedulerKind::MLFQ);
+        manager.create_process(main_test, FidTable::default());
+        let mut ctx = Context::new(0, 0);
+        manager.dispatch(&mut ctx, ScheduleSource::Reset);
+        manager.fork(&ctx);
+        let mut config = get_config(&mut manager);
+        config.flags = MLFQ_FLAG_BOOST;
+        manager.sched_ctl(SCHED_SET_MLFQ, &mut config).unwrap();
+        (0..10).for_each(|_| manager.dispatch(&mut ctx, ScheduleSource::Svc { id: SysCall::Yield }));
+        assert!(manager.proc_list(&ctx).iter().all(|x| x.queue_level == 0));
+        assert_eq!(manager.scheduler_stats().demotions, 0);
+    }
+
+    #[test]
+    fn privilege_test() {
+        let mut manager = ProcessManager::new(SchedulerKind::MLFQ);
+        manager.create_process(main_test, FidTable::default());
+        let mut ctx = Context::new(0, 0);
+        manager.dispatch(&mut ctx, ScheduleSource::Reset);
+        manager.fork(&ctx);
+        let mut config = get_config(&mut manager);
+        // Switch to the child, which was not started by the kernel
+        manager.dispatch(&mut ctx, ScheduleSource::Svc { id: SysCall::Yield });
+        assert!(manager.current_process().unwrap().borrow().parent.is_some());
+        assert!(manager.sched_ctl(SCHED_SET_MLFQ, &mut config).is_err());
+        assert!(manager.sched_ctl(SCHED_GET_MLFQ, &mut config).is_ok());
+    }
+
+    #[test]
+    fn wakeup_test() {
+        let mut manager = ProcessManager::new(SchedulerKind::MLFQ);
+        manager.create_process(main_test, FidTable::default());
+        let mut ctx = Context::new(0, 0);
+        manager.dispatch(&mut ctx, ScheduleSource::Reset);
+        manager.fork(&ctx);
+        let blocked = manager.current_process().unwrap();
+        let pid = blocked.borrow().pid;
+        blocked.borrow_mut().set_blocked();
+        manager.dispatch(&mut ctx, ScheduleSource::Svc { id: SysCall::Read });
+
+        // Blocked processes are parked rather than queued, but keep their level
+        assert_eq!(blocked.borrow().queue_slot, None);
+        assert!(manager.proc_list(&ctx).iter().all(|x| x.queue_level >= 0));
+        (0..10).for_each(|_| manager.dispatch(&mut ctx, ScheduleSource::Timer));
+        let usage = manager.usage(pid).unwrap();
+        assert_eq!((usage.user_ticks, usage.blocked_ticks), (0, 10));
+
+        // Once unblocked it is queued again at the next schedule
+        blocked.borrow_mut().set_unblocked(0);
+        (0..10).for_each(|_| manager.dispatch(&mut ctx, ScheduleSource::Timer));
+        let usage = manager.usage(pid).unwrap();
+        assert!(usage.user_ticks > 0);
+        assert_eq!(usage.blocked_ticks, 10);
+    }
+
+    // An interactive process repeatedly blocks for input while a CPU bound process runs, returns the
+    // number of ticks from each input arriving until the interactive process runs
+    fn input_latency(flags: u32, preempt_delay: u32) -> Vec<u32> {
+        let mut manager = ProcessManager::new(SchedulerKind::MLFQ);
+        manager.create_process(main_test, FidTable::default());
+        manager.create_process(main_test, FidTable::default());
+        let mut ctx = Context::new(0, 0);
+        manager.dispatch(&mut ctx, ScheduleSource::Reset);
+        let mut config = get_config(&mut manager);
+        config.flags = flags;
+        config.preempt_delay = preempt_delay;
+        manager.sched_ctl(SCHED_SET_MLFQ, &mut config).unwrap();
+
+        let interactive = current_pid(&mut manager).unwrap();
+        let mut latencies = Vec::new();
+        for round in 0..20 {
+            assert_eq!(current_pid(&mut manager), Some(interactive));
+            manager.current_process().unwrap().borrow_mut().set_blocked();
+            manager.dispatch(&mut ctx, ScheduleSource::Svc { id: SysCall::Read });
+            (0..(5 + round % 7)).for_each(|_| manager.dispatch(&mut ctx, ScheduleSource::Timer));
+
+            // Input arrives from a device, or a pipe written to by the running process
+            manager.table.get(&interactive).unwrap().borrow_mut().set_unblocked(0);
+            let src = if round % 2 == 0 { ScheduleSource::Io } else { ScheduleSource::Svc { id: SysCall::Write } };
+            manager.dispatch(&mut ctx, src);
+            let mut latency = 0;
+            while current_pid(&mut manager) != Some(interactive) {
+                manager.dispatch(&mut ctx, ScheduleSource::Timer);
+                latency = latency + 1;
+            }
+            latencies.push(latency);
+
+            // Blocking often keeps it in the top queue
+            let level = manager.proc_list(&ctx).iter().find(|x| x.pid == interactive).unwrap().queue_level;
+            assert_eq!(level, 0);
+        }
+        latencies
+    }
+
+    #[test]
+    fn wakeup_preempt_test() {
+        let latencies = input_latency(MLFQ_FLAG_WAKEUP_PREEMPT, 0);
+        // The first input arrives while the CPU bound process is still in the top queue
+        assert!(latencies[1..].iter().all(|x| *x == 0), "{:?}", latencies);
+    }
+
+    #[test]
+    fn preempt_delay_test() {
+        let bounded = input_latency(MLFQ_FLAG_WAKEUP_PREEMPT, 3);
+        assert!(bounded.iter().all(|x| *x <= 3), "{:?}", bounded);
+        assert!(bounded.iter().any(|x| *x > 0), "{:?}", bounded);
+        // Without preemption the interactive process waits for the rest of the bottom queue's quantum
+        let unbounded = input_latency(0, 0);
+        assert!(unbounded.iter().any(|x| *x > 3), "{:?}", unbounded);
+    }
+}
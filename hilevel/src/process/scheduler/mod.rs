@@ -1,171 +1,284 @@
 mod queues;
 mod idle;
+mod mlfq;
+mod policy;
+mod round_robin;
+mod stride;
+mod priority;
+#[cfg(test)]
+mod bench;
+
+pub use mlfq::{MLFQScheduler, MLFQConfig, Parked};
 
 use crate::process::{ProcessControlBlock, StrongPcbRef, ScheduleSource, ProcessStatus};
-use alloc::rc::Rc;
-use queues::{MultiLevelQueue, LinkedQueues, StrongQueueLevelRef};
-use crate::process::scheduler::queues::QueueLevel;
-use crate::SysCall;
-use core::cell::RefCell;
-use crate::process::scheduler::idle::idle_process;
-
-const BOOST_QUANTUM: u32 = 50;
-
-pub struct MLFQScheduler {
-    queues: MultiLevelQueue,
-    current: Option<Current>,
-    boost_tracker: u32,
-    idle_process: StrongPcbRef
-}
+use crate::process::info::SchedulerStats;
+use crate::process::scheduler::policy::PolicyScheduler;
+use crate::process::scheduler::round_robin::RoundRobin;
+use crate::process::scheduler::stride::Stride;
+use crate::process::scheduler::priority::FixedPriority;
+use alloc::boxed::Box;
+use alloc::string::{String, ToString};
 
-impl Default for MLFQScheduler {
-    fn default() -> Self {
-        MLFQScheduler{
-            queues: Default::default(),
-            current: None,
-            boost_tracker: 0,
-            idle_process: Rc::new(RefCell::new(idle_process()))
-        }
+// Called to switch from the previous process (None if no process was running) to the next
+pub type Dispatch<'a> = &'a mut dyn FnMut(Option<&mut ProcessControlBlock>, &mut ProcessControlBlock);
+
+pub trait Scheduler {
+
+    // Add new process to the scheduler
+    fn insert_process(&mut self, process: StrongPcbRef);
+
+    // Remove a process from the scheduler, will return None if process == current_process()
+    fn remove_process(&mut self, process: &StrongPcbRef) -> Option<StrongPcbRef>;
+
+    fn current_process(&self) -> Option<StrongPcbRef>;
+
+    // Decide which process should run next, calling dispatch if it isn't the current process
+    fn schedule(&mut self, src: ScheduleSource, dispatch: Dispatch);
+
+    // The queue level of a process, and its run count if it is current
+    fn placement(&self, process: &StrongPcbRef) -> (Option<usize>, u32);
+
+    fn stats(&self) -> &SchedulerStats;
+
+    // Called when a process which may not be current is stopped or continued
+    fn status_changed(&mut self, _process: &StrongPcbRef) {}
+
+    // Only the MLFQ scheduler can be tuned
+    fn mlfq_config(&self) -> Option<MLFQConfig> { None }
+
+    fn set_mlfq_config(&mut self, _config: &MLFQConfig) -> Result<(), String> {
+        Err("not an MLFQ scheduler".to_string())
     }
 }
 
-// Info about the process which is currently being executed in user mode
-struct Current {
-    process: StrongPcbRef,
-    // The queue that the process was taken from
-    queue: StrongQueueLevelRef,
-    // The number of time quantum the current process has already been running for
-    run_count: u32
+// SchedCtl requests, must match libc.h
+pub const SCHED_GET_MLFQ: u32 = 0;
+pub const SCHED_SET_MLFQ: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SchedulerKind {
+    MLFQ,
+    RoundRobin,
+    Stride,
+    FixedPriority,
 }
 
-impl Current {
+#[cfg(any(
+    all(feature = "sched_round_robin", feature = "sched_stride"),
+    all(feature = "sched_round_robin", feature = "sched_priority"),
+    all(feature = "sched_stride", feature = "sched_priority"),
+))]
+compile_error!("At most one of the sched_* features can be enabled");
 
-    fn new(process: StrongPcbRef, queue: StrongQueueLevelRef) -> Current {
-        Current {
-            process,
-            queue,
-            run_count: 0
+impl Default for SchedulerKind {
+
+    // Chosen at build time with the sched_* cargo features, the MLFQ is used if none are enabled
+    fn default() -> Self {
+        if cfg!(feature = "sched_round_robin") {
+            SchedulerKind::RoundRobin
+        } else if cfg!(feature = "sched_stride") {
+            SchedulerKind::Stride
+        } else if cfg!(feature = "sched_priority") {
+            SchedulerKind::FixedPriority
+        } else {
+            SchedulerKind::MLFQ
         }
     }
+}
 
-    fn incr_run_count(&mut self) {
-        self.run_count = self.run_count.saturating_add(1);         // Don't overflow
+pub fn new_scheduler(kind: SchedulerKind) -> Box<dyn Scheduler> {
+    match kind {
+        SchedulerKind::MLFQ => Box::new(MLFQScheduler::default()),
+        SchedulerKind::RoundRobin => Box::new(PolicyScheduler::new(RoundRobin::default())),
+        SchedulerKind::Stride => Box::new(PolicyScheduler::new(Stride::default())),
+        SchedulerKind::FixedPriority => Box::new(PolicyScheduler::new(FixedPriority::default())),
     }
 }
 
-impl MLFQScheduler {
+fn ready(process: &ProcessControlBlock) -> bool {
+    process.status == ProcessStatus::Ready && !process.stopped
+}
 
-    fn incr_boost_counter(&mut self) {
-        self.boost_tracker = self.boost_tracker + 1;
-        if self.boost_tracker > BOOST_QUANTUM {
-            self.queues.boost();
-            self.boost_tracker = 0
-        }
+// Charge a tick to a process that is waiting, the current process is charged separately
+fn charge_waiting(process: &StrongPcbRef) {
+    let mut pcb = process.borrow_mut();
+    if ready(&pcb) {
+        pcb.usage.ready_ticks = pcb.usage.ready_ticks.wrapping_add(1);
+    } else {
+        pcb.usage.blocked_ticks = pcb.usage.blocked_ticks.wrapping_add(1);
     }
+}
 
-    // Add new process to the scheduler
-    pub fn insert_process(&mut self, process: StrongPcbRef) {
-        if self.queues.contains(&process) { panic!("Process already in scheduler") }
-        self.queues.top_queue().borrow_mut().push_front(process)
-    }
+// The same workloads are run through every scheduler
+#[cfg(test)]
+mod tests {
+    use crate::process::{ProcessManager, Context, ScheduleSource, FidTable, ProcessStatus, PID};
+    use crate::process::testing::{main_test, current_pid};
+    use crate::process::scheduler::SchedulerKind;
+    use crate::process::signal::{SIG_TSTP, SIG_CONT};
+    use crate::SysCall;
+    use alloc::vec::Vec;
 
-    // Remove a process from the scheduler, will return None if process == current_process()
-    pub fn remove_process(&mut self, process: &StrongPcbRef) -> Option<StrongPcbRef> {
-        self.queues.remove_process(process)
+    const KINDS: &[SchedulerKind] = &[SchedulerKind::MLFQ, SchedulerKind::RoundRobin, SchedulerKind::Stride, SchedulerKind::FixedPriority];
+
+    fn start(kind: SchedulerKind, count: usize) -> (ProcessManager, Vec<PID>, Context) {
+        let mut manager = ProcessManager::new(kind);
+        let pids = (0..count).map(|_| manager.create_process(main_test, FidTable::default())).collect();
+        let mut ctx = Context::new(0, 0);
+        manager.dispatch(&mut ctx, ScheduleSource::Reset);
+        (manager, pids, ctx)
     }
 
-    pub fn current_process(&self) -> Option<StrongPcbRef> {
-        self.current.as_ref().map(|x| Rc::clone(&x.process))
+    // Only an executing process may be current
+    fn check_current(manager: &mut ProcessManager) {
+        if let Some(current) = manager.current_process() {
+            let pcb = current.borrow();
+            assert!(pcb.status == ProcessStatus::Executing && !pcb.stopped);
+        }
     }
 
-    pub fn schedule<F>(&mut self, src: ScheduleSource, mut dispatch: F)
-        where F: FnMut(Option<&mut ProcessControlBlock>, &mut ProcessControlBlock)
-    {
-        match src {
-            // A reset means no process is currently running
-            ScheduleSource::Reset => {
-                let (next_p, from_q) = self.queues.pop_process(ready).expect("No process found");
-                dispatch(None, &mut (*next_p).borrow_mut());
-                self.current = Some(Current::new(next_p, from_q));
+    #[test]
+    fn cpu_bound_test() {
+        for kind in KINDS {
+            let (mut manager, pids, mut ctx) = start(*kind, 3);
+            (0..300).for_each(|_| manager.dispatch(&mut ctx, ScheduleSource::Timer));
+            let stats = manager.scheduler_stats();
+            assert_eq!((stats.ticks, stats.idle_ticks, stats.voluntary_switches), (300, 0, 0));
+            let mut total = 0;
+            for pid in pids.iter() {
+                let usage = manager.usage(*pid).unwrap();
+                assert_eq!(usage.user_ticks + usage.ready_ticks + usage.blocked_ticks, 300, "{:?}", kind);
+                assert!(usage.user_ticks >= 80, "{:?} {:?}", kind, usage);
+                total = total + usage.user_ticks;
             }
+            assert_eq!(total, 300);
+        }
+    }
 
-            // Timer preemption
-            ScheduleSource::Timer => {
-                self.incr_boost_counter();
-                if self.current.is_some() {
-                    let current = self.current.as_mut().unwrap();
-                    current.incr_run_count();
-
-                    // If it has used up its run count, try to move to next top process
-                    if current.run_count >= QueueLevel::quantum(&(*current.queue).borrow()) {
-
-                        // Switch to next process only if one is ready
-                        let next = self.queues.pop_process(ready).map(|(next_p, from_q)| {
-                            // Move the current to a lower/same queue
-                            let below = LinkedQueues::below(&current.queue).unwrap_or(Rc::clone(&current.queue));
-                            below.borrow_mut().push_back(Rc::clone(&current.process));
-                            dispatch(Some(&mut current.process.borrow_mut()), &mut next_p.borrow_mut());
-                            Current::new(next_p, from_q)
-                        });
-                        next.map(|n| self.current = Some(n));
-                    }
-                }
-            },
-
-            ScheduleSource::Svc { id } => {
-                // There must have been a current process to have made a service call
-                let current = self.current.as_mut().unwrap();
-                let current_status = (*current.process).borrow().status.clone();
-
-                // Move current process back onto the MultiLevelQueue iff it is not terminated
-                let move_current_back_to_queue = || {
-                    if current_status != ProcessStatus::Terminated {
-                        // If Sys Yield then move down queue
-                        // If below max quantum count then move up queue
-                        // Otherwise stay at same queue level
-                        if id == SysCall::Yield {
-                            LinkedQueues::below(&current.queue).unwrap_or(Rc::clone(&current.queue))
-                        } else if current.run_count < QueueLevel::quantum(&(*current.queue).borrow()) {
-                            LinkedQueues::above(&current.queue).unwrap_or(Rc::clone(&current.queue))
-                        } else {
-                            Rc::clone(&current.queue)
-                        }.borrow_mut().push_back(Rc::clone(&current.process));
-                    }
-                };
-
-                // Try to move to the next top process, if one exists
-                let next = self.queues.pop_process(ready).map(|(next_p, from_q)| {
-                    move_current_back_to_queue();
-                    dispatch(Some(&mut current.process.borrow_mut()), &mut next_p.borrow_mut());
-                    Current::new(next_p, from_q)
-                });
-
-                // If there are no new processes and this one is no longer executing then we must idle
-                if next.is_none() && current_status != ProcessStatus::Executing {
-                    move_current_back_to_queue();
-                    dispatch(Some(&mut current.process.borrow_mut()), &mut (self.idle_process.borrow_mut()));
-                    self.current = None;
+    #[test]
+    fn blocking_test() {
+        for kind in KINDS {
+            // The first process blocks after every tick it runs for, until its IO completes 3 ticks later
+            let (mut manager, pids, mut ctx) = start(*kind, 2);
+            let mut unblock_at = None;
+            let mut blocks = 0;
+            for tick in 0..300 {
+                manager.dispatch(&mut ctx, ScheduleSource::Timer);
+                check_current(&mut manager);
+                if unblock_at == Some(tick) {
+                    manager.table.get(&pids[0]).unwrap().borrow_mut().set_unblocked(0);
+                    manager.dispatch(&mut ctx, ScheduleSource::Io);
+                    unblock_at = None;
+                } else if current_pid(&mut manager) == Some(pids[0]) && manager.usage(pids[0]).unwrap().user_ticks > blocks {
+                    blocks = blocks + 1;
+                    manager.current_process().unwrap().borrow_mut().set_blocked();
+                    manager.dispatch(&mut ctx, ScheduleSource::Svc { id: SysCall::Read });
+                    unblock_at = Some(tick + 3);
                 }
-
-                // If we were able to switch to a new process, then update current
-                next.map(|n| self.current = Some(n));
+                check_current(&mut manager);
             }
+            let usage = manager.usage(pids[0]).unwrap();
+            assert!(blocks >= 10, "{:?} {:?}", kind, usage);
+            assert_eq!(usage.voluntary_switches, blocks, "{:?}", kind);
+            assert!(usage.blocked_ticks >= blocks * 2, "{:?} {:?}", kind, usage);
+            let other = manager.usage(pids[1]).unwrap();
+            assert_eq!(other.voluntary_switches, 0);
+            let stats = manager.scheduler_stats();
+            assert_eq!(usage.user_ticks + other.user_ticks + stats.idle_ticks, 300, "{:?}", kind);
+        }
+    }
 
-            ScheduleSource::Io => {
-                // Once IO has completed we may no longer need to idle
-                if self.current.is_none() {
-                    let next = self.queues.pop_process(ready).map(|(next_p, from_q)| {
-                        dispatch(Some(&mut (self.idle_process.borrow_mut())), &mut (*next_p).borrow_mut());
-                        Current::new(next_p, from_q)
-                    });
-                    next.map(|x| { self.current = Some(x); });
-                }
+    #[test]
+    fn idle_test() {
+        for kind in KINDS {
+            let (mut manager, pids, mut ctx) = start(*kind, 1);
+            manager.current_process().unwrap().borrow_mut().set_blocked();
+            manager.dispatch(&mut ctx, ScheduleSource::Svc { id: SysCall::Read });
+            assert_eq!(current_pid(&mut manager), None);
+            (0..10).for_each(|_| manager.dispatch(&mut ctx, ScheduleSource::Timer));
+            assert_eq!(manager.scheduler_stats().idle_ticks, 10);
+            assert_eq!(manager.usage(pids[0]).unwrap().blocked_ticks, 10);
+            // Completing the IO ends the idling
+            manager.table.get(&pids[0]).unwrap().borrow_mut().set_unblocked(0);
+            manager.dispatch(&mut ctx, ScheduleSource::Io);
+            assert_eq!(current_pid(&mut manager), Some(pids[0]), "{:?}", kind);
+        }
+    }
 
+    #[test]
+    fn exit_test() {
+        for kind in KINDS {
+            let (mut manager, pids, mut ctx) = start(*kind, 3);
+            let exited = current_pid(&mut manager).unwrap();
+            manager.exit(0);
+            manager.dispatch(&mut ctx, ScheduleSource::Svc { id: SysCall::Exit });
+            for _ in 0..100 {
+                manager.dispatch(&mut ctx, ScheduleSource::Timer);
+                assert_ne!(current_pid(&mut manager), Some(exited), "{:?}", kind);
             }
+            assert!(pids.iter().filter(|x| **x != exited).all(|x| manager.usage(*x).unwrap().user_ticks > 0));
         }
     }
-}
 
-fn ready(process: &ProcessControlBlock) -> bool {
-    process.status == ProcessStatus::Ready
+    #[test]
+    fn stop_test() {
+        for kind in KINDS {
+            let (mut manager, pids, mut ctx) = start(*kind, 2);
+            let stopped = current_pid(&mut manager).unwrap();
+            let other = *pids.iter().find(|x| **x != stopped).unwrap();
+            manager.signal(stopped, SIG_TSTP).unwrap();
+            manager.dispatch(&mut ctx, ScheduleSource::Io);
+            for _ in 0..50 {
+                manager.dispatch(&mut ctx, ScheduleSource::Timer);
+                assert_eq!(current_pid(&mut manager), Some(other), "{:?}", kind);
+            }
+            assert_eq!(manager.usage(stopped).unwrap().blocked_ticks, 50);
+            manager.signal(stopped, SIG_CONT).unwrap();
+            let before = manager.usage(stopped).unwrap().user_ticks;
+            (0..50).for_each(|_| manager.dispatch(&mut ctx, ScheduleSource::Timer));
+            assert!(manager.usage(stopped).unwrap().user_ticks > before, "{:?}", kind);
+        }
+    }
+
+    #[test]
+    fn stride_share_test() {
+        let (mut manager, pids, mut ctx) = start(SchedulerKind::Stride, 2);
+        // Half as many tickets
+        manager.set_nice(pids[1], 10).unwrap();
+        (0..600).for_each(|_| manager.dispatch(&mut ctx, ScheduleSource::Timer));
+        let first = manager.usage(pids[0]).unwrap().user_ticks;
+        let second = manager.usage(pids[1]).unwrap().user_ticks;
+        assert!(first >= 390 && first <= 410, "{} {}", first, second);
+    }
+
+    #[test]
+    fn priority_test() {
+        let (mut manager, pids, mut ctx) = start(SchedulerKind::FixedPriority, 2);
+        manager.set_nice(pids[1], -5).unwrap();
+        (0..50).for_each(|_| manager.dispatch(&mut ctx, ScheduleSource::Timer));
+        // The higher priority process takes over at the next tick, and then never gives up the CPU
+        assert_eq!(manager.usage(pids[0]).unwrap().user_ticks, 1);
+        assert_eq!(current_pid(&mut manager), Some(pids[1]));
+
+        // Once it blocks the lower priority process runs, until it is preempted by the IO completing
+        manager.current_process().unwrap().borrow_mut().set_blocked();
+        manager.dispatch(&mut ctx, ScheduleSource::Svc { id: SysCall::Read });
+        assert_eq!(current_pid(&mut manager), Some(pids[0]));
+        manager.table.get(&pids[1]).unwrap().borrow_mut().set_unblocked(0);
+        manager.dispatch(&mut ctx, ScheduleSource::Io);
+        manager.dispatch(&mut ctx, ScheduleSource::Timer);
+        assert_eq!(current_pid(&mut manager), Some(pids[1]));
+    }
+
+    #[test]
+    fn round_robin_test() {
+        let (mut manager, pids, mut ctx) = start(SchedulerKind::RoundRobin, 3);
+        let mut order = Vec::new();
+        for _ in 0..24 {
+            order.push(current_pid(&mut manager).unwrap());
+            manager.dispatch(&mut ctx, ScheduleSource::Timer);
+        }
+        let expected: Vec<PID> = (0..24).map(|i| pids[(i / 4) % 3]).collect();
+        assert_eq!(order, expected);
+    }
 }
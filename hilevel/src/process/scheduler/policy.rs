@@ -0,0 +1,204 @@
+use crate::process::{ProcessControlBlock, StrongPcbRef, ScheduleSource, ProcessStatus};
+use crate::process::scheduler::{Scheduler, Dispatch, ready, charge_waiting};
+use crate::process::scheduler::idle::idle_process;
+use crate::process::info::SchedulerStats;
+use crate::SysCall;
+use alloc::collections::VecDeque;
+use alloc::rc::Rc;
+use core::cell::RefCell;
+
+// Orders the processes of a PolicyScheduler which aren't currently running
+pub trait Policy {
+
+    // Add a process which has never run
+    fn insert(&mut self, process: StrongPcbRef);
+
+    // Return a process after it ran for ticks, it may no longer be ready
+    fn requeue(&mut self, process: StrongPcbRef, ticks: u32);
+
+    // Remove the ready process that should run next
+    fn pop(&mut self) -> Option<StrongPcbRef>;
+
+    fn remove(&mut self, process: &StrongPcbRef) -> Option<StrongPcbRef>;
+
+    fn for_each(&self, f: &mut dyn FnMut(&StrongPcbRef));
+
+    // Whether the current process, which has run for ticks, should be replaced by a ready process
+    fn expired(&self, current: &ProcessControlBlock, ticks: u32) -> bool;
+
+    // Whether a waiting process should replace the current one as soon as possible
+    fn preempts(&self, _current: &ProcessControlBlock) -> bool { false }
+
+    fn level(&self, process: &ProcessControlBlock) -> usize;
+}
+
+// A scheduler where the order processes run in is decided by a Policy
+pub struct PolicyScheduler<P: Policy> {
+    policy: P,
+    current: Option<StrongPcbRef>,
+    run_count: u32,
+    idle_process: StrongPcbRef,
+    stats: SchedulerStats,
+}
+
+impl<P: Policy> PolicyScheduler<P> {
+
+    pub fn new(policy: P) -> Self {
+        PolicyScheduler {
+            policy,
+            current: None,
+            run_count: 0,
+            idle_process: Rc::new(RefCell::new(idle_process())),
+            stats: Default::default(),
+        }
+    }
+
+    fn contains(&self, process: &StrongPcbRef) -> bool {
+        let mut found = false;
+        self.policy.for_each(&mut |x| found = found || Rc::ptr_eq(x, process));
+        found
+    }
+
+    // Switch from the current process to next, or idle if there is no next
+    fn switch(&mut self, current: StrongPcbRef, next: Option<StrongPcbRef>, voluntary: bool, dispatch: Dispatch) {
+        let status = {
+            let mut pcb = current.borrow_mut();
+            pcb.usage.switched_out(&mut self.stats, voluntary);
+            match &next {
+                Some(next) => dispatch(Some(&mut pcb), &mut next.borrow_mut()),
+                None => dispatch(Some(&mut pcb), &mut self.idle_process.borrow_mut()),
+            }
+            pcb.status.clone()
+        };
+        if status != ProcessStatus::Exited && status != ProcessStatus::Terminated {
+            self.policy.requeue(current, self.run_count);
+        }
+        self.current = next;
+        self.run_count = 0;
+    }
+
+}
+
+impl<P: Policy> Scheduler for PolicyScheduler<P> {
+
+    fn insert_process(&mut self, process: StrongPcbRef) {
+        if self.contains(&process) { panic!("Process already in scheduler") }
+        self.policy.insert(process)
+    }
+
+    fn remove_process(&mut self, process: &StrongPcbRef) -> Option<StrongPcbRef> {
+        self.policy.remove(process)
+    }
+
+    fn current_process(&self) -> Option<StrongPcbRef> {
+        self.current.as_ref().map(|x| Rc::clone(x))
+    }
+
+    fn schedule(&mut self, src: ScheduleSource, dispatch: Dispatch) {
+        match src {
+            // A reset means no process is currently running
+            ScheduleSource::Reset => {
+                let next = self.policy.pop().expect("No process found");
+                dispatch(None, &mut next.borrow_mut());
+                self.current = Some(next);
+                self.run_count = 0;
+            }
+
+            // Switch once the policy decides the current process has run for long enough
+            ScheduleSource::Timer => {
+                self.stats.ticks = self.stats.ticks.wrapping_add(1);
+                self.policy.for_each(&mut charge_waiting);
+                match self.current_process() {
+                    None => self.stats.idle_ticks = self.stats.idle_ticks.wrapping_add(1),
+                    Some(current) => {
+                        self.run_count = self.run_count.saturating_add(1);
+                        let expired = {
+                            let mut pcb = current.borrow_mut();
+                            pcb.usage.user_ticks = pcb.usage.user_ticks.wrapping_add(1);
+                            self.policy.expired(&pcb, self.run_count)
+                        };
+                        // A kernel thread can block itself, it is switched out by the next interrupt
+                        if !ready_to_continue(&current.borrow()) {
+                            let next = self.policy.pop();
+                            return self.switch(current, next, true, dispatch)
+                        }
+                        if expired {
+                            if let Some(next) = self.policy.pop() {
+                                self.switch(current, Some(next), false, dispatch);
+                            }
+                        }
+                    }
+                }
+            }
+
+            // Only switch if the current process yielded, can no longer run, or should be preempted
+            ScheduleSource::Svc { id } => {
+                // There must have been a current process to have made a service call
+                let current = self.current_process().unwrap();
+                let (status, runnable, preempted) = {
+                    let pcb = current.borrow();
+                    (pcb.status.clone(), ready_to_continue(&pcb), self.policy.preempts(&pcb))
+                };
+                if runnable && id != SysCall::Yield && !preempted { return }
+                let next = self.policy.pop();
+                if next.is_none() && runnable { return }
+                let voluntary = id == SysCall::Yield || status != ProcessStatus::Executing;
+                self.switch(current, next, voluntary, dispatch);
+            }
+
+            ScheduleSource::Io | ScheduleSource::Fault => {
+                match self.current_process() {
+                    // Once IO has completed we may no longer need to idle
+                    None => {
+                        if let Some(next) = self.policy.pop() {
+                            dispatch(Some(&mut self.idle_process.borrow_mut()), &mut next.borrow_mut());
+                            self.current = Some(next);
+                            self.run_count = 0;
+                        }
+                    }
+                    // The IO or fault may have signalled the current process to stop or terminate
+                    Some(current) => {
+                        if ready_to_continue(&current.borrow()) { return }
+                        let next = self.policy.pop();
+                        self.switch(current, next, false, dispatch);
+                    }
+                }
+            }
+        }
+    }
+
+    fn placement(&self, process: &StrongPcbRef) -> (Option<usize>, u32) {
+        match &self.current {
+            Some(current) if Rc::ptr_eq(current, process) => (Some(self.policy.level(&process.borrow())), self.run_count),
+            _ if self.contains(process) => (Some(self.policy.level(&process.borrow())), 0),
+            _ => (None, 0)
+        }
+    }
+
+    fn stats(&self) -> &SchedulerStats {
+        &self.stats
+    }
+}
+
+// Whether the current process can keep executing
+fn ready_to_continue(process: &ProcessControlBlock) -> bool {
+    process.status == ProcessStatus::Executing && !process.stopped
+}
+
+// Remove the ready process that is better than every other, the earliest in the queue wins ties
+pub fn pop_best<F>(queue: &mut VecDeque<StrongPcbRef>, better: F) -> Option<StrongPcbRef>
+    where F: Fn(&ProcessControlBlock, &ProcessControlBlock) -> bool
+{
+    let mut best: Option<usize> = None;
+    for (i, item) in queue.iter().enumerate() {
+        let pcb = item.borrow();
+        if !ready(&pcb) { continue }
+        if best.map_or(true, |b| better(&pcb, &queue[b].borrow())) { best = Some(i) }
+    }
+    best.and_then(|i| queue.remove(i))
+}
+
+pub fn remove_from(queue: &mut VecDeque<StrongPcbRef>, process: &StrongPcbRef) -> Option<StrongPcbRef> {
+    let i = queue.iter().position(|x| Rc::ptr_eq(x, process))?;
+    queue.remove(i)
+}
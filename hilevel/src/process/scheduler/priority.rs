@@ -0,0 +1,60 @@
+use crate::process::{ProcessControlBlock, StrongPcbRef, NICE_MIN};
+use crate::process::scheduler::policy::{Policy, pop_best, remove_from};
+use crate::process::scheduler::ready;
+use alloc::collections::VecDeque;
+
+const PRIORITY_QUANTUM: u32 = 4;
+
+// The ready process with the lowest nice value always runs, processes with equal values take turns
+pub struct FixedPriority {
+    queue: VecDeque<StrongPcbRef>,
+    quantum: u32,
+}
+
+impl Default for FixedPriority {
+    fn default() -> Self {
+        FixedPriority { queue: VecDeque::new(), quantum: PRIORITY_QUANTUM }
+    }
+}
+
+impl Policy for FixedPriority {
+
+    fn insert(&mut self, process: StrongPcbRef) {
+        self.queue.push_back(process)
+    }
+
+    fn requeue(&mut self, process: StrongPcbRef, _ticks: u32) {
+        self.queue.push_back(process)
+    }
+
+    fn pop(&mut self) -> Option<StrongPcbRef> {
+        pop_best(&mut self.queue, |a, b| a.nice < b.nice)
+    }
+
+    fn remove(&mut self, process: &StrongPcbRef) -> Option<StrongPcbRef> {
+        remove_from(&mut self.queue, process)
+    }
+
+    fn for_each(&self, f: &mut dyn FnMut(&StrongPcbRef)) {
+        self.queue.iter().for_each(|x| f(x))
+    }
+
+    // Only processes with the same priority take turns
+    fn expired(&self, current: &ProcessControlBlock, ticks: u32) -> bool {
+        self.queue.iter().any(|x| {
+            let pcb = x.borrow();
+            ready(&pcb) && (pcb.nice < current.nice || (pcb.nice == current.nice && ticks >= self.quantum))
+        })
+    }
+
+    fn preempts(&self, current: &ProcessControlBlock) -> bool {
+        self.queue.iter().any(|x| {
+            let pcb = x.borrow();
+            ready(&pcb) && pcb.nice < current.nice
+        })
+    }
+
+    fn level(&self, process: &ProcessControlBlock) -> usize {
+        (process.nice - NICE_MIN) as usize
+    }
+}
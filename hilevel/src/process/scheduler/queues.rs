@@ -1,179 +1,183 @@
-use core::ops;
-use core::cell::RefCell;
-use crate::process::{ProcessControlBlock, StrongPcbRef};
-use alloc::rc::{Weak, Rc};
-use alloc::collections::VecDeque;
+use crate::process::StrongPcbRef;
 use alloc::vec::Vec;
 
-pub type StrongQueueLevelRef = Rc<RefCell<QueueLevel>>;
-
-const QUEUE_QUANTUM: &[u32] = &[2, 4, 8, 16];
+pub const QUEUE_QUANTUM: &[u32] = &[2, 4, 8, 16];
 
+// Only runnable processes are queued, so every operation is constant time (in the number of processes)
+// Each level is a doubly linked list threaded through a shared slab of nodes, and each queued process
+// keeps the index of its node in queue_slot, so it can be unlinked from anywhere in its list
 pub struct MultiLevelQueue {
-    top: StrongQueueLevelRef
+    levels: Vec<Level>,
+    nodes: Vec<Option<Node>>,
+    free: Vec<usize>,           // Unused indices into nodes
 }
 
-// Both above and below can't be strong otherwise there would be a reference cycle
-pub struct QueueLevel {
-    above: Option<Weak<RefCell<QueueLevel>>>,
-    internal: VecDeque<StrongPcbRef>,
-    below: Option<StrongQueueLevelRef>,
+#[derive(Debug, Clone, Copy)]
+struct Level {
+    head: Option<usize>,
+    tail: Option<usize>,
+    len: usize,
     quantum: u32,
 }
 
-pub trait LinkedQueues {
-    fn below(&self) -> Option<StrongQueueLevelRef>;
-    fn above(&self) -> Option<StrongQueueLevelRef>;
+struct Node {
+    process: StrongPcbRef,
+    level: usize,
+    prev: Option<usize>,
+    next: Option<usize>,
 }
 
 impl MultiLevelQueue {
 
-    pub fn new(mut quantums: Vec<u32>) -> Self {
+    pub fn new(quantums: Vec<u32>) -> Self {
         assert!(quantums.len() > 0);
-        // Create top queue
-        let top = Rc::new(RefCell::new(QueueLevel {
-            above: None,
-            internal: Default::default(),
-            below: None,
-            quantum: quantums.remove(0),
-        }));
-        let mut queues = vec![top];
-        // Add queues below, linking each up above
-        for quantum in quantums.into_iter() {
-            queues.push(Rc::new(RefCell::new(QueueLevel {
-                above: Some(Rc::downgrade(queues.last().unwrap())),
-                internal: Default::default(),
-                below: None,
-                quantum,
-            })));
-        }
-        // Link the queues to the ones below, by iterating upwards
-        let mut i = Rc::clone(queues.last().unwrap());
-        while i.borrow().above.is_some() {
-            let above = LinkedQueues::above(&i).unwrap();
-            above.borrow_mut().below = Some(Rc::clone(&i));
-            i = above;
+        MultiLevelQueue {
+            levels: quantums.into_iter().map(|quantum| Level { head: None, tail: None, len: 0, quantum }).collect(),
+            nodes: Vec::new(),
+            free: Vec::new(),
         }
-        MultiLevelQueue { top: queues.remove(0) }
     }
 
-    pub fn top_queue(&self) -> StrongQueueLevelRef {
-        Rc::clone(&self.top)
+    pub fn quantum(&self, level: usize) -> u32 {
+        self.levels[level].quantum
+    }
+
+    pub fn quantums(&self) -> Vec<u32> {
+        self.levels.iter().map(|x| x.quantum).collect()
     }
 
-    fn iter(&self) -> MultiLevelQueueIterator {
-        MultiLevelQueueIterator {start: Rc::clone(&self.top), current: None }
+    pub fn len(&self) -> usize {
+        self.levels.iter().map(|x| x.len).sum()
+    }
+
+    pub fn levels(&self) -> usize {
+        self.levels.len()
+    }
+
+    // The level below, or the same level if it is the bottom
+    pub fn below(&self, level: usize) -> usize {
+        (level + 1).min(self.levels.len() - 1)
+    }
+
+    // The level above, or the same level if it is the top
+    pub fn above(&self, level: usize) -> usize {
+        level.saturating_sub(1)
     }
 
     // If a given process is contained in any queue
     pub fn contains(&self, process: &StrongPcbRef) -> bool {
-        for queue in self.iter() {
-            let borrow = queue.borrow();
-            if borrow.iter().any(|x| Rc::ptr_eq(process, x)) {return true}
-        }
-        false
+        process.borrow().queue_slot.is_some()
     }
 
-    // Search queues for first matching process
-    pub fn pop_process<F>(&mut self, filter: F) -> Option<(StrongPcbRef, StrongQueueLevelRef)>
-        where F: Fn(&ProcessControlBlock)->bool
-    {
-        for queue in self.iter() {
-            let mut borrowed = queue.borrow_mut();
-            for (i, item) in borrowed.iter().enumerate() {
-                if filter(& (*item).borrow()) {
-                    let popped = borrowed.remove(i).unwrap();
-                    return Some((popped, Rc::clone(&queue)))
-                }
-            }
-        }
-        None
+    // The index of the queue containing a process, where the top queue is 0
+    pub fn level_of(&self, process: &StrongPcbRef) -> Option<usize> {
+        process.borrow().queue_slot.and_then(|slot| self.nodes[slot].as_ref()).map(|x| x.level)
     }
 
-    // Moves all processes to the top queue
-    pub fn boost(&mut self) {
-        for queue in self.iter().skip(1) {
-            let x = &mut queue.borrow_mut().internal;
-            self.top.borrow_mut().internal.append(x)
-        }
+    fn node(&mut self, slot: usize) -> &mut Node {
+        self.nodes[slot].as_mut().unwrap()
     }
 
-    // Removes a process if it is found in any queue
-    pub fn remove_process(&mut self, process: &StrongPcbRef) -> Option<StrongPcbRef> {
-        for queue in self.iter() {
-            let mut borrow = queue.borrow_mut();
-            for (i, item) in borrow.iter().enumerate() {
-                if Rc::ptr_eq(process, item) {
-                    return borrow.remove(i)
-                }
-            }
-        }
-        None
+    fn allocate(&mut self, process: StrongPcbRef, level: usize) -> usize {
+        if process.borrow().queue_slot.is_some() { panic!("Process already queued") }
+        let node = Node { process, level, prev: None, next: None };
+        let slot = match self.free.pop() {
+            Some(slot) => { self.nodes[slot] = Some(node); slot }
+            None => { self.nodes.push(Some(node)); self.nodes.len() - 1 }
+        };
+        self.node(slot).process.borrow_mut().queue_slot = Some(slot);
+        slot
     }
 
-}
+    pub fn push_back(&mut self, level: usize, process: StrongPcbRef) {
+        let slot = self.allocate(process, level);
+        let tail = self.levels[level].tail;
+        self.node(slot).prev = tail;
+        match tail {
+            Some(tail) => self.node(tail).next = Some(slot),
+            None => self.levels[level].head = Some(slot),
+        }
+        self.levels[level].tail = Some(slot);
+        self.levels[level].len = self.levels[level].len + 1;
+    }
 
-impl Default for MultiLevelQueue {
-    fn default() -> Self {
-        MultiLevelQueue::new(QUEUE_QUANTUM.to_vec())
+    pub fn push_front(&mut self, level: usize, process: StrongPcbRef) {
+        let slot = self.allocate(process, level);
+        let head = self.levels[level].head;
+        self.node(slot).next = head;
+        match head {
+            Some(head) => self.node(head).prev = Some(slot),
+            None => self.levels[level].tail = Some(slot),
+        }
+        self.levels[level].head = Some(slot);
+        self.levels[level].len = self.levels[level].len + 1;
     }
-}
 
-impl LinkedQueues for StrongQueueLevelRef {
+    fn unlink(&mut self, slot: usize) -> (StrongPcbRef, usize) {
+        let node = self.nodes[slot].take().unwrap();
+        match node.prev {
+            Some(prev) => self.node(prev).next = node.next,
+            None => self.levels[node.level].head = node.next,
+        }
+        match node.next {
+            Some(next) => self.node(next).prev = node.prev,
+            None => self.levels[node.level].tail = node.prev,
+        }
+        self.levels[node.level].len = self.levels[node.level].len - 1;
+        self.free.push(slot);
+        node.process.borrow_mut().queue_slot = None;
+        (node.process, node.level)
+    }
 
-    fn below(&self) -> Option<StrongQueueLevelRef> {
-        self.borrow().below.as_ref().map(|x| Rc::clone(x))
+    // The first process of the highest non empty queue, and the level it was taken from
+    pub fn pop(&mut self) -> Option<(StrongPcbRef, usize)> {
+        let slot = self.levels.iter().find_map(|x| x.head)?;
+        Some(self.unlink(slot))
     }
 
-    fn above(&self) -> Option<StrongQueueLevelRef> {
-        self.borrow().above.as_ref().map(|x| Weak::upgrade(x).unwrap())
+    // Removes a process if it is found in any queue, returning the level it was in
+    pub fn remove_process(&mut self, process: &StrongPcbRef) -> Option<(StrongPcbRef, usize)> {
+        let slot = process.borrow().queue_slot?;
+        Some(self.unlink(slot))
     }
-}
 
-impl ops::Deref for QueueLevel {
-    type Target = VecDeque<StrongPcbRef>;
-    fn deref(&self) -> &Self::Target {
-        &self.internal
+    // Every queued process from the top queue down, in order
+    pub fn drain(&mut self) -> Vec<(StrongPcbRef, usize)> {
+        let mut drained = Vec::with_capacity(self.len());
+        while let Some(x) = self.pop() { drained.push(x) }
+        drained
     }
-}
 
-impl ops::DerefMut for QueueLevel {
-    fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.internal
+    // Moves all processes to the top queue
+    pub fn boost(&mut self) {
+        for (process, level) in self.drain() {
+            if level > 0 {
+                let mut pcb = process.borrow_mut();
+                pcb.usage.boosts = pcb.usage.boosts.wrapping_add(1);
+            }
+            self.push_back(0, process);
+        }
     }
-}
 
-impl QueueLevel {
-    pub fn quantum(&self) -> u32 {
-        self.quantum
+    // Replace the queues, each process keeps its level and order, or moves to the new bottom queue
+    pub fn resize(&mut self, quantums: Vec<u32>) {
+        let drained = self.drain();
+        *self = MultiLevelQueue::new(quantums);
+        let bottom = self.levels.len() - 1;
+        drained.into_iter().for_each(|(process, level)| self.push_back(level.min(bottom), process));
     }
-}
 
-struct MultiLevelQueueIterator {
-    start: StrongQueueLevelRef,
-    current: Option<StrongQueueLevelRef>,
 }
 
-// Iterates through queues downwards
-impl Iterator for MultiLevelQueueIterator {
-    type Item = StrongQueueLevelRef;
-
-    fn next(&mut self) -> Option<Self::Item> {
-        self.current = match &self.current {
-            None => {
-                Some(Rc::clone(&self.start))
-            },
-            Some(x) => {
-                LinkedQueues::below(x)
-            },
-        };
-        self.current.clone()
+impl Default for MultiLevelQueue {
+    fn default() -> Self {
+        MultiLevelQueue::new(QUEUE_QUANTUM.to_vec())
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::process::scheduler::queues::{MultiLevelQueue, LinkedQueues};
+    use crate::process::scheduler::queues::MultiLevelQueue;
     use alloc::rc::Rc;
     use alloc::vec::Vec;
     use core::cell::RefCell;
@@ -182,29 +186,18 @@ mod tests {
     #[test]
     fn new_test() {
         let mlq = MultiLevelQueue::new(vec![1, 2, 3]);
-
-        let top = mlq.top_queue();
-        let middle = LinkedQueues::below(&top).unwrap();
-        let bottom = LinkedQueues::below(&middle).unwrap();
-        assert!(LinkedQueues::below(&bottom).is_none());
-
-        assert_eq!(top.borrow().quantum, 1);
-        assert_eq!(middle.borrow().quantum, 2);
-        assert_eq!(bottom.borrow().quantum, 3);
-
-        assert!(LinkedQueues::above(&top).is_none());
-        assert!(Rc::ptr_eq(&LinkedQueues::above(&bottom).unwrap(), &middle));
-        assert!(Rc::ptr_eq(&LinkedQueues::above(&middle).unwrap(), &top));
+        assert_eq!(mlq.quantums().len(), 3);
+        assert_eq!((mlq.quantum(0), mlq.quantum(1), mlq.quantum(2)), (1, 2, 3));
+        assert_eq!((mlq.below(0), mlq.below(2)), (1, 2));
+        assert_eq!((mlq.above(0), mlq.above(2)), (0, 1));
+        assert_eq!(mlq.len(), 0);
     }
 
     #[test]
     fn iter_test() {
         let qs = vec![1, 2, 3, 5, 9];
         let mlq = MultiLevelQueue::new(qs.clone());
-        let iterated: Vec<u32> = mlq.iter().map(|q| {
-            q.borrow().quantum
-        }).collect();
-        assert_eq!(qs, iterated);
+        assert_eq!(qs, mlq.quantums());
     }
 
     fn new_item() -> StrongPcbRef {
@@ -216,25 +209,63 @@ mod tests {
         let item = new_item();
         let mut mlq = MultiLevelQueue::new(vec![1, 2, 3]);
         assert_eq!(mlq.contains(&item), false);
-        mlq.top_queue().borrow_mut().push_front(Rc::clone(&item));
+        mlq.push_front(0, Rc::clone(&item));
         assert_eq!(mlq.contains(&item), true);
         mlq.remove_process(&item);
         assert_eq!(mlq.contains(&item), false);
-        let middle = LinkedQueues::below(&mlq.top_queue()).unwrap();
-        middle.borrow_mut().push_back(Rc::clone(&item));
+        assert_eq!(mlq.level_of(&item), None);
+        mlq.push_back(1, Rc::clone(&item));
         assert_eq!(mlq.contains(&item), true);
+        assert_eq!(mlq.level_of(&item), Some(1));
+    }
+
+    #[test]
+    fn unlink_test() {
+        let items: Vec<StrongPcbRef> = (0..4).map(|_| new_item()).collect();
+        let mut mlq = MultiLevelQueue::new(vec![1, 2]);
+        items.iter().for_each(|x| mlq.push_back(1, Rc::clone(x)));
+        // Remove from the middle, the tail and the head
+        mlq.remove_process(&items[1]).unwrap();
+        mlq.remove_process(&items[3]).unwrap();
+        mlq.push_front(1, Rc::clone(&items[3]));
+        assert_eq!(mlq.remove_process(&items[3]).map(|x| x.1), Some(1));
+        assert!(mlq.remove_process(&items[3]).is_none());
+        let order: Vec<bool> = mlq.drain().iter().map(|(x, _)| Rc::ptr_eq(x, &items[0])).collect();
+        assert_eq!(order, vec![true, false]);
+        // Freed slots are reused
+        items.iter().for_each(|x| mlq.push_back(0, Rc::clone(x)));
+        assert_eq!(mlq.nodes.len(), 4);
+    }
+
+    #[test]
+    fn resize_test() {
+        let (item1, item2, item3) = (new_item(), new_item(), new_item());
+        let mut mlq = MultiLevelQueue::new(vec![1, 2, 3, 4]);
+        mlq.push_back(1, Rc::clone(&item1));
+        mlq.push_back(2, Rc::clone(&item2));
+        mlq.push_back(3, Rc::clone(&item3));
+        mlq.resize(vec![5, 6]);
+        assert_eq!(mlq.quantums(), vec![5, 6]);
+        assert_eq!(mlq.level_of(&item1), Some(1));
+        // Processes from the removed queues are added to the bottom, in order
+        let order: Vec<bool> = mlq.drain().iter().map(|(x, _)| Rc::ptr_eq(x, &item3)).collect();
+        assert_eq!(order, vec![false, false, true]);
+        mlq.push_back(1, Rc::clone(&item2));
+        mlq.resize(vec![1, 2, 3]);
+        assert_eq!(mlq.level_of(&item2), Some(1));
+        assert_eq!(mlq.below(2), 2);
     }
 
     #[test]
     fn boost_test() {
         let item= new_item();
         let mut mlq = MultiLevelQueue::new(vec![1, 2, 3]);
-        let mut last_queue = mlq.iter().last().unwrap();
-        last_queue.borrow_mut().push_back(Rc::clone(&item));
+        mlq.push_back(2, Rc::clone(&item));
         mlq.boost();
-        let (popped, queue) = mlq.pop_process(|x| true).unwrap();
+        let (popped, level) = mlq.pop().unwrap();
         assert!(Rc::ptr_eq(&item, &popped));
-        assert!(Rc::ptr_eq(&queue, &mlq.top_queue()));
+        assert_eq!(popped.borrow().usage.boosts, 1);
+        assert_eq!(level, 0);
     }
 
     #[test]
@@ -243,15 +274,13 @@ mod tests {
         let item2 = new_item();
         let item3 = new_item();
         let mut mlq = MultiLevelQueue::new(vec![1, 2, 3]);
-        let mut last_queue = mlq.iter().last().unwrap();
-        mlq.top_queue().borrow_mut().push_back(Rc::clone(&item1));
-        mlq.top_queue().borrow_mut().push_back(Rc::clone(&item2));
-        last_queue.borrow_mut().push_back(Rc::clone(&item3));
-        fn filter(pcb: &ProcessControlBlock) -> bool { true };
-        assert!(Rc::ptr_eq(&item1, &mlq.pop_process(filter).unwrap().0));
-        assert!(Rc::ptr_eq(&item2, &mlq.pop_process(filter).unwrap().0));
-        assert!(Rc::ptr_eq(&item3, &mlq.pop_process(filter).unwrap().0));
-        assert!(mlq.pop_process(filter).is_none());
+        mlq.push_back(0, Rc::clone(&item1));
+        mlq.push_back(0, Rc::clone(&item2));
+        mlq.push_back(2, Rc::clone(&item3));
+        assert!(Rc::ptr_eq(&item1, &mlq.pop().unwrap().0));
+        assert!(Rc::ptr_eq(&item2, &mlq.pop().unwrap().0));
+        assert!(Rc::ptr_eq(&item3, &mlq.pop().unwrap().0));
+        assert!(mlq.pop().is_none());
     }
 
 }
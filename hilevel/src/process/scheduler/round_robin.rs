@@ -0,0 +1,46 @@
+use crate::process::{ProcessControlBlock, StrongPcbRef};
+use crate::process::scheduler::policy::{Policy, pop_best, remove_from};
+use alloc::collections::VecDeque;
+
+const RR_QUANTUM: u32 = 4;
+
+// Every process gets the same quantum in turn
+pub struct RoundRobin {
+    queue: VecDeque<StrongPcbRef>,
+    quantum: u32,
+}
+
+impl Default for RoundRobin {
+    fn default() -> Self {
+        RoundRobin { queue: VecDeque::new(), quantum: RR_QUANTUM }
+    }
+}
+
+impl Policy for RoundRobin {
+
+    fn insert(&mut self, process: StrongPcbRef) {
+        self.queue.push_back(process)
+    }
+
+    fn requeue(&mut self, process: StrongPcbRef, _ticks: u32) {
+        self.queue.push_back(process)
+    }
+
+    fn pop(&mut self) -> Option<StrongPcbRef> {
+        pop_best(&mut self.queue, |_, _| false)
+    }
+
+    fn remove(&mut self, process: &StrongPcbRef) -> Option<StrongPcbRef> {
+        remove_from(&mut self.queue, process)
+    }
+
+    fn for_each(&self, f: &mut dyn FnMut(&StrongPcbRef)) {
+        self.queue.iter().for_each(|x| f(x))
+    }
+
+    fn expired(&self, _current: &ProcessControlBlock, ticks: u32) -> bool {
+        ticks >= self.quantum
+    }
+
+    fn level(&self, _process: &ProcessControlBlock) -> usize { 0 }
+}
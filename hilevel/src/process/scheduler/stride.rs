@@ -0,0 +1,75 @@
+use crate::process::{ProcessControlBlock, StrongPcbRef, NICE_MAX};
+use crate::process::scheduler::policy::Policy;
+use crate::process::scheduler::ready;
+use alloc::collections::VecDeque;
+use alloc::rc::Rc;
+
+const STRIDE_QUANTUM: u32 = 2;
+const STRIDE_ONE: u64 = 1 << 20;
+
+// Deterministic proportional share, each process gets CPU time in proportion to its tickets
+// The ready process that has been charged the least (the lowest pass) runs next
+pub struct Stride {
+    queue: VecDeque<(StrongPcbRef, u64)>,
+    global_pass: u64,       // The pass of the most recently dispatched process
+    running_pass: u64,      // The pass of the current process
+    quantum: u32,
+}
+
+impl Default for Stride {
+    fn default() -> Self {
+        Stride { queue: VecDeque::new(), global_pass: 0, running_pass: 0, quantum: STRIDE_QUANTUM }
+    }
+}
+
+// From 1 ticket for the highest nice value, to 40 for the lowest
+fn tickets(process: &ProcessControlBlock) -> u64 {
+    (NICE_MAX + 1 - process.nice) as u64
+}
+
+fn stride(process: &ProcessControlBlock) -> u64 {
+    STRIDE_ONE / tickets(process)
+}
+
+impl Policy for Stride {
+
+    fn insert(&mut self, process: StrongPcbRef) {
+        self.queue.push_back((process, self.global_pass))
+    }
+
+    fn requeue(&mut self, process: StrongPcbRef, ticks: u32) {
+        let pass = self.running_pass + stride(&process.borrow()) * ticks as u64;
+        self.queue.push_back((process, pass))
+    }
+
+    fn pop(&mut self) -> Option<StrongPcbRef> {
+        let mut best: Option<usize> = None;
+        for (i, (item, pass)) in self.queue.iter().enumerate() {
+            if !ready(&item.borrow()) { continue }
+            if best.map_or(true, |b| *pass < self.queue[b].1) { best = Some(i) }
+        }
+        let (process, pass) = self.queue.remove(best?)?;
+        // A process that was blocked for a long time can only catch up by one stride
+        let pass = pass.max(self.global_pass.saturating_sub(stride(&process.borrow())));
+        self.global_pass = pass;
+        self.running_pass = pass;
+        Some(process)
+    }
+
+    fn remove(&mut self, process: &StrongPcbRef) -> Option<StrongPcbRef> {
+        let i = self.queue.iter().position(|(x, _)| Rc::ptr_eq(x, process))?;
+        self.queue.remove(i).map(|(x, _)| x)
+    }
+
+    fn for_each(&self, f: &mut dyn FnMut(&StrongPcbRef)) {
+        self.queue.iter().for_each(|(x, _)| f(x))
+    }
+
+    // Keep running until another ready process would have a lower pass
+    fn expired(&self, current: &ProcessControlBlock, ticks: u32) -> bool {
+        let pass = self.running_pass + stride(current) * ticks as u64;
+        ticks >= self.quantum && self.queue.iter().any(|(x, other)| *other < pass && ready(&x.borrow()))
+    }
+
+    fn level(&self, _process: &ProcessControlBlock) -> usize { 0 }
+}
@@ -0,0 +1,91 @@
+use crate::util::IdTable;
+use alloc::collections::BTreeMap;
+use alloc::rc::{Rc, Weak};
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use alloc::boxed::Box;
+use core::cell::{Cell, RefCell};
+
+pub type ShmId = i32;
+
+pub const SHM_MAX_BYTES: usize = 0x00010000; // = 64 KiB
+
+// A kernel owned region of memory, which every attached process accesses directly as there is no MMU.
+// It is held in words so that it is aligned for futexes, and in cells as processes write to it at any time
+#[derive(Debug)]
+pub struct Segment {
+    id: ShmId,
+    memory: Box<[Cell<u32>]>,
+}
+
+impl Segment {
+
+    pub fn id(&self) -> ShmId {
+        self.id
+    }
+
+    pub fn address(&self) -> usize {
+        self.memory.as_ptr() as usize
+    }
+
+    #[cfg(test)]
+    pub fn len(&self) -> usize {
+        self.memory.len() * 4
+    }
+
+}
+
+pub type StrongSegmentRef = Rc<Segment>;
+
+// The segments a process is attached to, shared by every thread of the process
+pub type ShmTable = BTreeMap<ShmId, StrongSegmentRef>;
+pub type SharedShmTable = Rc<RefCell<ShmTable>>;
+
+// Every segment that still exists, a segment is freed once no process is attached to it
+#[derive(Default)]
+pub struct SharedMemory {
+    segments: IdTable<ShmId, Weak<Segment>>,
+}
+
+impl SharedMemory {
+
+    // A new zeroed segment of at least size bytes
+    pub fn create(&mut self, size: usize) -> Result<StrongSegmentRef, String> {
+        if size == 0 || size > SHM_MAX_BYTES { return Err("invalid size".to_string()) }
+        // Forget freed segments, so their ids can be reused
+        self.segments.retain(|_, x| x.strong_count() > 0);
+        let id = self.segments.new_key().ok_or("too many segments")?;
+        let memory: Vec<Cell<u32>> = (0..(size + 3) / 4).map(|_| Cell::new(0)).collect();
+        let segment = Rc::new(Segment { id, memory: memory.into_boxed_slice() });
+        self.segments.insert(id, Rc::downgrade(&segment));
+        Ok(segment)
+    }
+
+    pub fn get(&self, id: ShmId) -> Option<StrongSegmentRef> {
+        self.segments.get(&id).and_then(|x| x.upgrade())
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::process::shm::{SharedMemory, SHM_MAX_BYTES};
+    use alloc::rc::Rc;
+
+    #[test]
+    fn create_test() {
+        let mut shm = SharedMemory::default();
+        assert!(shm.create(0).is_err());
+        assert!(shm.create(SHM_MAX_BYTES + 1).is_err());
+        let first = shm.create(6).unwrap();
+        let second = shm.create(SHM_MAX_BYTES).unwrap();
+        assert_eq!((first.id(), first.len(), first.address() % 4), (0, 8, 0));
+        assert_eq!(second.id(), 1);
+        assert!(Rc::ptr_eq(&shm.get(0).unwrap(), &first));
+
+        // Segments are freed with their last reference, and their ids reused
+        drop(second);
+        assert!(shm.get(1).is_none());
+        assert_eq!(shm.create(4).unwrap().id(), 1);
+    }
+}
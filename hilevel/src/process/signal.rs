@@ -0,0 +1,28 @@
+// Signal numbers, must match libc.h
+pub const SIG_TERM: i32 = 0;
+pub const SIG_QUIT: i32 = 1;
+pub const SIG_INT: i32 = 2;
+pub const SIG_TSTP: i32 = 3;
+pub const SIG_CONT: i32 = 4;
+pub const SIG_SEGV: i32 = 5;       // Sent by the kernel when a process causes an abort
+pub const SIG_ILL: i32 = 6;        // Sent by the kernel when a process executes an undefined instruction
+
+// The exit status of a killed process is this plus the signal
+pub const SIGNAL_EXIT_BASE: i32 = 128;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SignalAction {
+    Terminate,
+    Stop,
+    Continue,
+}
+
+// There are no user signal handlers, so every signal has a fixed action, None if it isn't a signal
+pub fn default_action(signal: i32) -> Option<SignalAction> {
+    match signal {
+        SIG_TERM | SIG_QUIT | SIG_INT | SIG_SEGV | SIG_ILL => Some(SignalAction::Terminate),
+        SIG_TSTP => Some(SignalAction::Stop),
+        SIG_CONT => Some(SignalAction::Continue),
+        _ => None,
+    }
+}
@@ -0,0 +1,17 @@
+use crate::process::{ProcessManager, StrongPcbRef, FidTable, Context, ScheduleSource, PID};
+
+// Fixtures shared by the tests of processes, and of the files they block on
+
+pub extern fn main_test() {}
+
+// The current process of a new manager, which can be blocked and unblocked
+pub fn new_process() -> StrongPcbRef {
+    let mut manager = ProcessManager::default();
+    manager.create_process(main_test, FidTable::default());
+    manager.dispatch(&mut Context::new(0, 0), ScheduleSource::Reset);
+    manager.current_process().unwrap()
+}
+
+pub fn current_pid(manager: &mut ProcessManager) -> Option<PID> {
+    manager.current_process().map(|x| x.borrow().pid)
+}
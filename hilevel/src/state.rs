@@ -1,12 +1,12 @@
 use crate::process::ProcessManager;
-use crate::io::PL011::UART0;
-use core::fmt::Write;
 use crate::io::IoManager;
+use crate::interrupt::InterruptManager;
 
 #[derive(Default)]
 pub struct KernelState {
     pub process_manager: ProcessManager,
     pub io_manager: IoManager,
+    pub interrupt_manager: InterruptManager,
 }
 
 // Mutable statics are treated as unsafe because the compiler does not aware of any
@@ -17,7 +17,7 @@ pub struct KernelState {
 static mut KERNEL_STATE: Option<KernelState> = None;
 
 pub fn init() -> &'static mut KernelState {
-    writeln!(UART0(), "Initialising kernel state").ok();
+    info!("Initialising kernel state");
     unsafe {
         if KERNEL_STATE.is_some() { panic!("State has already initialised") }
         KERNEL_STATE = Some(KernelState::default())
@@ -28,3 +28,8 @@ pub fn init() -> &'static mut KernelState {
 pub fn get() -> &'static mut KernelState {
     unsafe { KERNEL_STATE.as_mut().unwrap() }
 }
+
+// For fault handlers, which may run before the state has been initialised
+pub fn try_get() -> Option<&'static mut KernelState> {
+    unsafe { KERNEL_STATE.as_mut() }
+}
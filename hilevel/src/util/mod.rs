@@ -1,3 +1,5 @@
 mod table;
+mod name;
 
 pub use table::IdTable;
+pub use name::read_name;
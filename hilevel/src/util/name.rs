@@ -0,0 +1,28 @@
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::str;
+
+// Copy a NUL terminated name, of at most max bytes, from a process. None if it is too long or not UTF-8
+pub fn read_name(name: *const u8, max: usize) -> Option<String> {
+    if name.is_null() { return None }
+    let mut bytes = Vec::new();
+    for i in 0..=max {
+        let byte = unsafe { *name.add(i) };
+        if byte == 0 { return str::from_utf8(&bytes).ok().map(|x| x.to_string()) }
+        bytes.push(byte);
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::util::read_name;
+
+    #[test]
+    fn read_name_test() {
+        assert_eq!(read_name(b"name\0".as_ptr(), 4), Some("name".into()));
+        assert_eq!(read_name(b"name\0".as_ptr(), 3), None);
+        assert_eq!(read_name([0xFFu8, 0].as_ptr(), 4), None);
+        assert_eq!(read_name(core::ptr::null(), 4), None);
+    }
+}